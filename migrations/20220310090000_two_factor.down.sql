@@ -0,0 +1,5 @@
+BEGIN;
+  ALTER TABLE users DROP COLUMN recovery_codes;
+  ALTER TABLE users DROP COLUMN totp_enabled;
+  ALTER TABLE users DROP COLUMN totp_secret;
+COMMIT;
@@ -0,0 +1,7 @@
+BEGIN;
+
+  ALTER TABLE users ADD COLUMN totp_secret text;
+  ALTER TABLE users ADD COLUMN totp_enabled boolean NOT NULL DEFAULT false;
+  ALTER TABLE users ADD COLUMN recovery_codes text[] NOT NULL DEFAULT '{}';
+
+COMMIT;
@@ -0,0 +1,5 @@
+BEGIN;
+  ALTER TABLE personal_access_token DROP CONSTRAINT personal_access_token_user_id_fk;
+
+  DROP TABLE personal_access_token;
+COMMIT;
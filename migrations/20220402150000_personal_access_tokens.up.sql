@@ -0,0 +1,19 @@
+BEGIN;
+
+  CREATE TABLE personal_access_token (
+    id uuid NOT NULL,
+    user_id uuid NOT NULL,
+    name text NOT NULL,
+    token_hash text NOT NULL,
+    scopes text[] NOT NULL DEFAULT '{}',
+    created_at timestamptz NOT NULL DEFAULT now(),
+    last_used_at timestamptz,
+    expires_at timestamptz,
+    CONSTRAINT personal_access_token_pk PRIMARY KEY (id)
+  );
+  CREATE UNIQUE INDEX personal_access_token_hash_idx ON personal_access_token (token_hash);
+
+  ALTER TABLE personal_access_token
+  ADD CONSTRAINT personal_access_token_user_id_fk FOREIGN KEY (user_id) REFERENCES users (id) ON DELETE CASCADE;
+
+COMMIT;
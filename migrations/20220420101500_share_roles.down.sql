@@ -0,0 +1,4 @@
+BEGIN;
+  ALTER TABLE shopping_list_share DROP CONSTRAINT shopping_list_share_role_check;
+  ALTER TABLE shopping_list_share DROP COLUMN role;
+COMMIT;
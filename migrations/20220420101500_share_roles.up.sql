@@ -0,0 +1,7 @@
+BEGIN;
+
+  ALTER TABLE shopping_list_share ADD COLUMN role text NOT NULL DEFAULT 'EDITOR';
+  ALTER TABLE shopping_list_share
+  ADD CONSTRAINT shopping_list_share_role_check CHECK (role IN ('VIEWER', 'EDITOR', 'MANAGER'));
+
+COMMIT;
@@ -0,0 +1,6 @@
+BEGIN;
+  ALTER TABLE shopping_list_invite DROP CONSTRAINT shopping_list_invite_created_by_fk;
+  ALTER TABLE shopping_list_invite DROP CONSTRAINT shopping_list_invite_list_id_fk;
+
+  DROP TABLE shopping_list_invite;
+COMMIT;
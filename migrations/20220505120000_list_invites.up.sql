@@ -0,0 +1,23 @@
+BEGIN;
+
+  CREATE TABLE shopping_list_invite (
+    id uuid NOT NULL,
+    shopping_list_id uuid NOT NULL,
+    created_by uuid NOT NULL,
+    token_hash text NOT NULL,
+    role text NOT NULL DEFAULT 'EDITOR',
+    single_use boolean NOT NULL DEFAULT false,
+    use_count int NOT NULL DEFAULT 0,
+    created_at timestamptz NOT NULL DEFAULT now(),
+    expires_at timestamptz NOT NULL,
+    CONSTRAINT shopping_list_invite_pk PRIMARY KEY (id),
+    CONSTRAINT shopping_list_invite_role_check CHECK (role IN ('VIEWER', 'EDITOR', 'MANAGER'))
+  );
+  CREATE UNIQUE INDEX shopping_list_invite_hash_idx ON shopping_list_invite (token_hash);
+
+  ALTER TABLE shopping_list_invite
+  ADD CONSTRAINT shopping_list_invite_list_id_fk FOREIGN KEY (shopping_list_id) REFERENCES shopping_list (id) ON DELETE CASCADE;
+  ALTER TABLE shopping_list_invite
+  ADD CONSTRAINT shopping_list_invite_created_by_fk FOREIGN KEY (created_by) REFERENCES users (id) ON DELETE CASCADE;
+
+COMMIT;
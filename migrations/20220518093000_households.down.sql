@@ -0,0 +1,11 @@
+BEGIN;
+  ALTER TABLE shopping_list DROP CONSTRAINT shopping_list_household_id_fk;
+  ALTER TABLE shopping_list DROP COLUMN household_id;
+
+  ALTER TABLE household_member DROP CONSTRAINT household_member_user_id_fk;
+  ALTER TABLE household_member DROP CONSTRAINT household_member_household_id_fk;
+  ALTER TABLE household DROP CONSTRAINT household_owner_id_fk;
+
+  DROP TABLE household_member;
+  DROP TABLE household;
+COMMIT;
@@ -0,0 +1,30 @@
+BEGIN;
+
+  CREATE TABLE household (
+    id uuid NOT NULL,
+    name text NOT NULL,
+    owner_id uuid NOT NULL,
+    created_at timestamptz NOT NULL DEFAULT now(),
+    CONSTRAINT household_pk PRIMARY KEY (id)
+  );
+
+  CREATE TABLE household_member (
+    household_id uuid NOT NULL,
+    user_id uuid NOT NULL,
+    role text NOT NULL DEFAULT 'EDITOR',
+    CONSTRAINT household_member_pk PRIMARY KEY (household_id, user_id),
+    CONSTRAINT household_member_role_check CHECK (role IN ('VIEWER', 'EDITOR', 'MANAGER'))
+  );
+
+  ALTER TABLE household
+  ADD CONSTRAINT household_owner_id_fk FOREIGN KEY (owner_id) REFERENCES users (id) ON DELETE CASCADE;
+  ALTER TABLE household_member
+  ADD CONSTRAINT household_member_household_id_fk FOREIGN KEY (household_id) REFERENCES household (id) ON DELETE CASCADE;
+  ALTER TABLE household_member
+  ADD CONSTRAINT household_member_user_id_fk FOREIGN KEY (user_id) REFERENCES users (id) ON DELETE CASCADE;
+
+  ALTER TABLE shopping_list ADD COLUMN household_id uuid;
+  ALTER TABLE shopping_list
+  ADD CONSTRAINT shopping_list_household_id_fk FOREIGN KEY (household_id) REFERENCES household (id) ON DELETE SET NULL;
+
+COMMIT;
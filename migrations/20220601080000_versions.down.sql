@@ -0,0 +1,4 @@
+BEGIN;
+  ALTER TABLE item DROP COLUMN version;
+  ALTER TABLE shopping_list DROP COLUMN version;
+COMMIT;
@@ -0,0 +1,6 @@
+BEGIN;
+
+  ALTER TABLE shopping_list ADD COLUMN version int NOT NULL DEFAULT 1;
+  ALTER TABLE item ADD COLUMN version int NOT NULL DEFAULT 1;
+
+COMMIT;
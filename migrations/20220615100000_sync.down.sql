@@ -0,0 +1,31 @@
+BEGIN;
+  DROP TRIGGER shopping_list_share_delete ON shopping_list_share;
+  DROP TRIGGER item_delete ON item;
+  DROP TRIGGER shopping_list_delete ON shopping_list;
+  DROP TRIGGER household_member_change ON household_member;
+  DROP TRIGGER shopping_list_share_change ON shopping_list_share;
+  DROP TRIGGER item_change ON item;
+  DROP TRIGGER shopping_list_household ON shopping_list;
+  DROP TRIGGER shopping_list_change ON shopping_list;
+
+  DROP FUNCTION track_share_delete();
+  DROP FUNCTION track_item_delete();
+  DROP FUNCTION track_list_delete();
+  DROP FUNCTION track_list_household();
+  DROP FUNCTION track_list_change();
+  DROP FUNCTION track_item_change();
+  DROP FUNCTION track_change();
+
+  DROP TABLE tombstone;
+  DROP INDEX item_change_id_idx;
+
+  ALTER TABLE household_member DROP COLUMN change_id;
+  ALTER TABLE shopping_list_share DROP COLUMN change_id;
+  ALTER TABLE item DROP COLUMN updated_at;
+  ALTER TABLE item DROP COLUMN change_id;
+  ALTER TABLE shopping_list DROP COLUMN household_change_id;
+  ALTER TABLE shopping_list DROP COLUMN updated_at;
+  ALTER TABLE shopping_list DROP COLUMN change_id;
+
+  DROP SEQUENCE sync_change_seq;
+COMMIT;
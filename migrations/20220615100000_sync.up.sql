@@ -0,0 +1,101 @@
+BEGIN;
+
+  -- every change of a synced row takes the next value, clients sync from the last one they have seen
+  CREATE SEQUENCE sync_change_seq;
+
+  ALTER TABLE shopping_list ADD COLUMN change_id bigint NOT NULL DEFAULT nextval('sync_change_seq');
+  ALTER TABLE shopping_list ADD COLUMN updated_at timestamptz NOT NULL DEFAULT now();
+  -- change of the last move into or out of a household, members gain or lose access with it
+  ALTER TABLE shopping_list ADD COLUMN household_change_id bigint NOT NULL DEFAULT 0;
+  ALTER TABLE item ADD COLUMN change_id bigint NOT NULL DEFAULT nextval('sync_change_seq');
+  ALTER TABLE item ADD COLUMN updated_at timestamptz NOT NULL DEFAULT now();
+  ALTER TABLE shopping_list_share ADD COLUMN change_id bigint NOT NULL DEFAULT nextval('sync_change_seq');
+  ALTER TABLE household_member ADD COLUMN change_id bigint NOT NULL DEFAULT nextval('sync_change_seq');
+
+  CREATE INDEX item_change_id_idx ON item (shopping_list_id, change_id);
+
+  CREATE TABLE tombstone (
+    change_id bigint NOT NULL DEFAULT nextval('sync_change_seq'),
+    entity_type text NOT NULL,
+    entity_id uuid NOT NULL,
+    shopping_list_id uuid NOT NULL,
+    -- users who could see a deleted list, its shares are gone by the time clients sync
+    user_ids uuid[],
+    deleted_at timestamptz NOT NULL DEFAULT now(),
+    CONSTRAINT tombstone_pk PRIMARY KEY (change_id),
+    CONSTRAINT tombstone_entity_type_check CHECK (entity_type IN ('LIST', 'ITEM', 'SHARE'))
+  );
+  CREATE INDEX tombstone_deleted_at_idx ON tombstone (deleted_at);
+
+  CREATE FUNCTION track_change() RETURNS trigger AS $$
+  BEGIN
+    NEW.change_id := nextval('sync_change_seq');
+    RETURN NEW;
+  END;
+  $$ LANGUAGE plpgsql;
+
+  CREATE FUNCTION track_item_change() RETURNS trigger AS $$
+  BEGIN
+    NEW.change_id := nextval('sync_change_seq');
+    NEW.updated_at := now();
+    RETURN NEW;
+  END;
+  $$ LANGUAGE plpgsql;
+
+  CREATE FUNCTION track_list_change() RETURNS trigger AS $$
+  BEGIN
+    NEW.change_id := nextval('sync_change_seq');
+    NEW.updated_at := now();
+    IF NEW.household_id IS DISTINCT FROM OLD.household_id THEN
+      NEW.household_change_id := NEW.change_id;
+    END IF;
+    RETURN NEW;
+  END;
+  $$ LANGUAGE plpgsql;
+
+  CREATE FUNCTION track_list_household() RETURNS trigger AS $$
+  BEGIN
+    IF NEW.household_id IS NOT NULL THEN
+      NEW.household_change_id := NEW.change_id;
+    END IF;
+    RETURN NEW;
+  END;
+  $$ LANGUAGE plpgsql;
+
+  CREATE FUNCTION track_list_delete() RETURNS trigger AS $$
+  BEGIN
+    INSERT INTO tombstone (entity_type, entity_id, shopping_list_id, user_ids)
+      SELECT 'LIST', OLD.id, OLD.id, array_agg(DISTINCT user_id) FROM (
+        SELECT OLD.owner_id AS user_id
+        UNION SELECT target_user_id FROM shopping_list_share WHERE shopping_list_id = OLD.id
+        UNION SELECT user_id FROM household_member WHERE household_id = OLD.household_id
+      ) AS users;
+    RETURN OLD;
+  END;
+  $$ LANGUAGE plpgsql;
+
+  CREATE FUNCTION track_item_delete() RETURNS trigger AS $$
+  BEGIN
+    INSERT INTO tombstone (entity_type, entity_id, shopping_list_id) VALUES ('ITEM', OLD.id, OLD.shopping_list_id);
+    RETURN OLD;
+  END;
+  $$ LANGUAGE plpgsql;
+
+  CREATE FUNCTION track_share_delete() RETURNS trigger AS $$
+  BEGIN
+    INSERT INTO tombstone (entity_type, entity_id, shopping_list_id) VALUES ('SHARE', OLD.target_user_id, OLD.shopping_list_id);
+    RETURN OLD;
+  END;
+  $$ LANGUAGE plpgsql;
+
+  CREATE TRIGGER shopping_list_change BEFORE UPDATE ON shopping_list FOR EACH ROW EXECUTE PROCEDURE track_list_change();
+  CREATE TRIGGER shopping_list_household BEFORE INSERT ON shopping_list FOR EACH ROW EXECUTE PROCEDURE track_list_household();
+  CREATE TRIGGER item_change BEFORE UPDATE ON item FOR EACH ROW EXECUTE PROCEDURE track_item_change();
+  CREATE TRIGGER shopping_list_share_change BEFORE UPDATE ON shopping_list_share FOR EACH ROW EXECUTE PROCEDURE track_change();
+  CREATE TRIGGER household_member_change BEFORE UPDATE ON household_member FOR EACH ROW EXECUTE PROCEDURE track_change();
+  -- before the delete, so the shares of the list still exist
+  CREATE TRIGGER shopping_list_delete BEFORE DELETE ON shopping_list FOR EACH ROW EXECUTE PROCEDURE track_list_delete();
+  CREATE TRIGGER item_delete AFTER DELETE ON item FOR EACH ROW EXECUTE PROCEDURE track_item_delete();
+  CREATE TRIGGER shopping_list_share_delete AFTER DELETE ON shopping_list_share FOR EACH ROW EXECUTE PROCEDURE track_share_delete();
+
+COMMIT;
@@ -0,0 +1,4 @@
+BEGIN;
+  DROP TRIGGER item_move ON item;
+  DROP FUNCTION track_item_move();
+COMMIT;
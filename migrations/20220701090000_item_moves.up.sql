@@ -0,0 +1,15 @@
+BEGIN;
+
+  -- an item moved to another list is gone from its old one, clients syncing only that list learn it from here
+  CREATE FUNCTION track_item_move() RETURNS trigger AS $$
+  BEGIN
+    INSERT INTO tombstone (entity_type, entity_id, shopping_list_id) VALUES ('ITEM', OLD.id, OLD.shopping_list_id);
+    RETURN NEW;
+  END;
+  $$ LANGUAGE plpgsql;
+
+  CREATE TRIGGER item_move AFTER UPDATE OF shopping_list_id ON item FOR EACH ROW
+    WHEN (OLD.shopping_list_id IS DISTINCT FROM NEW.shopping_list_id)
+    EXECUTE PROCEDURE track_item_move();
+
+COMMIT;
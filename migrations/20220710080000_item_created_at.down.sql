@@ -0,0 +1,3 @@
+BEGIN;
+  ALTER TABLE item DROP COLUMN created_at;
+COMMIT;
@@ -0,0 +1,5 @@
+BEGIN;
+
+  ALTER TABLE item ADD COLUMN created_at timestamptz NOT NULL DEFAULT now();
+
+COMMIT;
@@ -0,0 +1,8 @@
+BEGIN;
+  DROP TRIGGER item_append_moved ON item;
+  DROP TRIGGER item_append ON item;
+  DROP FUNCTION append_item();
+
+  DROP INDEX item_position_idx;
+  ALTER TABLE item DROP COLUMN position;
+COMMIT;
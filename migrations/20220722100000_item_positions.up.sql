@@ -0,0 +1,27 @@
+BEGIN;
+
+  -- ranks leave gaps, so an item can be moved between two others without touching the rest of the list
+  ALTER TABLE item ADD COLUMN position bigint;
+  UPDATE item SET position = ranked.rank * 65536 FROM (
+    SELECT id, row_number() OVER (PARTITION BY shopping_list_id ORDER BY created_at, id) AS rank FROM item
+  ) AS ranked WHERE item.id = ranked.id;
+  ALTER TABLE item ALTER COLUMN position SET NOT NULL;
+
+  CREATE INDEX item_position_idx ON item (shopping_list_id, position);
+
+  -- new items, and items moved in from another list, go to the end of the list
+  CREATE FUNCTION append_item() RETURNS trigger AS $$
+  BEGIN
+    NEW.position := COALESCE((SELECT max(position) FROM item WHERE shopping_list_id = NEW.shopping_list_id), 0) + 65536;
+    RETURN NEW;
+  END;
+  $$ LANGUAGE plpgsql;
+
+  CREATE TRIGGER item_append BEFORE INSERT ON item FOR EACH ROW
+    WHEN (NEW.position IS NULL)
+    EXECUTE PROCEDURE append_item();
+  CREATE TRIGGER item_append_moved BEFORE UPDATE OF shopping_list_id ON item FOR EACH ROW
+    WHEN (OLD.shopping_list_id IS DISTINCT FROM NEW.shopping_list_id)
+    EXECUTE PROCEDURE append_item();
+
+COMMIT;
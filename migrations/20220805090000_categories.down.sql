@@ -0,0 +1,17 @@
+BEGIN;
+  ALTER TABLE item DROP CONSTRAINT item_category_id_fk;
+  ALTER TABLE item DROP COLUMN category_id;
+
+  ALTER TABLE category_assignment DROP CONSTRAINT category_assignment_category_id_fk;
+  ALTER TABLE category_assignment DROP CONSTRAINT category_assignment_user_id_fk;
+  ALTER TABLE store_category DROP CONSTRAINT store_category_category_id_fk;
+  ALTER TABLE store_category DROP CONSTRAINT store_category_store_id_fk;
+  ALTER TABLE store DROP CONSTRAINT store_owner_id_fk;
+  ALTER TABLE category DROP CONSTRAINT category_owner_id_fk;
+
+  DROP TABLE category_assignment;
+  DROP TABLE store_category;
+  DROP TABLE store;
+  DROP INDEX category_name_idx;
+  DROP TABLE category;
+COMMIT;
@@ -0,0 +1,55 @@
+BEGIN;
+
+  CREATE TABLE category (
+    id uuid NOT NULL,
+    owner_id uuid NOT NULL,
+    name text NOT NULL,
+    position int NOT NULL DEFAULT 0,
+    created_at timestamptz NOT NULL DEFAULT now(),
+    CONSTRAINT category_pk PRIMARY KEY (id)
+  );
+  CREATE UNIQUE INDEX category_name_idx ON category (owner_id, lower(name));
+
+  CREATE TABLE store (
+    id uuid NOT NULL,
+    owner_id uuid NOT NULL,
+    name text NOT NULL,
+    created_at timestamptz NOT NULL DEFAULT now(),
+    CONSTRAINT store_pk PRIMARY KEY (id)
+  );
+
+  -- order of the categories in one store, overrides their own position
+  CREATE TABLE store_category (
+    store_id uuid NOT NULL,
+    category_id uuid NOT NULL,
+    position int NOT NULL,
+    CONSTRAINT store_category_pk PRIMARY KEY (store_id, category_id)
+  );
+
+  -- category last given to an item of the same name, new items of that name get it as well
+  CREATE TABLE category_assignment (
+    user_id uuid NOT NULL,
+    item_name text NOT NULL,
+    category_id uuid NOT NULL,
+    updated_at timestamptz NOT NULL DEFAULT now(),
+    CONSTRAINT category_assignment_pk PRIMARY KEY (user_id, item_name)
+  );
+
+  ALTER TABLE category
+  ADD CONSTRAINT category_owner_id_fk FOREIGN KEY (owner_id) REFERENCES users (id) ON DELETE CASCADE;
+  ALTER TABLE store
+  ADD CONSTRAINT store_owner_id_fk FOREIGN KEY (owner_id) REFERENCES users (id) ON DELETE CASCADE;
+  ALTER TABLE store_category
+  ADD CONSTRAINT store_category_store_id_fk FOREIGN KEY (store_id) REFERENCES store (id) ON DELETE CASCADE;
+  ALTER TABLE store_category
+  ADD CONSTRAINT store_category_category_id_fk FOREIGN KEY (category_id) REFERENCES category (id) ON DELETE CASCADE;
+  ALTER TABLE category_assignment
+  ADD CONSTRAINT category_assignment_user_id_fk FOREIGN KEY (user_id) REFERENCES users (id) ON DELETE CASCADE;
+  ALTER TABLE category_assignment
+  ADD CONSTRAINT category_assignment_category_id_fk FOREIGN KEY (category_id) REFERENCES category (id) ON DELETE CASCADE;
+
+  ALTER TABLE item ADD COLUMN category_id uuid;
+  ALTER TABLE item
+  ADD CONSTRAINT item_category_id_fk FOREIGN KEY (category_id) REFERENCES category (id) ON DELETE SET NULL;
+
+COMMIT;
@@ -0,0 +1,5 @@
+BEGIN;
+  ALTER TABLE item
+  ALTER COLUMN total_amount TYPE real,
+  ALTER COLUMN current_amount TYPE real;
+COMMIT;
@@ -0,0 +1,19 @@
+BEGIN;
+
+  -- amounts keep the precision of their unit, see Unit::precision
+  CREATE FUNCTION unit_precision(unit text) RETURNS int AS $$
+    SELECT CASE
+      WHEN unit IN ('ITEM', 'MILLIGRAM', 'MILLILITER', 'MILLIMETER') THEN 0
+      WHEN unit IN ('GRAM', 'CENTIMETER') THEN 1
+      WHEN unit IN ('DECILITER', 'INCH') THEN 2
+      ELSE 3
+    END
+  $$ LANGUAGE sql IMMUTABLE;
+
+  ALTER TABLE item
+  ALTER COLUMN total_amount TYPE numeric(12, 3) USING round(total_amount::numeric, unit_precision(unit)),
+  ALTER COLUMN current_amount TYPE numeric(12, 3) USING round(current_amount::numeric, unit_precision(unit));
+
+  DROP FUNCTION unit_precision(text);
+
+COMMIT;
@@ -0,0 +1,6 @@
+BEGIN;
+  ALTER TABLE shopping_list DROP COLUMN currency;
+  ALTER TABLE shopping_list DROP COLUMN budget;
+  ALTER TABLE item DROP COLUMN currency;
+  ALTER TABLE item DROP COLUMN unit_price;
+COMMIT;
@@ -0,0 +1,10 @@
+BEGIN;
+
+  -- price of one unit of the item, per gram or per cup, so it needs more than cents
+  ALTER TABLE item ADD COLUMN unit_price numeric(12, 4);
+  ALTER TABLE item ADD COLUMN currency text;
+
+  ALTER TABLE shopping_list ADD COLUMN budget numeric(12, 2);
+  ALTER TABLE shopping_list ADD COLUMN currency text;
+
+COMMIT;
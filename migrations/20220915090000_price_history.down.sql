@@ -0,0 +1,4 @@
+BEGIN;
+  DROP TABLE price_history;
+  ALTER TABLE item DROP COLUMN store_id;
+COMMIT;
@@ -0,0 +1,28 @@
+BEGIN;
+
+  -- store the item is bought in, its price is recorded for that store
+  ALTER TABLE item ADD COLUMN store_id uuid;
+  ALTER TABLE item
+  ADD CONSTRAINT item_store_id_fk FOREIGN KEY (store_id) REFERENCES store (id) ON DELETE SET NULL;
+
+  -- unit price of every item a user bought, products are items with the same normalized name
+  CREATE TABLE price_history (
+    id uuid NOT NULL,
+    user_id uuid NOT NULL,
+    product_name text NOT NULL,
+    store_id uuid,
+    item_id uuid,
+    unit text NOT NULL,
+    unit_price numeric(12, 4) NOT NULL,
+    currency text,
+    recorded_at timestamptz NOT NULL DEFAULT now(),
+    CONSTRAINT price_history_pk PRIMARY KEY (id)
+  );
+  CREATE INDEX price_history_product_idx ON price_history (user_id, product_name, recorded_at);
+
+  ALTER TABLE price_history
+  ADD CONSTRAINT price_history_user_id_fk FOREIGN KEY (user_id) REFERENCES users (id) ON DELETE CASCADE;
+  ALTER TABLE price_history
+  ADD CONSTRAINT price_history_store_id_fk FOREIGN KEY (store_id) REFERENCES store (id) ON DELETE SET NULL;
+
+COMMIT;
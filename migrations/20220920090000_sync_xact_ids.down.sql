@@ -0,0 +1,45 @@
+BEGIN;
+  CREATE OR REPLACE FUNCTION track_list_household() RETURNS trigger AS $$
+  BEGIN
+    IF NEW.household_id IS NOT NULL THEN
+      NEW.household_change_id := NEW.change_id;
+    END IF;
+    RETURN NEW;
+  END;
+  $$ LANGUAGE plpgsql;
+
+  CREATE OR REPLACE FUNCTION track_list_change() RETURNS trigger AS $$
+  BEGIN
+    NEW.change_id := nextval('sync_change_seq');
+    NEW.updated_at := now();
+    IF NEW.household_id IS DISTINCT FROM OLD.household_id THEN
+      NEW.household_change_id := NEW.change_id;
+    END IF;
+    RETURN NEW;
+  END;
+  $$ LANGUAGE plpgsql;
+
+  CREATE OR REPLACE FUNCTION track_item_change() RETURNS trigger AS $$
+  BEGIN
+    NEW.change_id := nextval('sync_change_seq');
+    NEW.updated_at := now();
+    RETURN NEW;
+  END;
+  $$ LANGUAGE plpgsql;
+
+  CREATE OR REPLACE FUNCTION track_change() RETURNS trigger AS $$
+  BEGIN
+    NEW.change_id := nextval('sync_change_seq');
+    RETURN NEW;
+  END;
+  $$ LANGUAGE plpgsql;
+
+  DROP INDEX item_change_xid_idx;
+
+  ALTER TABLE tombstone DROP COLUMN change_xid;
+  ALTER TABLE household_member DROP COLUMN change_xid;
+  ALTER TABLE shopping_list_share DROP COLUMN change_xid;
+  ALTER TABLE item DROP COLUMN change_xid;
+  ALTER TABLE shopping_list DROP COLUMN household_change_xid;
+  ALTER TABLE shopping_list DROP COLUMN change_xid;
+COMMIT;
@@ -0,0 +1,61 @@
+BEGIN;
+
+  -- transaction that wrote the row, a sync sends what was written by transactions that were
+  -- still running at the previous sync even when they took their change_id before it
+  ALTER TABLE shopping_list ADD COLUMN change_xid bigint NOT NULL DEFAULT 0;
+  ALTER TABLE shopping_list ADD COLUMN household_change_xid bigint NOT NULL DEFAULT 0;
+  ALTER TABLE item ADD COLUMN change_xid bigint NOT NULL DEFAULT 0;
+  ALTER TABLE shopping_list_share ADD COLUMN change_xid bigint NOT NULL DEFAULT 0;
+  ALTER TABLE household_member ADD COLUMN change_xid bigint NOT NULL DEFAULT 0;
+  ALTER TABLE tombstone ADD COLUMN change_xid bigint NOT NULL DEFAULT 0;
+
+  -- existing rows keep 0, cursors from before are reset anyway
+  ALTER TABLE shopping_list ALTER COLUMN change_xid SET DEFAULT pg_current_xact_id()::text::bigint;
+  ALTER TABLE item ALTER COLUMN change_xid SET DEFAULT pg_current_xact_id()::text::bigint;
+  ALTER TABLE shopping_list_share ALTER COLUMN change_xid SET DEFAULT pg_current_xact_id()::text::bigint;
+  ALTER TABLE household_member ALTER COLUMN change_xid SET DEFAULT pg_current_xact_id()::text::bigint;
+  ALTER TABLE tombstone ALTER COLUMN change_xid SET DEFAULT pg_current_xact_id()::text::bigint;
+
+  CREATE INDEX item_change_xid_idx ON item (shopping_list_id, change_xid);
+
+  CREATE OR REPLACE FUNCTION track_change() RETURNS trigger AS $$
+  BEGIN
+    NEW.change_id := nextval('sync_change_seq');
+    NEW.change_xid := pg_current_xact_id()::text::bigint;
+    RETURN NEW;
+  END;
+  $$ LANGUAGE plpgsql;
+
+  CREATE OR REPLACE FUNCTION track_item_change() RETURNS trigger AS $$
+  BEGIN
+    NEW.change_id := nextval('sync_change_seq');
+    NEW.change_xid := pg_current_xact_id()::text::bigint;
+    NEW.updated_at := now();
+    RETURN NEW;
+  END;
+  $$ LANGUAGE plpgsql;
+
+  CREATE OR REPLACE FUNCTION track_list_change() RETURNS trigger AS $$
+  BEGIN
+    NEW.change_id := nextval('sync_change_seq');
+    NEW.change_xid := pg_current_xact_id()::text::bigint;
+    NEW.updated_at := now();
+    IF NEW.household_id IS DISTINCT FROM OLD.household_id THEN
+      NEW.household_change_id := NEW.change_id;
+      NEW.household_change_xid := NEW.change_xid;
+    END IF;
+    RETURN NEW;
+  END;
+  $$ LANGUAGE plpgsql;
+
+  CREATE OR REPLACE FUNCTION track_list_household() RETURNS trigger AS $$
+  BEGIN
+    IF NEW.household_id IS NOT NULL THEN
+      NEW.household_change_id := NEW.change_id;
+      NEW.household_change_xid := NEW.change_xid;
+    END IF;
+    RETURN NEW;
+  END;
+  $$ LANGUAGE plpgsql;
+
+COMMIT;
@@ -1,10 +1,22 @@
+#![recursion_limit = "256"]
+
 extern crate dotenv;
+use std::convert::Infallible;
 use dotenv::dotenv;
+use warp::hyper::Server;
+use warp::hyper::server::conn::AddrStream;
+use warp::hyper::service::{make_service_fn, service_fn};
 use shopping_list::register_cancel_handler;
 use tokio_postgres::{NoTls};
-use shopping_list::services::database::{init_postgres, init_redis};
+use shopping_list::services::database::{init_postgres, init_redis, init_redis_client};
+use shopping_list::services::keys::init_keys;
+use shopping_list::services::notifier::init_notifier;
+use shopping_list::services::events::init_list_events;
+use shopping_list::services::sync::init_tombstone_cleanup;
 use shopping_list::routes::router;
 use shopping_list::models::GlobalContext;
+use shopping_list::middlewares::RemoteAddress;
+use shopping_list::middlewares::idempotency::with_idempotency;
 
 const DEFAULT_PORT: u16 = 3030;
 
@@ -21,14 +33,34 @@ async fn main() {
 
     let pg_pool = init_postgres(NoTls).unwrap();
     let redis_pool = init_redis().unwrap();
+    let keys = init_keys().unwrap();
+    let notifier = init_notifier();
+    let events = init_list_events(init_redis_client());
+    init_tombstone_cleanup(pg_pool.clone());
     let ctx = GlobalContext {
-        pg_pool,
-        redis_pool,
+        pg_pool: pg_pool.clone(),
+        redis_pool: redis_pool.clone(),
+        keys: keys.clone(),
+        notifier,
+        events,
     };
 
-    let handlers = router(&ctx);
+    let service = warp::service(router(&ctx));
+    let make_service = make_service_fn(move |connection: &AddrStream| {
+        let remote = RemoteAddress(connection.remote_addr());
+        let service = service.clone();
+        let redis_pool = redis_pool.clone();
+        let pg_pool = pg_pool.clone();
+        let keys = keys.clone();
+        async move {
+            Ok::<_, Infallible>(service_fn(move |mut req| {
+                req.extensions_mut().insert(remote);
+                with_idempotency(req, service.clone(), redis_pool.clone(), pg_pool.clone(), keys.clone())
+            }))
+        }
+    });
 
-    warp::serve(handlers)
-        .run(([0, 0, 0, 0], port))
-        .await;
+    if let Err(e) = Server::bind(&([0, 0, 0, 0], port).into()).serve(make_service).await {
+        println!("Server error {:?}", e);
+    }
 }
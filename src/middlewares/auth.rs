@@ -3,24 +3,80 @@ use warp::http::HeaderMap;
 use uuid::Uuid;
 use warp::{Filter, Rejection};
 use crate::middlewares::error::HttpError;
-use jsonwebtoken::{encode, decode, Header, Algorithm, Validation, EncodingKey, DecodingKey};
+use jsonwebtoken::{encode, decode, decode_header, Header, Algorithm, Validation};
 use jsonwebtoken::errors::Error as TokenError;
 use crate::models::user::TokenClaims;
-use crate::services::database::{RedisPool, RedisConn};
-use mobc_redis::redis::AsyncCommands;
+use crate::services::database::{RedisConn, DBPool, get_connection};
+use crate::services::access_tokens::{find_access_token, find_access_token_user, ACCESS_TOKEN_PREFIX};
+use crate::models::access_token::Scope;
+use crate::services::keys::KeyStore;
+use crate::models::GlobalContext;
+use mobc_redis::redis::{AsyncCommands, Script};
+use rand::Rng;
+use rand::distributions::Alphanumeric;
+use chrono::Utc;
+use std::collections::HashMap;
 
 use super::with_connection;
 
-// const PRIVATE_KEY: &[u8] = std::env::var("JWT_KEY_PRIVATE").unwrap().as_bytes();
-// const PUBLIC_KEY: String = std::env::var("JWT_KEY_PUBLIC").unwrap();
-// TODO load from env v variable?
-const PRIVATE_KEY: &[u8] = include_bytes!("../../jwtRS256.key");
-const PUBLIC_KEY: &[u8] = include_bytes!("../../jwtRS256.key.pub");
 const EXPIRATION_ENV_KEY: &str = "JWT_EXPIRE_MILLIS";
+const REFRESH_EXPIRATION_ENV_KEY: &str = "REFRESH_TOKEN_EXPIRE_MILLIS";
+const DEFAULT_EXPIRATION: usize = 15 * 60 * 1000;
+const DEFAULT_REFRESH_EXPIRATION: usize = 30 * 24 * 60 * 60 * 1000;
+const REFRESH_TOKEN_LENGTH: usize = 64;
+
+// Swaps the current refresh token of a session only if the presented one is still current.
+// Returns 1 when rotated, -1 when an already rotated token was presented and 0 when the session is gone.
+const ROTATE_REFRESH_TOKEN_SCRIPT: &str = r"
+    local current = redis.call('GET', KEYS[1])
+    if not current then
+        return 0
+    end
+    if current ~= ARGV[1] then
+        return -1
+    end
+    redis.call('SET', KEYS[1], ARGV[2], 'PX', ARGV[3])
+    return 1
+";
+
+// Records the use of a session only while its meta hash exists, so neither sessions issued
+// before it was kept nor one revoked concurrently get a hash without expiration.
+const TOUCH_SESSION_SCRIPT: &str = r"
+    if redis.call('EXISTS', KEYS[1]) == 1 then
+        redis.call('HSET', KEYS[1], 'lastSeen', ARGV[1])
+    end
+    return 0
+";
 
 #[derive(Debug)]
 pub struct AuthenticatedUser {
-    pub id: Uuid
+    pub id: Uuid,
+    /// Id of the session, or of the personal access token the request was authenticated with
+    pub session_id: Uuid,
+    /// Scopes of a personal access token, `None` for sessions which may do everything
+    pub scopes: Option<Vec<Scope>>,
+}
+
+impl AuthenticatedUser {
+    /// Needed where one request does things of different scopes.
+    pub fn has_scope(&self, scope: Scope) -> bool {
+        match &self.scopes {
+            Some(scopes) => scopes.contains(&scope),
+            None => true,
+        }
+    }
+}
+
+pub struct SessionTokens {
+    pub token: String,
+    pub refresh_token: String,
+}
+
+pub struct SessionInfo {
+    pub id: Uuid,
+    pub created_at: i64,
+    pub last_seen: i64,
+    pub user_agent: Option<String>,
 }
 
 struct TokenData {
@@ -28,50 +84,250 @@ struct TokenData {
     pub token_id: Uuid,
 }
 
-pub fn with_auth(redis_pool: &RedisPool) -> impl Filter<Extract = (AuthenticatedUser,), Error = Rejection> + Clone {
-    warp::filters::header::headers_cloned()
-        .and(with_connection(redis_pool))
+/// Accepts session tokens only.
+pub fn with_auth(ctx: &GlobalContext) -> impl Filter<Extract = (AuthenticatedUser,), Error = Rejection> + Clone {
+    authorize(ctx, None)
+}
+
+/// Accepts session tokens and personal access tokens granted the scope.
+pub fn with_scope(ctx: &GlobalContext, scope: Scope) -> impl Filter<Extract = (AuthenticatedUser,), Error = Rejection> + Clone {
+    authorize(ctx, Some(scope))
+}
+
+/// Like `with_scope`, but also takes the token from the `token` query parameter
+/// since browsers can't set headers when opening a WebSocket.
+pub fn with_socket_scope(ctx: &GlobalContext, scope: Scope) -> impl Filter<Extract = (AuthenticatedUser,), Error = Rejection> + Clone {
+    let query_token = warp::query::<HashMap<String, String>>()
+        .map(|mut query: HashMap<String, String>| query.remove("token"))
+        .or(warp::any().map(|| None))
+        .unify();
+    let token = warp::filters::header::headers_cloned()
+        .and(query_token)
+        .map(|headers: HeaderMap, query_token: Option<String>| get_bearer_token(&headers).or(query_token));
+    authenticate_with(ctx, token, Some(scope))
+}
+
+fn authorize(ctx: &GlobalContext, scope: Option<Scope>) -> impl Filter<Extract = (AuthenticatedUser,), Error = Rejection> + Clone {
+    let token = warp::filters::header::headers_cloned()
+        .map(|headers: HeaderMap| get_bearer_token(&headers));
+    authenticate_with(ctx, token, scope)
+}
+
+fn authenticate_with<F>(ctx: &GlobalContext, token: F, scope: Option<Scope>) -> impl Filter<Extract = (AuthenticatedUser,), Error = Rejection> + Clone
+    where
+        F: Filter<Extract = (Option<String>,), Error = std::convert::Infallible> + Clone,
+{
+    let pg_pool = ctx.pg_pool.clone();
+    token
+        .and(with_connection(&ctx.redis_pool))
+        .and(with_keys(&ctx.keys))
+        .and(warp::any().map(move || pg_pool.clone()))
+        .and(warp::any().map(move || scope))
         .and_then(authenticate)
 }
 
-async fn authenticate(headers: HeaderMap, mut redis: RedisConn) -> Result<AuthenticatedUser, Rejection> {
-    let header = headers.get(AUTHORIZATION);
-    if let Some(auth_header) = header {
-        let token = auth_header.to_str()
-            .map_err(|_err| HttpError::InvalidToken)?;
-        let parts: Vec<&str> = token.split(" ").collect();
-        let TokenData { user_id, token_id } = validate_token(parts[1])
+pub fn with_keys(keys: &KeyStore) -> impl Filter<Extract = (KeyStore,), Error = std::convert::Infallible> + Clone {
+    let keys = keys.clone();
+    warp::any().map(move || keys.clone())
+}
+
+/// User the credentials of the request belong to, `None` without valid ones. Neither the session
+/// nor the scope of a token is checked, the route still authenticates the request itself.
+pub async fn identify_user(headers: &HeaderMap, keys: &KeyStore, pg_pool: DBPool) -> Result<Option<Uuid>, Rejection> {
+    let token = match get_bearer_token(headers) {
+        Some(token) => token,
+        None => return Ok(None),
+    };
+    if token.starts_with(ACCESS_TOKEN_PREFIX) {
+        let db = get_connection(pg_pool, "postgres").await?;
+        return Ok(find_access_token_user(&token, &db).await?);
+    }
+    Ok(validate_token(&token, keys).ok().map(|data| data.user_id))
+}
+
+fn get_bearer_token(headers: &HeaderMap) -> Option<String> {
+    let auth_header = headers.get(AUTHORIZATION)?.to_str().ok()?;
+    auth_header.split(' ').nth(1).map(String::from)
+}
+
+async fn authenticate(
+    token: Option<String>,
+    mut redis: RedisConn,
+    keys: KeyStore,
+    pg_pool: DBPool,
+    scope: Option<Scope>,
+) -> Result<AuthenticatedUser, Rejection> {
+    if let Some(token) = token {
+        if token.starts_with(ACCESS_TOKEN_PREFIX) {
+            return authenticate_access_token(&token, pg_pool, scope).await;
+        }
+        let TokenData { user_id, token_id } = validate_token(&token, &keys)
             .map_err(|error| warp::reject::custom(error))?;
-        // TODO refresh token
         let session_key = get_redis_auth_key(&user_id, Some(token_id));
-        let session_user_id: Option<String> = redis.get(session_key).await.map_err(|e| HttpError::Redis(e))?;
+        let session_user_id: Option<String> = redis.get(session_key).await.map_err(HttpError::Redis)?;
         let session_token = session_user_id.ok_or(String::from("Session expired")).map_err(|msg| HttpError::Unauthorized(msg))?;
-        if session_token != parts[1] {
+        if session_token != token {
             return Err(warp::reject::custom(HttpError::InvalidToken));
         }
-        return Ok(AuthenticatedUser { id: user_id });
+        let now = Utc::now().timestamp_millis();
+        let _: i32 = Script::new(TOUCH_SESSION_SCRIPT)
+            .key(get_redis_session_meta_key(&user_id, &token_id))
+            .arg(now)
+            .invoke_async(&mut *redis)
+            .await
+            .map_err(HttpError::Redis)?;
+        return Ok(AuthenticatedUser { id: user_id, session_id: token_id, scopes: None });
     }
     Err(warp::reject::custom(HttpError::InvalidToken))
 }
 
-pub async fn create_token(user_id: &Uuid, mut redis: RedisConn) -> Result<String, TokenError> {
-    let expiration: usize = dotenv::var(EXPIRATION_ENV_KEY).unwrap().parse::<usize>().unwrap();
-    let token_id = Uuid::new_v4();
-    let claims = TokenClaims::new(user_id, token_id, expiration);
-    let encoding_key = EncodingKey::from_rsa_pem(PRIVATE_KEY)?;
-    let header = Header::new(Algorithm::RS256);
-    let token = encode(&header, &claims, &encoding_key)?;
-    let new_session_key = get_redis_auth_key(user_id, Some(token_id));
-    
-    let _: () = redis.pset_ex(new_session_key, token.as_str(), expiration).await.unwrap();
+async fn authenticate_access_token(token: &str, pg_pool: DBPool, scope: Option<Scope>) -> Result<AuthenticatedUser, Rejection> {
+    let required_scope = scope.ok_or_else(|| {
+        let msg = String::from("Personal access tokens can't be used for this endpoint");
+        warp::reject::custom(HttpError::Forbidden(msg))
+    })?;
+    let db = get_connection(pg_pool, "postgres").await?;
+    let access_token = find_access_token(token, &db).await?
+        .ok_or_else(|| warp::reject::custom(HttpError::Unauthorized(String::from("Invalid token"))))?;
+    if !access_token.scopes.contains(&required_scope) {
+        let msg = format!("Token is missing the {} scope", required_scope);
+        return Err(warp::reject::custom(HttpError::Forbidden(msg)));
+    }
+    Ok(AuthenticatedUser {
+        id: access_token.user_id,
+        session_id: access_token.id,
+        scopes: Some(access_token.scopes),
+    })
+}
+
+pub async fn create_token(user_id: &Uuid, user_agent: Option<String>, keys: &KeyStore, mut redis: RedisConn) -> Result<SessionTokens, HttpError> {
+    let session_id = Uuid::new_v4();
+    let token = issue_access_token(user_id, &session_id, keys, &mut redis).await?;
+    let refresh_token = generate_refresh_token();
+    let refresh_expiration = get_expiration(REFRESH_EXPIRATION_ENV_KEY, DEFAULT_REFRESH_EXPIRATION);
+
+    let now = Utc::now().timestamp_millis().to_string();
+    let mut meta = vec![("createdAt", now.clone()), ("lastSeen", now)];
+    if let Some(user_agent) = user_agent {
+        meta.push(("userAgent", user_agent));
+    }
+    let meta_key = get_redis_session_meta_key(user_id, &session_id);
+    let _: () = redis.hset_multiple(&meta_key, &meta).await.map_err(HttpError::Redis)?;
+    let _: () = redis.pexpire(&meta_key, refresh_expiration).await.map_err(HttpError::Redis)?;
+
+    let _: () = redis.pset_ex(get_redis_refresh_key(user_id, &session_id), refresh_token.as_str(), refresh_expiration).await.map_err(HttpError::Redis)?;
+    let _: () = redis.pset_ex(get_redis_refresh_lookup_key(&refresh_token), get_redis_auth_key(user_id, Some(session_id)), refresh_expiration).await.map_err(HttpError::Redis)?;
+    Ok(SessionTokens { token, refresh_token })
+}
+
+/// Exchanges a refresh token for a new access and refresh token of the same session.
+/// Presenting a refresh token that was already rotated revokes the whole session,
+/// as it means the token leaked to someone else.
+pub async fn refresh_token(refresh_token: &str, keys: &KeyStore, mut redis: RedisConn) -> Result<(Uuid, SessionTokens), HttpError> {
+    let lookup_key = get_redis_refresh_lookup_key(refresh_token);
+    let session_key: Option<String> = redis.get(&lookup_key).await.map_err(HttpError::Redis)?;
+    let session_key = session_key.ok_or_else(|| HttpError::Unauthorized(String::from("Invalid refresh token")))?;
+    let (user_id, session_id) = parse_redis_auth_key(&session_key).ok_or(HttpError::InvalidToken)?;
+
+    let refresh_expiration = get_expiration(REFRESH_EXPIRATION_ENV_KEY, DEFAULT_REFRESH_EXPIRATION);
+    let new_refresh_token = generate_refresh_token();
+    let rotated: i32 = Script::new(ROTATE_REFRESH_TOKEN_SCRIPT)
+        .key(get_redis_refresh_key(&user_id, &session_id))
+        .arg(refresh_token)
+        .arg(new_refresh_token.as_str())
+        .arg(refresh_expiration)
+        .invoke_async(&mut *redis)
+        .await
+        .map_err(HttpError::Redis)?;
+
+    match rotated {
+        1 => {
+            let _: () = redis.pset_ex(get_redis_refresh_lookup_key(&new_refresh_token), session_key.as_str(), refresh_expiration).await.map_err(HttpError::Redis)?;
+            let _: () = redis.pexpire(get_redis_session_meta_key(&user_id, &session_id), refresh_expiration).await.map_err(HttpError::Redis)?;
+            let token = issue_access_token(&user_id, &session_id, keys, &mut redis).await?;
+            Ok((user_id, SessionTokens { token, refresh_token: new_refresh_token }))
+        },
+        -1 => {
+            println!("Refresh token reuse detected, revoking session {}", session_key);
+            revoke_session(&user_id, &session_id, redis).await?;
+            Err(HttpError::Unauthorized(String::from("Invalid refresh token")))
+        },
+        _ => Err(HttpError::Unauthorized(String::from("Session expired"))),
+    }
+}
+
+async fn issue_access_token(user_id: &Uuid, session_id: &Uuid, keys: &KeyStore, redis: &mut RedisConn) -> Result<String, HttpError> {
+    let expiration = get_expiration(EXPIRATION_ENV_KEY, DEFAULT_EXPIRATION);
+    let claims = TokenClaims::new(user_id, *session_id, expiration);
+    let mut header = Header::new(Algorithm::RS256);
+    header.kid = Some(String::from(keys.signing_key_id()));
+    let token = encode(&header, &claims, keys.signing_key()).map_err(|e: TokenError| {
+        println!("Token creation failed {:?}", e);
+        HttpError::InternalServerError
+    })?;
+    let new_session_key = get_redis_auth_key(user_id, Some(*session_id));
+
+    let _: () = redis.pset_ex(new_session_key, token.as_str(), expiration).await.map_err(HttpError::Redis)?;
     Ok(token)
 }
 
+/// Revokes a single session, other sessions of the user stay logged in.
+pub async fn revoke_session(user_id: &Uuid, session_id: &Uuid, mut redis: RedisConn) -> Result<(), HttpError> {
+    let keys = vec![
+        get_redis_auth_key(user_id, Some(*session_id)),
+        get_redis_refresh_key(user_id, session_id),
+        get_redis_session_meta_key(user_id, session_id),
+    ];
+    redis.del::<_, ()>(keys).await.map_err(HttpError::Redis)
+}
+
+/// Revokes every session of the user except the one given.
+pub async fn revoke_other_sessions(user_id: &Uuid, current_session_id: &Uuid, mut redis: RedisConn) -> Result<(), HttpError> {
+    let current_keys = [
+        get_redis_auth_key(user_id, Some(*current_session_id)),
+        get_redis_refresh_key(user_id, current_session_id),
+        get_redis_session_meta_key(user_id, current_session_id),
+    ];
+    let keys: Vec<String> = redis.keys(get_redis_auth_key(user_id, None)).await.map_err(HttpError::Redis)?;
+    let other_keys: Vec<String> = keys.into_iter().filter(|key| !current_keys.contains(key)).collect();
+    if other_keys.is_empty() {
+        return Ok(());
+    }
+    redis.del::<_, ()>(other_keys).await.map_err(HttpError::Redis)
+}
+
+pub async fn get_user_sessions(user_id: &Uuid, mut redis: RedisConn) -> Result<Vec<SessionInfo>, HttpError> {
+    let meta_keys: Vec<String> = redis.keys(get_redis_session_meta_key_query(user_id)).await.map_err(HttpError::Redis)?;
+    let mut sessions = Vec::new();
+    for key in meta_keys.iter() {
+        let session_id = key.split(':')
+            .nth(1)
+            .and_then(|id| Uuid::parse_str(id).ok());
+        let meta: HashMap<String, String> = redis.hgetall(key).await.map_err(HttpError::Redis)?;
+        // left behind by lastSeen updates that raced a revoke, they have nothing else and no expiration
+        if !meta.contains_key("createdAt") {
+            redis.del::<_, ()>(key).await.map_err(HttpError::Redis)?;
+            continue;
+        }
+        if let Some(id) = session_id {
+            let get_timestamp = |field: &str| meta.get(field).and_then(|value| value.parse::<i64>().ok()).unwrap_or_default();
+            sessions.push(SessionInfo {
+                id,
+                created_at: get_timestamp("createdAt"),
+                last_seen: get_timestamp("lastSeen"),
+                user_agent: meta.get("userAgent").cloned(),
+            });
+        }
+    }
+    sessions.sort_by_key(|session| std::cmp::Reverse(session.last_seen));
+    Ok(sessions)
+}
+
 pub async fn delete_user_tokens(user_id: &Uuid, mut redis: RedisConn) -> Result<bool, HttpError> {
     let key_query = get_redis_auth_key(user_id, None);
-    let keys: Vec<String> = redis.keys(key_query).await.map_err(|e| HttpError::Redis(e))?;
+    let keys: Vec<String> = redis.keys(key_query).await.map_err(HttpError::Redis)?;
     for key in keys.iter() {
-        redis.del(key).await.map_err(|e| HttpError::Redis(e))?;
+        redis.del::<_, ()>(key).await.map_err(HttpError::Redis)?;
     }
     Ok(true)
 }
@@ -83,12 +339,50 @@ fn get_redis_auth_key(user_id: &Uuid, token_id: Option<Uuid>) -> String {
     }
 }
 
-fn validate_token(token: &str) -> Result<TokenData, HttpError> {
-    let decoding_key = &DecodingKey::from_rsa_pem(PUBLIC_KEY)
-        .map_err(|_err| HttpError::InternalServerError)?;
+fn get_redis_refresh_key(user_id: &Uuid, session_id: &Uuid) -> String {
+    format!("{}:{}:refresh", user_id, session_id)
+}
+
+fn get_redis_session_meta_key(user_id: &Uuid, session_id: &Uuid) -> String {
+    format!("{}:{}:meta", user_id, session_id)
+}
+
+fn get_redis_session_meta_key_query(user_id: &Uuid) -> String {
+    format!("{}:*:meta", user_id)
+}
+
+fn get_redis_refresh_lookup_key(refresh_token: &str) -> String {
+    format!("refresh:{}", refresh_token)
+}
+
+fn parse_redis_auth_key(key: &str) -> Option<(Uuid, Uuid)> {
+    let (user_id, session_id) = key.split_once(':')?;
+    Some((Uuid::parse_str(user_id).ok()?, Uuid::parse_str(session_id).ok()?))
+}
+
+fn get_expiration(env_key: &str, default: usize) -> usize {
+    dotenv::var(env_key)
+        .ok()
+        .and_then(|value| value.parse::<usize>().ok())
+        .unwrap_or(default)
+}
+
+fn generate_refresh_token() -> String {
+    rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(REFRESH_TOKEN_LENGTH)
+        .map(char::from)
+        .collect()
+}
+
+fn validate_token(token: &str, keys: &KeyStore) -> Result<TokenData, HttpError> {
+    let header = decode_header(token)
+        .map_err(|_err| HttpError::Unauthorized(String::from("Invalid token")))?;
+    let decoding_key = keys.verification_key(header.kid.as_deref())
+        .ok_or_else(|| HttpError::Unauthorized(String::from("Invalid token")))?;
     let validation = Validation::new(Algorithm::RS256);
 
-    let data = decode::<TokenClaims>(token, &decoding_key, &validation)
+    let data = decode::<TokenClaims>(token, decoding_key, &validation)
         .map_err(|_err| HttpError::Unauthorized(String::from("Invalid token")))?;
     let user_id = Uuid::parse_str(data.claims.sub.as_str())
         .map_err(|_e| HttpError::InvalidToken)?;
@@ -4,10 +4,12 @@ use warp::{Rejection, Reply};
 use warp::reject::{Reject, MethodNotAllowed, InvalidQuery};
 use std::convert::Infallible;
 use warp::http::StatusCode;
+use warp::http::header::{RETRY_AFTER, ETAG};
 use tokio_postgres::Error as TokioError;
 use mobc_redis::redis::RedisError;
 use warp::filters::body::BodyDeserializeError;
 use std::error::Error;
+use crate::middlewares::precondition::get_etag;
 
 #[derive(Debug)]
 pub enum HttpError {
@@ -20,6 +22,9 @@ pub enum HttpError {
     Forbidden(String),
     NotFound(String),
     Conflict(String),
+    TooManyRequests(String, u64),
+    /// Current representation and version of a resource that was changed concurrently
+    PreconditionFailed(serde_json::Value, i32),
     InternalServerError,
 }
 
@@ -34,9 +39,18 @@ struct HttpErrorBody<'s, T> {
 
 pub async fn handle_rejection(rejection: Rejection) -> Result<impl Reply, Infallible> {
     println!("rejection is {:?}", rejection);
+    if let Some(HttpError::PreconditionFailed(current, version)) = rejection.find::<HttpError>() {
+        let reply = warp::reply::with_status(warp::reply::json(current), StatusCode::PRECONDITION_FAILED);
+        let mut response = reply.into_response();
+        if let Ok(etag) = get_etag(*version).parse() {
+            response.headers_mut().insert(ETAG, etag);
+        }
+        return Ok(response);
+    }
     let status;
     let message;
     let mut validation_errors : Option<Vec<ValidationErrors>> = None;
+    let mut retry_after: Option<u64> = None;
     if rejection.is_not_found() {
         status = StatusCode::NOT_FOUND;
         message = String::from("Not found");
@@ -44,6 +58,9 @@ pub async fn handle_rejection(rejection: Rejection) -> Result<impl Reply, Infall
     else if let Some(e) = rejection.find::<HttpError>() {
         let (new_status, new_message, errors) = error_match(e);
         println!("Error: {:?}", e);
+        if let HttpError::TooManyRequests(_, seconds) = e {
+            retry_after = Some(*seconds);
+        }
         validation_errors = errors;
         status = new_status;
         message = String::from(new_message);
@@ -72,10 +89,24 @@ pub async fn handle_rejection(rejection: Rejection) -> Result<impl Reply, Infall
         data: validation_errors,
     };
     let reply = warp::reply::json(&error);
-    Ok(warp::reply::with_status(reply, status))
+    let mut response = warp::reply::with_status(reply, status).into_response();
+    if let Some(seconds) = retry_after {
+        response.headers_mut().insert(RETRY_AFTER, seconds.into());
+    }
+    Ok(response)
+}
+
+/// For errors outside of the filters, in the same shape as rejections.
+pub fn error_reply(status: StatusCode, message: &str) -> warp::reply::Response {
+    let error: HttpErrorBody<ValidationErrors> = HttpErrorBody {
+        message,
+        code: status.as_str(),
+        data: None,
+    };
+    warp::reply::with_status(warp::reply::json(&error), status).into_response()
 }
 
-fn error_match(error: &HttpError) -> (StatusCode, &str, Option<Vec<ValidationErrors>>) {
+pub fn error_match(error: &HttpError) -> (StatusCode, &str, Option<Vec<ValidationErrors>>) {
     match error {
         HttpError::BadRequest(e) => {
             let mut errors = Vec::new();
@@ -99,6 +130,8 @@ fn error_match(error: &HttpError) -> (StatusCode, &str, Option<Vec<ValidationErr
         HttpError::Forbidden(message) => (StatusCode::FORBIDDEN, message.as_str(), None),
         HttpError::NotFound(message) => (StatusCode::NOT_FOUND, message.as_str(), None),
         HttpError::Conflict(message) => (StatusCode::CONFLICT, message.as_str(), None),
+        HttpError::TooManyRequests(message, _) => (StatusCode::TOO_MANY_REQUESTS, message.as_str(), None),
+        HttpError::PreconditionFailed(_, _) => (StatusCode::PRECONDITION_FAILED, "Resource was modified", None),
         HttpError::InternalServerError => (StatusCode::INTERNAL_SERVER_ERROR, "Internal server error", None),
     }
 }
@@ -0,0 +1,275 @@
+use std::convert::Infallible;
+use serde_derive::{Deserialize, Serialize};
+use ring::digest::{Context, SHA256};
+use uuid::Uuid;
+use mobc_redis::redis;
+use warp::http::{HeaderMap, HeaderValue, Method, StatusCode};
+use warp::http::header::{CONTENT_LENGTH, HeaderName};
+use warp::hyper::{Body, Request, Response};
+use warp::hyper::body::{Bytes, HttpBody};
+use warp::hyper::service::Service;
+use crate::middlewares::auth::identify_user;
+use crate::middlewares::error::error_reply;
+use crate::services::database::{DBPool, RedisConn, RedisPool, get_connection};
+use crate::services::keys::KeyStore;
+
+const IDEMPOTENCY_KEY_HEADER: &str = "idempotency-key";
+const REPLAYED_HEADER: &str = "idempotent-replayed";
+const IDEMPOTENCY_WINDOW_ENV_KEY: &str = "IDEMPOTENCY_KEY_EXPIRE_SECONDS";
+const DEFAULT_IDEMPOTENCY_WINDOW_SECONDS: usize = 24 * 60 * 60;
+// a request that never finished, e.g. because the instance died, doesn't block its key for the whole window
+const IN_PROGRESS_EXPIRATION_SECONDS: usize = 60;
+const MAX_KEY_LENGTH: usize = 255;
+// larger bodies are rejected by the routes anyway
+const MAX_BODY_LENGTH: usize = 1024 * 16;
+
+/// What is kept for a key, the response is missing while the first request is still being handled.
+#[derive(Debug, Serialize, Deserialize)]
+struct IdempotencyRecord {
+    fingerprint: String,
+    response: Option<StoredResponse>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct StoredResponse {
+    status: u16,
+    headers: Vec<(String, String)>,
+    /// base64, bodies aren't necessarily text
+    body: String,
+}
+
+/// Runs a mutating request with an `Idempotency-Key` header only once. The response is stored
+/// and replayed for retries with the same key and body, reusing the key for a different request
+/// is rejected. Keys are scoped by the user the request is authenticated as, so users can't see
+/// each other's responses. This wraps the whole service as warp filters can't read the body twice.
+pub async fn with_idempotency<S>(
+    req: Request<Body>,
+    mut service: S,
+    redis_pool: RedisPool,
+    pg_pool: DBPool,
+    keys: KeyStore,
+) -> Result<Response<Body>, Infallible>
+    where
+        S: Service<Request<Body>, Response = Response<Body>, Error = Infallible>,
+{
+    let key = match req.headers().get(IDEMPOTENCY_KEY_HEADER) {
+        Some(key) if is_mutating(req.method()) => key.clone(),
+        _ => return service.call(req).await,
+    };
+    let key = match key.to_str() {
+        Ok(key) if !key.is_empty() && key.len() <= MAX_KEY_LENGTH => String::from(key),
+        _ => return Ok(error_reply(StatusCode::BAD_REQUEST, "Invalid Idempotency-Key header")),
+    };
+
+    let (parts, body) = req.into_parts();
+    let body = match read_body(body, &parts.headers).await {
+        Some(body) => body,
+        None => return Ok(error_reply(StatusCode::PAYLOAD_TOO_LARGE, "Payload too large")),
+    };
+    let fingerprint = get_fingerprint(&parts.method, &parts.uri.to_string(), &body);
+    let user_id = match identify_user(&parts.headers, &keys, pg_pool).await {
+        Ok(user_id) => user_id,
+        Err(_e) => return Ok(error_reply(StatusCode::INTERNAL_SERVER_ERROR, "Internal server error")),
+    };
+    let redis_key = get_redis_key(user_id.as_ref(), &key);
+
+    let mut redis = match get_connection(redis_pool.clone(), "redis").await {
+        Ok(redis) => redis,
+        Err(_e) => return Ok(error_reply(StatusCode::INTERNAL_SERVER_ERROR, "Internal server error")),
+    };
+    let pending = IdempotencyRecord { fingerprint: fingerprint.clone(), response: None };
+    match claim_key(&mut redis, &redis_key, &pending).await {
+        Ok(true) => {},
+        Ok(false) => return Ok(replay(&mut redis, &redis_key, &fingerprint).await),
+        Err(e) => {
+            println!("Failed to claim idempotency key {:?}", e);
+            return Ok(error_reply(StatusCode::INTERNAL_SERVER_ERROR, "Internal server error"));
+        }
+    }
+    // back to the pool while the request is handled, the route may need connections of its own
+    drop(redis);
+
+    let response = service.call(Request::from_parts(parts, Body::from(body))).await?;
+    let mut redis = match get_connection(redis_pool, "redis").await {
+        Ok(redis) => redis,
+        // the key expires with IN_PROGRESS_EXPIRATION_SECONDS, the response still reaches the client
+        Err(_e) => return Ok(response),
+    };
+    Ok(store_response(&mut redis, &redis_key, fingerprint, response).await)
+}
+
+fn is_mutating(method: &Method) -> bool {
+    method == Method::POST || method == Method::PATCH || method == Method::DELETE
+}
+
+/// Successes and client errors that only depend on the request. Server errors, conflicts of
+/// concurrent changes, lockouts and rejected credentials may pass on a retry.
+fn is_replayable(status: StatusCode) -> bool {
+    status.is_success() || matches!(
+        status,
+        StatusCode::BAD_REQUEST | StatusCode::NOT_FOUND | StatusCode::PRECONDITION_FAILED | StatusCode::UNPROCESSABLE_ENTITY
+    )
+}
+
+async fn read_body(mut body: Body, headers: &HeaderMap) -> Option<Bytes> {
+    let content_length = headers.get(CONTENT_LENGTH)
+        .and_then(|length| length.to_str().ok())
+        .and_then(|length| length.parse::<usize>().ok())
+        .unwrap_or(0);
+    if content_length > MAX_BODY_LENGTH {
+        return None;
+    }
+    let mut bytes = Vec::with_capacity(content_length);
+    while let Some(chunk) = body.data().await {
+        bytes.extend_from_slice(&chunk.ok()?);
+        if bytes.len() > MAX_BODY_LENGTH {
+            return None;
+        }
+    }
+    Some(Bytes::from(bytes))
+}
+
+fn get_fingerprint(method: &Method, uri: &str, body: &[u8]) -> String {
+    let mut context = Context::new(&SHA256);
+    context.update(method.as_str().as_bytes());
+    context.update(b" ");
+    context.update(uri.as_bytes());
+    context.update(b"\n");
+    context.update(body);
+    base64::encode(context.finish().as_ref())
+}
+
+/// Requests without valid credentials, like logins, share one scope.
+fn get_redis_key(user_id: Option<&Uuid>, key: &str) -> String {
+    match user_id {
+        Some(user_id) => format!("idempotency:{}:{}", user_id, key),
+        None => format!("idempotency:anonymous:{}", key),
+    }
+}
+
+/// Whether this request is the first one with the key.
+async fn claim_key(redis: &mut RedisConn, redis_key: &str, pending: &IdempotencyRecord) -> redis::RedisResult<bool> {
+    let pending = serde_json::to_string(pending).unwrap_or_default();
+    let claimed: Option<String> = redis::cmd("SET")
+        .arg(redis_key)
+        .arg(pending)
+        .arg("NX")
+        .arg("EX")
+        .arg(IN_PROGRESS_EXPIRATION_SECONDS)
+        .query_async(&mut **redis)
+        .await?;
+    Ok(claimed.is_some())
+}
+
+async fn replay(redis: &mut RedisConn, redis_key: &str, fingerprint: &str) -> Response<Body> {
+    let stored: redis::RedisResult<Option<String>> = redis::cmd("GET").arg(redis_key).query_async(&mut **redis).await;
+    let record = match stored {
+        Ok(Some(record)) => serde_json::from_str::<IdempotencyRecord>(&record).ok(),
+        Ok(None) => None,
+        Err(e) => {
+            println!("Failed to read idempotency key {:?}", e);
+            return error_reply(StatusCode::INTERNAL_SERVER_ERROR, "Internal server error");
+        }
+    };
+    match record {
+        Some(record) if record.fingerprint != fingerprint => {
+            error_reply(StatusCode::UNPROCESSABLE_ENTITY, "Idempotency-Key was already used for a different request")
+        },
+        Some(IdempotencyRecord { response: Some(response), .. }) => to_response(response),
+        // still in progress, or expired just now
+        _ => error_reply(StatusCode::CONFLICT, "A request with this Idempotency-Key is still in progress"),
+    }
+}
+
+/// Only responses a retry would get again are stored, for the others the key is released so
+/// the request can be retried with it.
+async fn store_response(redis: &mut RedisConn, redis_key: &str, fingerprint: String, response: Response<Body>) -> Response<Body> {
+    let (parts, body) = response.into_parts();
+    if !is_replayable(parts.status) {
+        let deleted: redis::RedisResult<()> = redis::cmd("DEL").arg(redis_key).query_async(&mut **redis).await;
+        if let Err(e) = deleted {
+            println!("Failed to release idempotency key {:?}", e);
+        }
+        return Response::from_parts(parts, body);
+    }
+
+    let body = match warp::hyper::body::to_bytes(body).await {
+        Ok(body) => body,
+        Err(_e) => return error_reply(StatusCode::INTERNAL_SERVER_ERROR, "Internal server error"),
+    };
+    let headers = parts.headers.iter()
+        .filter_map(|(name, value)| Some((name.to_string(), String::from(value.to_str().ok()?))))
+        .collect();
+    let record = IdempotencyRecord {
+        fingerprint,
+        response: Some(StoredResponse { status: parts.status.as_u16(), headers, body: base64::encode(&body) }),
+    };
+    let window = dotenv::var(IDEMPOTENCY_WINDOW_ENV_KEY)
+        .ok()
+        .and_then(|value| value.parse::<usize>().ok())
+        .unwrap_or(DEFAULT_IDEMPOTENCY_WINDOW_SECONDS);
+    let stored: redis::RedisResult<()> = redis::cmd("SET")
+        .arg(redis_key)
+        .arg(serde_json::to_string(&record).unwrap_or_default())
+        .arg("EX")
+        .arg(window)
+        .query_async(&mut **redis)
+        .await;
+    if let Err(e) = stored {
+        println!("Failed to store idempotent response {:?}", e);
+    }
+    Response::from_parts(parts, Body::from(body))
+}
+
+fn to_response(stored: StoredResponse) -> Response<Body> {
+    let body = base64::decode(&stored.body).unwrap_or_default();
+    let mut response = Response::new(Body::from(body));
+    *response.status_mut() = StatusCode::from_u16(stored.status).unwrap_or(StatusCode::OK);
+    for (name, value) in stored.headers.iter() {
+        if let (Ok(name), Ok(value)) = (name.parse::<HeaderName>(), value.parse::<HeaderValue>()) {
+            response.headers_mut().append(name, value);
+        }
+    }
+    response.headers_mut().insert(REPLAYED_HEADER, HeaderValue::from_static("true"));
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::middlewares::error::{HttpError, error_match};
+
+    #[test]
+    fn successes_and_deterministic_errors_are_replayed() {
+        assert!(is_replayable(StatusCode::OK));
+        assert!(is_replayable(StatusCode::CREATED));
+        assert!(is_replayable(StatusCode::NO_CONTENT));
+        assert!(is_replayable(StatusCode::BAD_REQUEST));
+        assert!(is_replayable(StatusCode::NOT_FOUND));
+        assert!(is_replayable(StatusCode::PRECONDITION_FAILED));
+        assert!(is_replayable(StatusCode::UNPROCESSABLE_ENTITY));
+    }
+
+    #[test]
+    fn concurrent_change_conflict_releases_the_key() {
+        let conflict = HttpError::Conflict(String::from("Item is being changed concurrently, try again"));
+        let (status, _message, _errors) = error_match(&conflict);
+        assert_eq!(status, StatusCode::CONFLICT);
+        assert!(!is_replayable(status));
+    }
+
+    #[test]
+    fn login_lockout_releases_the_key() {
+        let lockout = HttpError::TooManyRequests(String::from("Too many failed login attempts"), 30);
+        let (status, _message, _errors) = error_match(&lockout);
+        assert!(!is_replayable(status));
+    }
+
+    #[test]
+    fn errors_that_pass_on_a_retry_release_the_key() {
+        assert!(!is_replayable(StatusCode::UNAUTHORIZED));
+        assert!(!is_replayable(StatusCode::FORBIDDEN));
+        assert!(!is_replayable(StatusCode::INTERNAL_SERVER_ERROR));
+        assert!(!is_replayable(StatusCode::SERVICE_UNAVAILABLE));
+    }
+}
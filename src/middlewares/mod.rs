@@ -1,5 +1,7 @@
 pub mod auth;
 pub mod error;
+pub mod precondition;
+pub mod idempotency;
 
 use std::fmt::{Debug};
 use validator::{Validate, ValidationErrors};
@@ -8,6 +10,11 @@ use serde::de::DeserializeOwned;
 use crate::middlewares::error::HttpError;
 use crate::services::database::{get_connection};
 use mobc::{Pool, Manager, Connection};
+use crate::services::notifier::SharedNotifier;
+use crate::services::events::ListEvents;
+use crate::services::login_throttle::ClientAddress;
+use std::convert::Infallible;
+use std::net::SocketAddr;
 
 fn validate_dto<T: Validate>(data: T) -> Result<T, HttpError> {
     match data.validate() {
@@ -65,4 +72,26 @@ where <M as Manager>::Error: std::fmt::Debug
     warp::any()
         .map(move || pool.clone())
         .and_then(|cloned_pool| get_connection(cloned_pool, "redis"))
-}
\ No newline at end of file
+}
+
+pub fn with_notifier(notifier: &SharedNotifier) -> impl Filter<Extract = (SharedNotifier,), Error = Infallible> + Clone {
+    let notifier = notifier.clone();
+    warp::any().map(move || notifier.clone())
+}
+
+pub fn with_events(events: &ListEvents) -> impl Filter<Extract = (ListEvents,), Error = Infallible> + Clone {
+    let events = events.clone();
+    warp::any().map(move || events.clone())
+}
+
+/// Address of the connection, set by the server on every request as it doesn't serve
+/// through warp directly, see `idempotency::with_idempotency`.
+#[derive(Debug, Clone, Copy)]
+pub struct RemoteAddress(pub SocketAddr);
+
+pub fn with_client_address() -> impl Filter<Extract = (ClientAddress,), Error = Rejection> + Clone {
+    warp::ext::optional::<RemoteAddress>()
+        .map(|remote: Option<RemoteAddress>| remote.map(|remote| remote.0))
+        .and(warp::header::optional::<String>("x-forwarded-for"))
+        .map(ClientAddress::new)
+}
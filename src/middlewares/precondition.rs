@@ -0,0 +1,51 @@
+use serde::Serialize;
+use warp::{Filter, Rejection, Reply};
+use warp::http::header::ETAG;
+use warp::reply::WithHeader;
+use crate::middlewares::error::HttpError;
+
+/// Attempts of an unconditional update that keeps losing against concurrent changes.
+pub(crate) const MAX_UPDATE_ATTEMPTS: usize = 3;
+
+/// Value of the `If-Match` header, requests without it aren't conditional.
+#[derive(Debug, Clone)]
+pub struct IfMatch(Option<String>);
+
+impl IfMatch {
+    /// Condition on a version the client already knows, e.g. the base version of a queued offline change.
+    pub fn from_version(version: Option<i32>) -> Self {
+        IfMatch(version.map(get_etag))
+    }
+
+    pub fn is_present(&self) -> bool {
+        self.0.is_some()
+    }
+
+    pub fn matches(&self, version: i32) -> bool {
+        match &self.0 {
+            Some(header) => {
+                let current = get_etag(version);
+                header.split(',').map(str::trim).any(|tag| tag == "*" || tag == current)
+            },
+            None => true,
+        }
+    }
+}
+
+pub fn with_if_match() -> impl Filter<Extract = (IfMatch,), Error = Rejection> + Clone {
+    warp::header::optional::<String>("if-match").map(IfMatch)
+}
+
+pub fn get_etag(version: i32) -> String {
+    format!("\"{}\"", version)
+}
+
+pub fn with_etag<T: Reply>(reply: T, version: i32) -> WithHeader<T> {
+    warp::reply::with_header(reply, ETAG, get_etag(version))
+}
+
+/// Rejects with 412 and the current representation, so the client can merge its change and retry.
+pub fn precondition_failed<T: Serialize>(current: &T, version: i32) -> Rejection {
+    let current = serde_json::to_value(current).unwrap_or_default();
+    warp::reject::custom(HttpError::PreconditionFailed(current, version))
+}
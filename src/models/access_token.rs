@@ -0,0 +1,94 @@
+use serde_derive::{Deserialize, Serialize};
+use validator::{Validate};
+use mobc_postgres::tokio_postgres::Row;
+use chrono::{DateTime, Utc};
+use std::str::FromStr;
+use uuid::Uuid;
+use crate::models::SqlQueryResponse;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Scope {
+    #[serde(rename = "lists:read")]
+    ListsRead,
+    #[serde(rename = "lists:write")]
+    ListsWrite,
+    #[serde(rename = "items:read")]
+    ItemsRead,
+    #[serde(rename = "items:write")]
+    ItemsWrite,
+}
+
+impl FromStr for Scope {
+    type Err = ();
+
+    fn from_str(input: &str) -> Result<Scope, Self::Err> {
+        match input {
+            "lists:read" => Ok(Scope::ListsRead),
+            "lists:write" => Ok(Scope::ListsWrite),
+            "items:read" => Ok(Scope::ItemsRead),
+            "items:write" => Ok(Scope::ItemsWrite),
+            _ => Err(()),
+        }
+    }
+}
+
+impl std::fmt::Display for Scope {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let value = match self {
+            Scope::ListsRead => "lists:read",
+            Scope::ListsWrite => "lists:write",
+            Scope::ItemsRead => "items:read",
+            Scope::ItemsWrite => "items:write",
+        };
+        write!(f, "{}", value)
+    }
+}
+
+pub fn parse_scopes(values: Vec<String>) -> Vec<Scope> {
+    values.iter().filter_map(|value| Scope::from_str(value).ok()).collect()
+}
+
+#[derive(Debug, Deserialize, Validate, Serialize, Clone)]
+pub struct AccessTokenDTO {
+    #[validate(length(min = 1, max = 100))]
+    pub name: String,
+    #[validate(length(min = 1))]
+    pub scopes: Vec<Scope>,
+    #[serde(rename = "expiresInDays")]
+    #[validate(range(min = 1, max = 3650))]
+    pub expires_in_days: Option<i32>,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct AccessTokenResponse {
+    pub id: Uuid,
+    pub name: String,
+    pub scopes: Vec<Scope>,
+    #[serde(rename = "createdAt")]
+    pub created_at: DateTime<Utc>,
+    #[serde(rename = "lastUsedAt")]
+    pub last_used_at: Option<DateTime<Utc>>,
+    #[serde(rename = "expiresAt")]
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+impl SqlQueryResponse for AccessTokenResponse {
+    fn from_row(row: &Row) -> Self {
+        Self {
+            id: row.get("id"),
+            name: row.get("name"),
+            scopes: parse_scopes(row.get("scopes")),
+            created_at: row.get("created_at"),
+            last_used_at: row.get("last_used_at"),
+            expires_at: row.get("expires_at"),
+        }
+    }
+}
+
+/// Returned only once on creation, afterwards just the hash of the token is known.
+#[derive(Debug, Serialize, Clone)]
+pub struct CreatedAccessTokenResponse {
+    #[serde(flatten)]
+    pub access_token: AccessTokenResponse,
+    pub token: String,
+}
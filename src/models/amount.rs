@@ -0,0 +1,179 @@
+use rust_decimal::Decimal;
+use rust_decimal::prelude::ToPrimitive;
+use serde::{Deserialize, Deserializer, Serializer};
+use serde::de::{self, Visitor};
+use std::fmt;
+use std::str::FromStr;
+use validator::ValidationError;
+
+const MAX_AMOUNT: u32 = 5000;
+
+/// Reads an amount given as a number, a decimal string or a fraction like "3/4" or "1 1/2".
+pub fn parse_amount(input: &str) -> Option<Decimal> {
+    let parts: Vec<&str> = input.split_whitespace().collect();
+    match parts.as_slice() {
+        [value] if value.contains('/') => parse_fraction(value),
+        [value] => Decimal::from_str(value).ok(),
+        // a mixed number, "1 -1/2" doesn't mean anything
+        [whole, fraction] if fraction.contains('/') => {
+            let whole = Decimal::from_str(whole).ok().filter(|whole| whole.fract().is_zero())?;
+            let fraction = parse_fraction(fraction).filter(|fraction| !fraction.is_sign_negative())?;
+            whole.checked_add(fraction)
+        },
+        _ => None,
+    }
+}
+
+fn parse_fraction(input: &str) -> Option<Decimal> {
+    let (numerator, denominator) = input.split_once('/')?;
+    let numerator = Decimal::from_str(numerator).ok()?;
+    let denominator = Decimal::from_str(denominator).ok()?;
+    numerator.checked_div(denominator)
+}
+
+pub fn validate_amount(amount: &Decimal) -> Result<(), ValidationError> {
+    if amount.is_sign_negative() || *amount > Decimal::from(MAX_AMOUNT) {
+        return Err(ValidationError::new("range"));
+    }
+    Ok(())
+}
+
+struct AmountVisitor;
+
+impl<'de> Visitor<'de> for AmountVisitor {
+    type Value = Decimal;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a number, a decimal string or a fraction")
+    }
+
+    fn visit_u64<E: de::Error>(self, value: u64) -> Result<Decimal, E> {
+        Ok(Decimal::from(value))
+    }
+
+    fn visit_i64<E: de::Error>(self, value: i64) -> Result<Decimal, E> {
+        Ok(Decimal::from(value))
+    }
+
+    // the shortest representation of the float, so 0.3 stays 0.3
+    fn visit_f64<E: de::Error>(self, value: f64) -> Result<Decimal, E> {
+        Decimal::from_str(&value.to_string()).map_err(|_e| E::custom("amount is out of range"))
+    }
+
+    fn visit_str<E: de::Error>(self, value: &str) -> Result<Decimal, E> {
+        parse_amount(value).ok_or_else(|| E::custom(format!("invalid amount {}", value)))
+    }
+}
+
+/// Amounts are written as JSON numbers, exactness only matters for what is stored.
+pub fn serialize<S: Serializer>(amount: &Decimal, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_f64(amount.to_f64().unwrap_or_default())
+}
+
+pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Decimal, D::Error> {
+    deserializer.deserialize_any(AmountVisitor)
+}
+
+/// Wraps the amount so a missing one (`null`) can be told apart.
+struct Amount(Decimal);
+
+impl<'de> Deserialize<'de> for Amount {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Amount, D::Error> {
+        deserializer.deserialize_any(AmountVisitor).map(Amount)
+    }
+}
+
+pub mod option {
+    use rust_decimal::Decimal;
+    use serde::{Deserialize, Deserializer, Serializer};
+    use super::Amount;
+
+    pub fn serialize<S: Serializer>(amount: &Option<Decimal>, serializer: S) -> Result<S::Ok, S::Error> {
+        match amount {
+            Some(amount) => super::serialize(amount, serializer),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<Decimal>, D::Error> {
+        Ok(Option::<Amount>::deserialize(deserializer)?.map(|amount| amount.0))
+    }
+}
+
+/// For changes where `null` removes the amount, `None` is only a missing field. Needs
+/// `#[serde(default)]` on the field.
+pub mod nested_option {
+    use rust_decimal::Decimal;
+    use serde::{Deserialize, Deserializer, Serializer};
+    use super::Amount;
+
+    pub fn serialize<S: Serializer>(amount: &Option<Option<Decimal>>, serializer: S) -> Result<S::Ok, S::Error> {
+        super::option::serialize(&amount.flatten(), serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<Option<Decimal>>, D::Error> {
+        Ok(Some(Option::<Amount>::deserialize(deserializer)?.map(|amount| amount.0)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decimal(value: &str) -> Option<Decimal> {
+        Decimal::from_str(value).ok()
+    }
+
+    #[test]
+    fn parse_amount_reads_numbers_and_fractions() {
+        assert_eq!(parse_amount("2"), decimal("2"));
+        assert_eq!(parse_amount(" 0.3 "), decimal("0.3"));
+        assert_eq!(parse_amount("3/4"), decimal("0.75"));
+        assert_eq!(parse_amount("1 1/2"), decimal("1.5"));
+        assert_eq!(parse_amount("2 3/4"), decimal("2.75"));
+    }
+
+    #[test]
+    fn parse_amount_rejects_invalid_input() {
+        assert_eq!(parse_amount(""), None);
+        assert_eq!(parse_amount("a"), None);
+        assert_eq!(parse_amount("1/0"), None);
+        assert_eq!(parse_amount("1 1/0"), None);
+        assert_eq!(parse_amount("1 -1/2"), None);
+        assert_eq!(parse_amount("1.5 1/2"), None);
+        assert_eq!(parse_amount("1 2"), None);
+        assert_eq!(parse_amount("1 1/2 1/2"), None);
+    }
+
+    #[derive(serde_derive::Deserialize, serde_derive::Serialize)]
+    struct Change {
+        #[serde(default, with = "nested_option")]
+        price: Option<Option<Decimal>>,
+    }
+
+    #[test]
+    fn nested_option_tells_null_from_missing() {
+        let change = |json: &str| serde_json::from_str::<Change>(json).unwrap().price;
+        assert_eq!(change(r#"{"price": "3/4"}"#), Some(decimal("0.75")));
+        assert_eq!(change(r#"{"price": 1.5}"#), Some(decimal("1.5")));
+        assert_eq!(change(r#"{"price": null}"#), Some(None));
+        assert_eq!(change("{}"), None);
+        assert!(serde_json::from_str::<Change>(r#"{"price": "1/0"}"#).is_err());
+    }
+
+    #[test]
+    fn nested_option_serializes_numbers() {
+        let change = Change { price: Some(decimal("0.75")) };
+        assert_eq!(serde_json::to_string(&change).unwrap(), r#"{"price":0.75}"#);
+        let change = Change { price: Some(None) };
+        assert_eq!(serde_json::to_string(&change).unwrap(), r#"{"price":null}"#);
+    }
+
+    #[test]
+    fn parse_fraction_divides() {
+        assert_eq!(parse_fraction("1/3").map(|fraction| fraction.round_dp(3)), decimal("0.333"));
+        assert_eq!(parse_fraction("1/0"), None);
+        assert_eq!(parse_fraction("1"), None);
+        assert_eq!(parse_fraction("a/2"), None);
+    }
+}
@@ -0,0 +1,81 @@
+use serde_derive::{Deserialize, Serialize};
+use validator::{Validate};
+use mobc_postgres::tokio_postgres::Row;
+use uuid::Uuid;
+use crate::models::SqlQueryResponse;
+use crate::models::item::Item;
+
+#[derive(Debug, Deserialize, Validate, Serialize, Clone)]
+pub struct CategoryDTO {
+    #[validate(length(min = 1, max = 100))]
+    pub name: String,
+    /// Categories are sorted by it, lowest first
+    pub position: Option<i32>,
+}
+
+#[derive(Debug, Deserialize, Validate, Serialize, Clone)]
+pub struct PartialCategoryDTO {
+    #[validate(length(min = 1, max = 100))]
+    pub name: Option<String>,
+    pub position: Option<i32>,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct CategoryResponse {
+    pub id: Uuid,
+    pub name: String,
+    pub position: i32,
+}
+
+impl SqlQueryResponse for CategoryResponse {
+    fn from_row(row: &Row) -> Self {
+        Self {
+            id: row.get("id"),
+            name: row.get("name"),
+            position: row.get("position"),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Validate, Serialize, Clone)]
+pub struct StoreDTO {
+    #[validate(length(min = 1, max = 100))]
+    pub name: String,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct StoreResponse {
+    pub id: Uuid,
+    pub name: String,
+}
+
+impl SqlQueryResponse for StoreResponse {
+    fn from_row(row: &Row) -> Self {
+        Self {
+            id: row.get("id"),
+            name: row.get("name"),
+        }
+    }
+}
+
+/// Order of the categories in a store, categories left out keep their own position after these.
+#[derive(Debug, Deserialize, Validate, Serialize, Clone)]
+pub struct StoreCategoryOrderDTO {
+    #[serde(rename = "categoryIds")]
+    #[validate(length(max = 200))]
+    pub category_ids: Vec<Uuid>,
+}
+
+/// Items of one category, in the order of the category in the store.
+#[derive(Debug, Serialize)]
+pub struct ItemGroup {
+    /// `None` for the items without a category, which come last
+    pub category: Option<CategoryResponse>,
+    pub items: Vec<Item>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct GroupedItemsResponse {
+    pub groups: Vec<ItemGroup>,
+    pub total: i32,
+}
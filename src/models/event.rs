@@ -0,0 +1,53 @@
+use serde_derive::{Deserialize, Serialize};
+use uuid::Uuid;
+use crate::models::item::Item;
+use crate::models::shopping_list::ShoppingList;
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(tag = "type", rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum ListEvent {
+    ItemCreated { item: Item },
+    ItemUpdated { item: Item },
+    ItemDeleted { id: Uuid },
+    ListChanged { list: ShoppingList },
+    ListDeleted,
+    /// Sent when events were dropped for a slow client, or are no longer in the history
+    /// when resuming, so the client has to fetch the list again.
+    Resync,
+}
+
+/// Change of a shopping list as published to every server instance.
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct ListEventMessage {
+    /// Position in the event history of the list, set once the event was recorded there
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    #[serde(rename = "shoppingListId")]
+    pub shopping_list_id: Uuid,
+    /// User who made the change, so clients can skip their own changes
+    #[serde(rename = "userId")]
+    pub user_id: Option<Uuid>,
+    #[serde(flatten)]
+    pub event: ListEvent,
+}
+
+impl ListEventMessage {
+    pub fn new(shopping_list_id: Uuid, user_id: Uuid, event: ListEvent) -> Self {
+        Self {
+            id: None,
+            shopping_list_id,
+            user_id: Some(user_id),
+            event,
+        }
+    }
+
+    /// Tells the client to fetch the list again because events were lost.
+    pub fn resync(shopping_list_id: Uuid) -> Self {
+        Self {
+            id: None,
+            shopping_list_id,
+            user_id: None,
+            event: ListEvent::Resync,
+        }
+    }
+}
@@ -0,0 +1,51 @@
+use serde_derive::{Deserialize, Serialize};
+use validator::{Validate};
+use mobc_postgres::tokio_postgres::Row;
+use chrono::{DateTime, Utc};
+use std::str::FromStr;
+use uuid::Uuid;
+use crate::models::SqlQueryResponse;
+use crate::models::sharing::{is_share_role, ShareRole, DEFAULT_SHARE_ROLE};
+
+#[derive(Debug, Deserialize, Validate, Serialize, Clone)]
+pub struct HouseholdDTO {
+    #[validate(length(min = 1, max = 100))]
+    pub name: String,
+}
+
+/// Household as seen by one of its members, `role` is what the member gets on the household's lists.
+#[derive(Debug, Serialize, Clone)]
+pub struct HouseholdResponse {
+    pub id: Uuid,
+    pub name: String,
+    pub owner: Uuid,
+    pub role: ShareRole,
+    #[serde(rename = "createdAt")]
+    pub created_at: DateTime<Utc>,
+}
+
+impl SqlQueryResponse for HouseholdResponse {
+    fn from_row(row: &Row) -> Self {
+        Self {
+            id: row.get("id"),
+            name: row.get("name"),
+            owner: row.get("owner_id"),
+            role: ShareRole::from_str(row.get("role")).unwrap_or(DEFAULT_SHARE_ROLE),
+            created_at: row.get("created_at"),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Validate, Serialize, Clone)]
+pub struct HouseholdMemberDTO {
+    #[serde(rename = "targetUserId")]
+    pub target_user_id: Uuid,
+    #[validate(custom = "is_share_role")]
+    pub role: Option<ShareRole>,
+}
+
+#[derive(Debug, Deserialize, Validate, Serialize, Clone)]
+pub struct ListHouseholdDTO {
+    #[serde(rename = "householdId")]
+    pub household_id: Option<Uuid>,
+}
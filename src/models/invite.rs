@@ -0,0 +1,57 @@
+use serde_derive::{Deserialize, Serialize};
+use validator::{Validate};
+use mobc_postgres::tokio_postgres::Row;
+use chrono::{DateTime, Utc};
+use std::str::FromStr;
+use uuid::Uuid;
+use crate::models::SqlQueryResponse;
+use crate::models::sharing::{is_share_role, ShareRole, DEFAULT_SHARE_ROLE};
+
+#[derive(Debug, Deserialize, Validate, Serialize, Clone)]
+pub struct InviteDTO {
+    #[validate(custom = "is_share_role")]
+    pub role: Option<ShareRole>,
+    #[serde(rename = "expiresInHours")]
+    #[validate(range(min = 1, max = 720))]
+    pub expires_in_hours: Option<i32>,
+    #[serde(rename = "singleUse")]
+    pub single_use: Option<bool>,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct InviteResponse {
+    pub id: Uuid,
+    #[serde(rename = "shoppingListId")]
+    pub shopping_list_id: Uuid,
+    pub role: ShareRole,
+    #[serde(rename = "singleUse")]
+    pub single_use: bool,
+    #[serde(rename = "useCount")]
+    pub use_count: i32,
+    #[serde(rename = "createdAt")]
+    pub created_at: DateTime<Utc>,
+    #[serde(rename = "expiresAt")]
+    pub expires_at: DateTime<Utc>,
+}
+
+impl SqlQueryResponse for InviteResponse {
+    fn from_row(row: &Row) -> Self {
+        Self {
+            id: row.get("id"),
+            shopping_list_id: row.get("shopping_list_id"),
+            role: ShareRole::from_str(row.get("role")).unwrap_or(DEFAULT_SHARE_ROLE),
+            single_use: row.get("single_use"),
+            use_count: row.get("use_count"),
+            created_at: row.get("created_at"),
+            expires_at: row.get("expires_at"),
+        }
+    }
+}
+
+/// Returned only once on creation, afterwards just the hash of the token is known.
+#[derive(Debug, Serialize, Clone)]
+pub struct CreatedInviteResponse {
+    #[serde(flatten)]
+    pub invite: InviteResponse,
+    pub token: String,
+}
@@ -1,40 +1,157 @@
 use serde_derive::{Deserialize, Serialize};
-use validator::{Validate};
-use crate::models::unit::Unit;
+use validator::{Validate, ValidationError, ValidationErrors};
+use crate::models::unit::{Unit, UnitSystem, Dimension};
 use mobc_postgres::tokio_postgres::Row;
 use std::str::FromStr;
 use uuid::Uuid;
-use crate::models::Model;
+use crate::models::{Model, Pagination, deserialize_some};
+use chrono::{DateTime, Utc};
+use rust_decimal::Decimal;
+use crate::models::amount::validate_amount;
+use crate::models::price::{is_currency, validate_price};
 
 #[derive(Debug, Deserialize, Validate, Serialize, Clone)]
 pub struct Item {
     pub id: Option<String>,
     pub name: String,
     pub description: String,
-    #[serde(rename = "totalAmount")]
-    #[validate(range(min = 0, max = 5000))]
-    pub total_amount: f32,
-    #[serde(rename = "currentAmount")]
-    #[validate(range(min = 0, max = 5000))]
-    pub current_amount: f32,
+    /// Also accepted as a decimal string or a fraction like "1 1/2"
+    #[serde(rename = "totalAmount", with = "crate::models::amount")]
+    #[validate(custom = "validate_amount")]
+    pub total_amount: Decimal,
+    #[serde(rename = "currentAmount", with = "crate::models::amount")]
+    #[validate(custom = "validate_amount")]
+    pub current_amount: Decimal,
     pub unit: Unit,
     pub bought: bool,
     pub tags: Vec<String>,
+    #[serde(default)]
+    pub version: i32,
+    #[serde(rename = "updatedAt", default)]
+    pub updated_at: Option<DateTime<Utc>>,
+    #[serde(rename = "createdAt", default)]
+    pub created_at: Option<DateTime<Utc>>,
+    /// Rank in the manual order of the list, new items are appended
+    #[serde(default)]
+    pub position: Option<i64>,
+    /// Learned from earlier items of the same name when not given
+    #[serde(rename = "categoryId", default)]
+    pub category_id: Option<Uuid>,
+    /// Amounts in the unit system asked for, only when the item uses another one
+    #[serde(skip_deserializing, skip_serializing_if = "Option::is_none")]
+    pub converted: Option<ConvertedAmount>,
+    /// Price of one unit, e.g. of one gram
+    #[serde(rename = "unitPrice", default, with = "crate::models::amount::option")]
+    #[validate(custom = "validate_price")]
+    pub unit_price: Option<Decimal>,
+    /// The currency of the list when not given
+    #[serde(default)]
+    #[validate(custom = "is_currency")]
+    pub currency: Option<String>,
+    /// Where the item is bought, its price is recorded for that store
+    #[serde(rename = "storeId", default)]
+    pub store_id: Option<Uuid>,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct ConvertedAmount {
+    #[serde(rename = "totalAmount", serialize_with = "crate::models::amount::serialize")]
+    pub total_amount: Decimal,
+    #[serde(rename = "currentAmount", serialize_with = "crate::models::amount::serialize")]
+    pub current_amount: Decimal,
+    pub unit: Unit,
+}
+
+/// Items with the same normalized name are the same product, sizes trailing the name
+/// ("milk 1L") are ignored.
+pub fn normalize_name(name: &str) -> String {
+    let name = name.to_lowercase();
+    let words: Vec<&str> = name.split_whitespace().collect();
+    let end = words.iter()
+        .rposition(|word| !word.starts_with(|c: char| c.is_ascii_digit()))
+        .map_or(words.len(), |index| index + 1);
+    words[..end].join(" ")
+}
+
+impl Item {
+    /// Items with the same key are duplicates, bought and not bought items are never merged.
+    pub fn merge_key(&self) -> (String, Dimension, bool) {
+        (normalize_name(&self.name), self.unit.dimension(), self.bought)
+    }
+
+    /// Adds the amounts of a duplicate in the unit of this item and takes over its tags,
+    /// and its description, category, store and price when this item has none.
+    pub fn merge(&mut self, other: &Item) {
+        let unit = self.unit.clone();
+        let convert = |amount: Decimal| other.unit.convert(amount, &unit).unwrap_or_default();
+        self.total_amount += convert(other.total_amount);
+        self.current_amount += convert(other.current_amount);
+        for tag in other.tags.iter() {
+            if !self.tags.contains(tag) {
+                self.tags.push(tag.clone());
+            }
+        }
+        if self.description.is_empty() {
+            self.description = other.description.clone();
+        }
+        if self.category_id.is_none() {
+            self.category_id = other.category_id;
+        }
+        if self.store_id.is_none() {
+            self.store_id = other.store_id;
+        }
+        if self.unit_price.is_none() {
+            self.unit_price = other.unit_price.and_then(|price| other.unit.convert_price(price, &unit));
+            self.currency = other.currency.clone();
+        }
+    }
+
+    /// Fills `converted` when the unit of the item isn't one of the system, the stored amounts
+    /// stay as they were entered.
+    pub fn convert_to(&mut self, system: UnitSystem) {
+        match self.unit.system() {
+            Some(item_system) if item_system != system => {},
+            _ => return,
+        }
+        let unit = self.unit.preferred(self.total_amount, system);
+        let total_amount = self.unit.convert(self.total_amount, &unit);
+        let current_amount = self.unit.convert(self.current_amount, &unit);
+        if let (Some(total_amount), Some(current_amount)) = (total_amount, current_amount) {
+            self.converted = Some(ConvertedAmount { total_amount, current_amount, unit });
+        }
+    }
+
+    /// Keeps the amounts to the precision of the unit, e.g. whole items.
+    pub fn round_amounts(&mut self) {
+        self.total_amount = self.unit.round(self.total_amount);
+        self.current_amount = self.unit.round(self.current_amount);
+    }
 }
 
 #[derive(Debug, Deserialize, Validate, Serialize, Clone)]
 pub struct PartialItem {
     pub name: Option<String>,
     pub description: Option<String>,
-    #[serde(rename = "totalAmount")]
-    #[validate(range(min = 0, max = 5000))]
-    pub total_amount: Option<f32>,
-    #[serde(rename = "currentAmount")]
-    #[validate(range(min = 0, max = 5000))]
-    pub current_amount: Option<f32>,
+    #[serde(rename = "totalAmount", default, with = "crate::models::amount::option")]
+    #[validate(custom = "validate_amount")]
+    pub total_amount: Option<Decimal>,
+    #[serde(rename = "currentAmount", default, with = "crate::models::amount::option")]
+    #[validate(custom = "validate_amount")]
+    pub current_amount: Option<Decimal>,
     pub unit: Option<Unit>,
     pub bought: Option<bool>,
     pub tags: Option<Vec<String>>,
+    /// `null` removes the item from its category
+    #[serde(rename = "categoryId", default, deserialize_with = "deserialize_some")]
+    pub category_id: Option<Option<Uuid>>,
+    #[serde(rename = "unitPrice", default, with = "crate::models::amount::nested_option")]
+    #[validate(custom = "validate_price")]
+    pub unit_price: Option<Option<Decimal>>,
+    #[serde(default, deserialize_with = "deserialize_some")]
+    #[validate(custom = "is_currency")]
+    pub currency: Option<Option<String>>,
+    #[serde(rename = "storeId", default, deserialize_with = "deserialize_some")]
+    pub store_id: Option<Option<Uuid>>,
 }
 
 impl Model< PartialItem> for Item {
@@ -60,6 +177,19 @@ impl Model< PartialItem> for Item {
        if let Some(tags) = &updates.tags  {
            self.tags = tags.clone();
        }
+       if let Some(category_id) = &updates.category_id  {
+           self.category_id = *category_id;
+       }
+       if let Some(unit_price) = &updates.unit_price  {
+           self.unit_price = *unit_price;
+       }
+       if let Some(currency) = &updates.currency  {
+           self.currency = currency.clone();
+       }
+       if let Some(store_id) = &updates.store_id  {
+           self.store_id = *store_id;
+       }
+       self.round_amounts();
    }
 
     fn from_row(row: &Row) -> Self {
@@ -73,12 +203,181 @@ impl Model< PartialItem> for Item {
             unit: Unit::from_str(row.get(5)).unwrap(),
             bought: row.get(6),
             tags: row.get(7),
+            version: row.get("version"),
+            updated_at: row.get("updated_at"),
+            created_at: row.get("created_at"),
+            position: row.get("position"),
+            category_id: row.get("category_id"),
+            converted: None,
+            unit_price: row.get("unit_price"),
+            currency: row.get("currency"),
+            store_id: row.get("store_id"),
         }
     }
 }
 
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub enum ItemSort {
+    NAME,
+    UNIT,
+    CREATED,
+    /// Order the user arranged the items in
+    POSITION,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub enum SortOrder {
+    ASC,
+    DESC,
+}
+
+#[derive(Debug, Deserialize, Validate, Clone)]
+pub struct ItemQuery {
+    #[validate(range(min = 1, max = 2000))]
+    pub limit: Option<i32>,
+    #[validate(range(min = 1))]
+    pub page: Option<i32>,
+    pub bought: Option<bool>,
+    /// Comma separated, items with any of them match
+    pub tags: Option<String>,
+    /// Part of the name or description
+    #[validate(length(min = 1, max = 100))]
+    pub search: Option<String>,
+    pub sort: Option<ItemSort>,
+    pub order: Option<SortOrder>,
+    /// Groups the items by category, in the category order of `storeId` when given
+    pub grouped: Option<bool>,
+    #[serde(rename = "storeId")]
+    pub store_id: Option<Uuid>,
+    /// Adds the amounts converted to this system to the items
+    #[serde(rename = "unitSystem")]
+    pub unit_system: Option<UnitSystem>,
+}
+
+impl ItemQuery {
+    pub fn get_pagination(&self) -> Pagination {
+        Pagination {
+            limit: self.limit,
+            page: self.page,
+        }
+    }
+
+    pub fn get_tags(&self) -> Vec<String> {
+        self.tags.as_deref()
+            .unwrap_or_default()
+            .split(',')
+            .map(str::trim)
+            .filter(|tag| !tag.is_empty())
+            .map(String::from)
+            .collect()
+    }
+}
+
+/// Moves an item right before or right after another item of the same list.
+#[derive(Debug, Deserialize, Validate, Clone)]
+#[validate(schema(function = "is_one_anchor"))]
+pub struct ItemPositionDTO {
+    pub before: Option<Uuid>,
+    pub after: Option<Uuid>,
+}
+
+fn is_one_anchor(position: &ItemPositionDTO) -> Result<(), ValidationError> {
+    match (position.before, position.after) {
+        (Some(_), None) | (None, Some(_)) => Ok(()),
+        _ => Err(ValidationError::new("Either before or after is required")),
+    }
+}
+
+#[derive(Debug, Deserialize, Validate, Clone)]
+pub struct CreateItemsQuery {
+    /// Adds either all items or none of them
+    pub atomic: Option<bool>,
+    /// Merges the new items into items of the list they duplicate
+    pub merge: Option<bool>,
+}
+
 #[derive(Debug, Serialize)]
 pub struct PartialUpdateResponse {
     pub items: Vec<Item>,
-    pub errors: Vec<String>,
-}
\ No newline at end of file
+    pub errors: Vec<ItemError>,
+    /// Items of the list that were merged into the returned ones
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub merged: Vec<Uuid>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ItemError {
+    /// Position of the item in the request
+    pub index: usize,
+    pub message: String,
+}
+
+/// One change of a batch, applied only when the whole batch succeeds.
+#[derive(Debug, Deserialize, Clone)]
+#[serde(tag = "type", rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum ItemOperation {
+    Update {
+        #[serde(rename = "itemId")]
+        item_id: Uuid,
+        #[serde(rename = "baseVersion")]
+        base_version: Option<i32>,
+        changes: PartialItem,
+    },
+    Delete {
+        #[serde(rename = "itemId")]
+        item_id: Uuid,
+        #[serde(rename = "baseVersion")]
+        base_version: Option<i32>,
+    },
+    /// Moves the item to another list the user can edit
+    Move {
+        #[serde(rename = "itemId")]
+        item_id: Uuid,
+        #[serde(rename = "baseVersion")]
+        base_version: Option<i32>,
+        #[serde(rename = "targetListId")]
+        target_list_id: Uuid,
+    },
+}
+
+impl ItemOperation {
+    pub fn item_id(&self) -> &Uuid {
+        match self {
+            ItemOperation::Update { item_id, .. } => item_id,
+            ItemOperation::Delete { item_id, .. } => item_id,
+            ItemOperation::Move { item_id, .. } => item_id,
+        }
+    }
+
+    pub fn base_version(&self) -> Option<i32> {
+        match self {
+            ItemOperation::Update { base_version, .. } => *base_version,
+            ItemOperation::Delete { base_version, .. } => *base_version,
+            ItemOperation::Move { base_version, .. } => *base_version,
+        }
+    }
+}
+
+impl Validate for ItemOperation {
+    fn validate(&self) -> Result<(), ValidationErrors> {
+        match self {
+            ItemOperation::Update { changes, .. } => changes.validate(),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Default)]
+pub struct BatchItemsResponse {
+    pub updated: Vec<Item>,
+    pub moved: Vec<MovedItem>,
+    pub deleted: Vec<Uuid>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct MovedItem {
+    #[serde(rename = "shoppingListId")]
+    pub shopping_list_id: Uuid,
+    #[serde(flatten)]
+    pub item: Item,
+}
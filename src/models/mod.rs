@@ -9,9 +9,21 @@ pub mod item;
 pub mod unit;
 pub mod user;
 pub mod sharing;
+pub mod two_factor;
+pub mod access_token;
+pub mod invite;
+pub mod household;
+pub mod event;
+pub mod sync;
+pub mod category;
+pub mod amount;
+pub mod price;
 
 use serde_derive::{Deserialize};
 use crate::services::database::{DBPool, RedisPool};
+use crate::services::keys::KeyStore;
+use crate::services::notifier::SharedNotifier;
+use crate::services::events::ListEvents;
 
 pub fn is_uuid(value: &str) -> Result<(), ValidationError> {
     match Uuid::parse_str(value) {
@@ -20,9 +32,22 @@ pub fn is_uuid(value: &str) -> Result<(), ValidationError> {
     }
 }
 
+/// For optional fields that may be set to null, a missing field stays `None`
+/// while null becomes `Some(None)`.
+pub fn deserialize_some<'de, T, D>(deserializer: D) -> Result<Option<T>, D::Error>
+    where
+        T: serde::Deserialize<'de>,
+        D: serde::Deserializer<'de>,
+{
+    T::deserialize(deserializer).map(Some)
+}
+
 pub struct GlobalContext {
     pub pg_pool: DBPool,
     pub redis_pool: RedisPool,
+    pub keys: KeyStore,
+    pub notifier: SharedNotifier,
+    pub events: ListEvents,
 }
 
 #[derive(Debug, Serialize, Clone)]
@@ -0,0 +1,102 @@
+use serde_derive::Serialize;
+use rust_decimal::Decimal;
+use chrono::{DateTime, Utc};
+use uuid::Uuid;
+use crate::models::unit::Unit;
+use mobc_postgres::tokio_postgres::Row;
+use validator::ValidationError;
+use crate::models::SqlQueryResponse;
+
+const MAX_PRICE: u32 = 1_000_000;
+
+/// ISO 4217 code, e.g. EUR.
+pub fn is_currency(currency: &str) -> Result<(), ValidationError> {
+    if currency.len() != 3 || !currency.chars().all(|c| c.is_ascii_uppercase()) {
+        return Err(ValidationError::new("currency"));
+    }
+    Ok(())
+}
+
+pub fn validate_price(price: &Decimal) -> Result<(), ValidationError> {
+    if price.is_sign_negative() || *price > Decimal::from(MAX_PRICE) {
+        return Err(ValidationError::new("range"));
+    }
+    Ok(())
+}
+
+/// What the items of a list are estimated to cost, from their unit prices.
+#[derive(Debug, Serialize, Clone, Default)]
+pub struct ListEstimate {
+    /// One per currency, items without one are in the currency of the list
+    pub totals: Vec<CurrencyTotal>,
+    /// Items without a price, the totals are missing their cost
+    #[serde(rename = "unpricedItems")]
+    pub unpriced_items: i64,
+    /// Set when the total in the currency of the list is above its budget
+    #[serde(rename = "overBudget")]
+    pub over_budget: bool,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct CurrencyTotal {
+    pub currency: Option<String>,
+    #[serde(serialize_with = "crate::models::amount::serialize")]
+    pub estimated: Decimal,
+    /// Cost of what was bought, including the bought part of items not bought completely
+    #[serde(serialize_with = "crate::models::amount::serialize")]
+    pub bought: Decimal,
+    #[serde(serialize_with = "crate::models::amount::serialize")]
+    pub remaining: Decimal,
+}
+
+impl SqlQueryResponse for CurrencyTotal {
+    fn from_row(row: &Row) -> Self {
+        let estimated: Decimal = row.get("estimated");
+        let bought: Decimal = row.get("bought");
+        Self {
+            currency: row.get("currency"),
+            estimated,
+            bought,
+            remaining: estimated - bought,
+        }
+    }
+}
+
+/// A price paid for a product, converted to the unit of the latest one.
+#[derive(Debug, Serialize, Clone)]
+pub struct PricePoint {
+    #[serde(rename = "unitPrice", serialize_with = "crate::models::amount::serialize")]
+    pub unit_price: Decimal,
+    pub currency: Option<String>,
+    #[serde(rename = "storeId")]
+    pub store_id: Option<Uuid>,
+    #[serde(rename = "storeName")]
+    pub store_name: Option<String>,
+    #[serde(rename = "recordedAt")]
+    pub recorded_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Copy, Serialize)]
+pub enum PriceTrend {
+    UP,
+    DOWN,
+    STABLE,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ProductPricesResponse {
+    pub name: String,
+    /// Unit and currency all prices are given in, prices in another currency are left out
+    pub unit: Option<Unit>,
+    pub currency: Option<String>,
+    /// Newest first
+    pub prices: Vec<PricePoint>,
+    /// From the oldest to the latest price
+    pub trend: Option<PriceTrend>,
+    /// Change from the oldest to the latest price in percent
+    #[serde(rename = "changePercent", serialize_with = "crate::models::amount::option::serialize")]
+    pub change_percent: Option<Decimal>,
+    /// Store with the lowest latest price
+    #[serde(rename = "cheapestStore")]
+    pub cheapest_store: Option<PricePoint>,
+}
@@ -1,9 +1,78 @@
 use serde_derive::{Deserialize, Serialize};
-use validator::{Validate};
+use validator::{Validate, ValidationError};
+use mobc_postgres::tokio_postgres::Row;
+use std::str::FromStr;
 use uuid::Uuid;
+use crate::models::SqlQueryResponse;
+
+/// What a user may do with a shopping list, every role includes the permissions of the ones before it.
+/// `OWNER` can't be granted through sharing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum ShareRole {
+    VIEWER,
+    EDITOR,
+    MANAGER,
+    OWNER,
+}
+
+/// Role of shares that don't specify one, also what shares created before roles existed got.
+pub const DEFAULT_SHARE_ROLE: ShareRole = ShareRole::EDITOR;
+
+impl FromStr for ShareRole {
+    type Err = ();
+
+    fn from_str(input: &str) -> Result<ShareRole, Self::Err> {
+        match input {
+            "VIEWER" => Ok(ShareRole::VIEWER),
+            "EDITOR" => Ok(ShareRole::EDITOR),
+            "MANAGER" => Ok(ShareRole::MANAGER),
+            "OWNER" => Ok(ShareRole::OWNER),
+            _ => Err(()),
+        }
+    }
+}
+
+impl std::fmt::Display for ShareRole {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let value = match self {
+            ShareRole::VIEWER => "VIEWER",
+            ShareRole::EDITOR => "EDITOR",
+            ShareRole::MANAGER => "MANAGER",
+            ShareRole::OWNER => "OWNER",
+        };
+        write!(f, "{}", value)
+    }
+}
+
+pub fn is_share_role(role: &ShareRole) -> Result<(), ValidationError> {
+    match role {
+        ShareRole::OWNER => Err(ValidationError::new("Owner role can't be shared")),
+        _ => Ok(()),
+    }
+}
 
 #[derive(Debug, Deserialize, Validate, Serialize, Clone)]
 pub struct ShareListBody {
     #[serde(rename = "targetUserId")]
     pub target_user_id: Uuid,
-}
\ No newline at end of file
+    #[validate(custom = "is_share_role")]
+    pub role: Option<ShareRole>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct SharedUserResponse {
+    pub id: Uuid,
+    pub username: String,
+    pub role: ShareRole,
+}
+
+impl SqlQueryResponse for SharedUserResponse {
+    fn from_row(row: &Row) -> Self {
+        let id: Uuid = row.get("id");
+        Self {
+            id,
+            username: row.get("username"),
+            role: ShareRole::from_str(row.get("role")).unwrap_or(DEFAULT_SHARE_ROLE),
+        }
+    }
+}
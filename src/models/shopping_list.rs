@@ -1,8 +1,11 @@
 use serde_derive::{Deserialize, Serialize};
 use validator::{Validate};
 use mobc_postgres::tokio_postgres::Row;
-use crate::models::{is_uuid, Model};
+use crate::models::{is_uuid, Model, deserialize_some};
+use crate::models::price::{ListEstimate, is_currency, validate_price};
 use uuid::Uuid;
+use chrono::{DateTime, Utc};
+use rust_decimal::Decimal;
 
 #[derive(Debug, Deserialize, Validate, Serialize, Clone)]
 pub struct ShoppingList {
@@ -11,18 +14,51 @@ pub struct ShoppingList {
     pub description: String,
     #[validate(custom = "is_uuid")]
     pub owner: String,
+    pub household: Option<String>,
+    #[serde(default)]
+    pub version: i32,
+    #[serde(rename = "updatedAt", default)]
+    pub updated_at: Option<DateTime<Utc>>,
+    #[serde(default, with = "crate::models::amount::option")]
+    pub budget: Option<Decimal>,
+    #[serde(default)]
+    pub currency: Option<String>,
+    /// Cost of the items, only with the list itself and the lists of the user
+    #[serde(skip_deserializing, skip_serializing_if = "Option::is_none")]
+    pub estimate: Option<ListEstimate>,
+}
+
+impl ShoppingList {
+    pub fn get_id(&self) -> Option<Uuid> {
+        self.id.as_deref().and_then(|id| Uuid::parse_str(id).ok())
+    }
 }
 
 #[derive(Debug, Deserialize, Validate, Serialize, Clone)]
 pub struct PartialShoppingListDTO {
     pub title: String,
     pub description: String,
+    #[serde(rename = "householdId")]
+    pub household_id: Option<Uuid>,
+    /// In the currency of the list
+    #[serde(default, with = "crate::models::amount::option")]
+    #[validate(custom = "validate_price")]
+    pub budget: Option<Decimal>,
+    #[validate(custom = "is_currency")]
+    pub currency: Option<String>,
 }
 
 #[derive(Debug, Deserialize, Validate, Serialize, Clone)]
 pub struct PartialShoppingList {
     pub title: Option<String>,
     pub description: Option<String>,
+    /// `null` removes the budget
+    #[serde(default, with = "crate::models::amount::nested_option")]
+    #[validate(custom = "validate_price")]
+    pub budget: Option<Option<Decimal>>,
+    #[serde(default, deserialize_with = "deserialize_some")]
+    #[validate(custom = "is_currency")]
+    pub currency: Option<Option<String>>,
 }
 
 impl Model<PartialShoppingList> for ShoppingList {
@@ -33,16 +69,29 @@ impl Model<PartialShoppingList> for ShoppingList {
         if let Some(description) = &changes.description {
             self.description = String::from(description);
         }
+        if let Some(budget) = &changes.budget {
+            self.budget = *budget;
+        }
+        if let Some(currency) = &changes.currency {
+            self.currency = currency.clone();
+        }
     }
 
     fn from_row(row: &Row) -> Self {
         let id: Uuid = row.get("id");
         let owner_id: Uuid = row.get("owner_id");
+        let household_id: Option<Uuid> = row.get("household_id");
         ShoppingList {
             id: Some(id.to_string()),
             title: row.get("title"),
             description: row.get("description"),
             owner: owner_id.to_string(),
+            household: household_id.map(|id| id.to_string()),
+            version: row.get("version"),
+            updated_at: row.get("updated_at"),
+            budget: row.get("budget"),
+            currency: row.get("currency"),
+            estimate: None,
         }
     }
 }
\ No newline at end of file
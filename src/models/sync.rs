@@ -0,0 +1,211 @@
+use serde_derive::{Deserialize, Serialize};
+use validator::{Validate, ValidationErrors};
+use mobc_postgres::tokio_postgres::Row;
+use chrono::{DateTime, Utc};
+use std::str::FromStr;
+use uuid::Uuid;
+use crate::models::SqlQueryResponse;
+use crate::models::item::{Item, PartialItem};
+use crate::models::sharing::SharedUserResponse;
+use crate::models::shopping_list::{ShoppingList, PartialShoppingList, PartialShoppingListDTO};
+
+#[derive(Debug, Deserialize, Validate, Clone)]
+pub struct SyncQuery {
+    /// Cursor of the previous sync, everything visible is returned without it
+    pub since: Option<String>,
+}
+
+/// Position in the change history, clients pass it back as `since` on their next sync.
+/// It is the oldest transaction still running when the sync read its snapshot, rows written
+/// by it or later ones are sent again next time, so a change committed after the sync isn't
+/// skipped even when it took its change_id before.
+/// It also records when it was issued, tombstones older than the retention are gone
+/// so an old cursor can't tell about every delete anymore.
+#[derive(Debug, Clone, Copy)]
+pub struct SyncCursor {
+    pub xmin: i64,
+    pub issued_at: i64,
+}
+
+// cursors of change ids issued before don't have the prefix, so they are reset
+const CURSOR_PREFIX: char = 'x';
+
+impl FromStr for SyncCursor {
+    type Err = ();
+
+    fn from_str(input: &str) -> Result<SyncCursor, Self::Err> {
+        let (xmin, issued_at) = input.strip_prefix(CURSOR_PREFIX).and_then(|cursor| cursor.split_once('.')).ok_or(())?;
+        Ok(SyncCursor {
+            xmin: xmin.parse().map_err(|_e| ())?,
+            issued_at: issued_at.parse().map_err(|_e| ())?,
+        })
+    }
+}
+
+impl std::fmt::Display for SyncCursor {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}{}.{}", CURSOR_PREFIX, self.xmin, self.issued_at)
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct SyncResponse {
+    pub cursor: String,
+    /// Set when the cursor was missing or expired, the client replaces everything it has
+    pub reset: bool,
+    /// Every list the user can still access, lists the client has beyond those were lost
+    #[serde(rename = "listIds")]
+    pub list_ids: Vec<Uuid>,
+    pub lists: Vec<ShoppingList>,
+    pub items: Vec<SyncItem>,
+    /// Shares of the lists the user manages
+    pub shares: Vec<SyncShare>,
+    pub deleted: Vec<Tombstone>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SyncItem {
+    #[serde(rename = "shoppingListId")]
+    pub shopping_list_id: Uuid,
+    #[serde(flatten)]
+    pub item: Item,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SyncShare {
+    #[serde(rename = "shoppingListId")]
+    pub shopping_list_id: Uuid,
+    #[serde(flatten)]
+    pub user: SharedUserResponse,
+}
+
+impl SqlQueryResponse for SyncShare {
+    fn from_row(row: &Row) -> Self {
+        Self {
+            shopping_list_id: row.get("shopping_list_id"),
+            user: SharedUserResponse::from_row(row),
+        }
+    }
+}
+
+/// A deleted list, item or share, for shares the id is the one of the user it was shared with.
+/// Items moved to another list leave one in their old list.
+#[derive(Debug, Serialize)]
+pub struct Tombstone {
+    #[serde(rename = "type")]
+    pub entity_type: String,
+    pub id: Uuid,
+    #[serde(rename = "shoppingListId")]
+    pub shopping_list_id: Uuid,
+    #[serde(rename = "deletedAt")]
+    pub deleted_at: DateTime<Utc>,
+}
+
+impl SqlQueryResponse for Tombstone {
+    fn from_row(row: &Row) -> Self {
+        Self {
+            entity_type: row.get("entity_type"),
+            id: row.get("entity_id"),
+            shopping_list_id: row.get("shopping_list_id"),
+            deleted_at: row.get("deleted_at"),
+        }
+    }
+}
+
+/// A change made while offline. New lists and items carry the id the client gave them,
+/// so applying the same mutation again doesn't create them twice.
+#[derive(Debug, Deserialize, Clone)]
+#[serde(tag = "type", rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum SyncMutation {
+    CreateList {
+        #[serde(rename = "shoppingListId")]
+        shopping_list_id: Uuid,
+        list: PartialShoppingListDTO,
+    },
+    UpdateList {
+        #[serde(rename = "shoppingListId")]
+        shopping_list_id: Uuid,
+        #[serde(rename = "baseVersion")]
+        base_version: Option<i32>,
+        changes: PartialShoppingList,
+    },
+    DeleteList {
+        #[serde(rename = "shoppingListId")]
+        shopping_list_id: Uuid,
+        #[serde(rename = "baseVersion")]
+        base_version: Option<i32>,
+    },
+    CreateItem {
+        #[serde(rename = "shoppingListId")]
+        shopping_list_id: Uuid,
+        #[serde(rename = "itemId")]
+        item_id: Uuid,
+        item: Item,
+    },
+    UpdateItem {
+        #[serde(rename = "shoppingListId")]
+        shopping_list_id: Uuid,
+        #[serde(rename = "itemId")]
+        item_id: Uuid,
+        #[serde(rename = "baseVersion")]
+        base_version: Option<i32>,
+        changes: PartialItem,
+    },
+    DeleteItem {
+        #[serde(rename = "shoppingListId")]
+        shopping_list_id: Uuid,
+        #[serde(rename = "itemId")]
+        item_id: Uuid,
+        #[serde(rename = "baseVersion")]
+        base_version: Option<i32>,
+    },
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct SyncMutationDTO {
+    /// Chosen by the client to match the results to its queued mutations
+    pub id: String,
+    #[serde(flatten)]
+    pub mutation: SyncMutation,
+}
+
+impl Validate for SyncMutationDTO {
+    fn validate(&self) -> Result<(), ValidationErrors> {
+        match &self.mutation {
+            SyncMutation::CreateList { list, .. } => list.validate(),
+            SyncMutation::UpdateList { changes, .. } => changes.validate(),
+            SyncMutation::CreateItem { item, .. } => item.validate(),
+            SyncMutation::UpdateItem { changes, .. } => changes.validate(),
+            SyncMutation::DeleteList { .. } | SyncMutation::DeleteItem { .. } => Ok(()),
+        }
+    }
+}
+
+/// Outcome of one mutation, the batch goes on when one of them fails.
+#[derive(Debug, Serialize)]
+pub struct SyncMutationResult {
+    pub id: String,
+    pub status: u16,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub list: Option<ShoppingList>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub item: Option<Item>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+    /// Current state of a list or item that was changed since the base version
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub current: Option<serde_json::Value>,
+}
+
+impl SyncMutationResult {
+    pub fn new(id: &str, status: u16) -> Self {
+        Self {
+            id: String::from(id),
+            status,
+            list: None,
+            item: None,
+            message: None,
+            current: None,
+        }
+    }
+}
@@ -0,0 +1,39 @@
+use serde_derive::{Deserialize, Serialize};
+use validator::{Validate};
+use uuid::Uuid;
+
+#[derive(Debug, Serialize, Clone)]
+pub struct TwoFactorEnrollmentResponse {
+    pub secret: String,
+    #[serde(rename = "otpauthUri")]
+    pub otpauth_uri: String,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct RecoveryCodesResponse {
+    #[serde(rename = "recoveryCodes")]
+    pub recovery_codes: Vec<String>,
+}
+
+#[derive(Debug, Deserialize, Validate, Serialize, Clone)]
+pub struct TwoFactorCodeDTO {
+    #[validate(length(min = 1))]
+    pub code: String,
+}
+
+#[derive(Debug, Deserialize, Validate, Serialize, Clone)]
+pub struct TwoFactorLoginDTO {
+    #[validate(length(min = 1))]
+    pub challenge: String,
+    /// Either a code from the authenticator app or one of the recovery codes
+    #[validate(length(min = 1))]
+    pub code: String,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct TwoFactorChallengeResponse {
+    pub id: Uuid,
+    #[serde(rename = "twoFactorRequired")]
+    pub two_factor_required: bool,
+    pub challenge: String,
+}
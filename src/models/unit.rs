@@ -1,7 +1,9 @@
 use serde_derive::{Deserialize, Serialize};
 use std::str::{FromStr};
+use rust_decimal::{Decimal, RoundingStrategy};
+use rust_decimal::prelude::{FromPrimitive, ToPrimitive};
 
-#[derive(Debug,Clone,Serialize,Deserialize)]
+#[derive(Debug,Clone,PartialEq,Serialize,Deserialize)]
 pub enum Unit {
     ITEM,
     TEASPOON,
@@ -84,3 +86,186 @@ impl ToString for Unit {
         }
     }
 }
+
+/// What a unit measures, amounts only convert between units of the same dimension.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Dimension {
+    COUNT,
+    VOLUME,
+    MASS,
+    LENGTH,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum UnitSystem {
+    METRIC,
+    /// US customary units
+    IMPERIAL,
+}
+
+// units amounts are converted to in each system, smallest first
+const METRIC_VOLUME: [Unit; 2] = [Unit::MILLILITER, Unit::LITER];
+const METRIC_MASS: [Unit; 3] = [Unit::MILLIGRAM, Unit::GRAM, Unit::KILOGRAM];
+const METRIC_LENGTH: [Unit; 3] = [Unit::MILLIMETER, Unit::CENTIMETER, Unit::METER];
+const IMPERIAL_VOLUME: [Unit; 5] = [Unit::TEASPOON, Unit::TABLESPOON, Unit::CUP, Unit::QUART, Unit::GALLON];
+const IMPERIAL_MASS: [Unit; 2] = [Unit::OUNCE, Unit::POUND];
+const IMPERIAL_LENGTH: [Unit; 1] = [Unit::INCH];
+
+impl Unit {
+    pub fn dimension(&self) -> Dimension {
+        match self {
+            Unit::ITEM => Dimension::COUNT,
+            Unit::TEASPOON | Unit::TABLESPOON | Unit::FLUID | Unit::GILL | Unit::CUP | Unit::PINT
+            | Unit::QUART | Unit::GALLON | Unit::MILLILITER | Unit::LITER | Unit::DECILITER => Dimension::VOLUME,
+            Unit::POUND | Unit::OUNCE | Unit::MILLIGRAM | Unit::GRAM | Unit::KILOGRAM => Dimension::MASS,
+            Unit::MILLIMETER | Unit::CENTIMETER | Unit::METER | Unit::INCH => Dimension::LENGTH,
+        }
+    }
+
+    /// `None` for ITEM, which belongs to every system.
+    pub fn system(&self) -> Option<UnitSystem> {
+        match self {
+            Unit::ITEM => None,
+            Unit::MILLILITER | Unit::LITER | Unit::DECILITER | Unit::MILLIGRAM | Unit::GRAM
+            | Unit::KILOGRAM | Unit::MILLIMETER | Unit::CENTIMETER | Unit::METER => Some(UnitSystem::METRIC),
+            _ => Some(UnitSystem::IMPERIAL),
+        }
+    }
+
+    /// Size of the unit in milliliters, grams or millimeters.
+    fn base_factor(&self) -> f64 {
+        match self {
+            Unit::ITEM => 1.0,
+            Unit::TEASPOON => 4.928_921_593_75,
+            Unit::TABLESPOON => 14.786_764_781_25,
+            Unit::FLUID => 29.573_529_562_5,
+            Unit::GILL => 118.294_118_25,
+            Unit::CUP => 236.588_236_5,
+            Unit::PINT => 473.176_473,
+            Unit::QUART => 946.352_946,
+            Unit::GALLON => 3_785.411_784,
+            Unit::MILLILITER => 1.0,
+            Unit::LITER => 1_000.0,
+            Unit::DECILITER => 100.0,
+            Unit::POUND => 453.592_37,
+            Unit::OUNCE => 28.349_523_125,
+            Unit::MILLIGRAM => 0.001,
+            Unit::GRAM => 1.0,
+            Unit::KILOGRAM => 1_000.0,
+            Unit::MILLIMETER => 1.0,
+            Unit::CENTIMETER => 10.0,
+            Unit::METER => 1_000.0,
+            Unit::INCH => 25.4,
+        }
+    }
+
+    /// Decimal places amounts of the unit are kept with, whole items and grams to a tenth.
+    pub fn precision(&self) -> u32 {
+        match self {
+            Unit::ITEM | Unit::MILLIGRAM | Unit::MILLILITER | Unit::MILLIMETER => 0,
+            Unit::GRAM | Unit::CENTIMETER => 1,
+            Unit::DECILITER | Unit::INCH => 2,
+            _ => 3,
+        }
+    }
+
+    pub fn round(&self, amount: Decimal) -> Decimal {
+        amount.round_dp_with_strategy(self.precision(), RoundingStrategy::MidpointAwayFromZero).normalize()
+    }
+
+    pub fn is_convertible_to(&self, target: &Unit) -> bool {
+        self.dimension() == target.dimension()
+    }
+
+    /// The amount of this unit in the target unit, `None` when they measure different things.
+    pub fn convert(&self, amount: Decimal, target: &Unit) -> Option<Decimal> {
+        if self == target {
+            return Some(amount);
+        }
+        if !self.is_convertible_to(target) {
+            return None;
+        }
+        let converted = amount.to_f64()? * self.base_factor() / target.base_factor();
+        Decimal::from_f64(converted).map(|converted| target.round(converted))
+    }
+
+    /// Price of one of this unit as the price of one target unit, kept to 4 decimal places
+    /// like the prices stored.
+    pub fn convert_price(&self, price: Decimal, target: &Unit) -> Option<Decimal> {
+        if self == target {
+            return Some(price);
+        }
+        if !self.is_convertible_to(target) {
+            return None;
+        }
+        let converted = price.to_f64()? * target.base_factor() / self.base_factor();
+        Decimal::from_f64(converted).map(|converted| converted.round_dp(4))
+    }
+
+    /// The largest unit of the system the amount is at least one of, e.g. 1500 g is 1.5 kg
+    /// but 500 g stay grams.
+    pub fn preferred(&self, amount: Decimal, system: UnitSystem) -> Unit {
+        let units: &[Unit] = match (system, self.dimension()) {
+            (_, Dimension::COUNT) => return Unit::ITEM,
+            (UnitSystem::METRIC, Dimension::VOLUME) => &METRIC_VOLUME,
+            (UnitSystem::METRIC, Dimension::MASS) => &METRIC_MASS,
+            (UnitSystem::METRIC, Dimension::LENGTH) => &METRIC_LENGTH,
+            (UnitSystem::IMPERIAL, Dimension::VOLUME) => &IMPERIAL_VOLUME,
+            (UnitSystem::IMPERIAL, Dimension::MASS) => &IMPERIAL_MASS,
+            (UnitSystem::IMPERIAL, Dimension::LENGTH) => &IMPERIAL_LENGTH,
+        };
+        let base = amount.to_f64().unwrap_or_default() * self.base_factor();
+        units.iter()
+            .rev()
+            .find(|unit| base >= unit.base_factor())
+            .unwrap_or(&units[0])
+            .clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decimal(value: &str) -> Decimal {
+        Decimal::from_str(value).unwrap()
+    }
+
+    #[test]
+    fn precision_depends_on_unit() {
+        assert_eq!(Unit::ITEM.precision(), 0);
+        assert_eq!(Unit::GRAM.precision(), 1);
+        assert_eq!(Unit::DECILITER.precision(), 2);
+        assert_eq!(Unit::KILOGRAM.precision(), 3);
+    }
+
+    #[test]
+    fn round_keeps_precision_of_unit() {
+        assert_eq!(Unit::ITEM.round(decimal("2.5")), decimal("3"));
+        assert_eq!(Unit::ITEM.round(decimal("2.4")), decimal("2"));
+        assert_eq!(Unit::GRAM.round(decimal("12.25")), decimal("12.3"));
+        assert_eq!(Unit::GRAM.round(decimal("12.24")), decimal("12.2"));
+        assert_eq!(Unit::KILOGRAM.round(decimal("1.23456")), decimal("1.235"));
+        assert_eq!(Unit::GRAM.round(decimal("12.000")).to_string(), "12");
+    }
+
+    #[test]
+    fn convert_between_units_of_a_dimension() {
+        assert_eq!(Unit::CUP.convert(decimal("1"), &Unit::MILLILITER), Some(decimal("237")));
+        assert_eq!(Unit::POUND.convert(decimal("1"), &Unit::GRAM), Some(decimal("453.6")));
+        assert_eq!(Unit::GRAM.convert(decimal("1500"), &Unit::KILOGRAM), Some(decimal("1.5")));
+        assert_eq!(Unit::GRAM.convert(decimal("12.25"), &Unit::GRAM), Some(decimal("12.25")));
+        assert_eq!(Unit::GRAM.convert(decimal("1"), &Unit::MILLILITER), None);
+        assert_eq!(Unit::ITEM.convert(decimal("1"), &Unit::GRAM), None);
+    }
+
+    #[test]
+    fn preferred_is_largest_unit_of_at_least_one() {
+        assert_eq!(Unit::GRAM.preferred(decimal("1500"), UnitSystem::METRIC), Unit::KILOGRAM);
+        assert_eq!(Unit::GRAM.preferred(decimal("500"), UnitSystem::METRIC), Unit::GRAM);
+        assert_eq!(Unit::KILOGRAM.preferred(decimal("0.5"), UnitSystem::METRIC), Unit::GRAM);
+        assert_eq!(Unit::GRAM.preferred(decimal("1000"), UnitSystem::IMPERIAL), Unit::POUND);
+        assert_eq!(Unit::GRAM.preferred(decimal("1"), UnitSystem::IMPERIAL), Unit::OUNCE);
+        assert_eq!(Unit::ITEM.preferred(decimal("3"), UnitSystem::IMPERIAL), Unit::ITEM);
+    }
+}
@@ -18,6 +18,36 @@ pub struct LoginDTO {
     pub password: String,
 }
 
+#[derive(Debug, Deserialize, Validate, Serialize, Clone)]
+pub struct RefreshTokenDTO {
+    #[serde(rename = "refreshToken")]
+    #[validate(length(min = 1))]
+    pub refresh_token: String,
+}
+
+#[derive(Debug, Deserialize, Validate, Serialize, Clone)]
+pub struct ChangePasswordDTO {
+    #[serde(rename = "oldPassword")]
+    pub old_password: String,
+    #[serde(rename = "newPassword")]
+    #[validate(length(min = 1))]
+    pub new_password: String,
+}
+
+#[derive(Debug, Deserialize, Validate, Serialize, Clone)]
+pub struct PasswordResetRequestDTO {
+    pub username: String,
+}
+
+#[derive(Debug, Deserialize, Validate, Serialize, Clone)]
+pub struct PasswordResetDTO {
+    #[validate(length(min = 1))]
+    pub token: String,
+    #[serde(rename = "newPassword")]
+    #[validate(length(min = 1))]
+    pub new_password: String,
+}
+
 struct UserUpdate {}
 
 impl Model<UserUpdate> for User {
@@ -67,6 +97,18 @@ impl SqlQueryResponse for UserResponseWithSharing {
     }
 }
 
+#[derive(Debug, Serialize, Clone)]
+pub struct SessionResponse {
+    pub id: Uuid,
+    #[serde(rename = "createdAt")]
+    pub created_at: i64,
+    #[serde(rename = "lastSeen")]
+    pub last_seen: i64,
+    #[serde(rename = "userAgent")]
+    pub user_agent: Option<String>,
+    pub current: bool,
+}
+
 #[derive(Serialize, Deserialize)]
 pub struct TokenClaims {
     iss: String,
@@ -0,0 +1,106 @@
+use warp::Filter;
+use warp::{Reply,Rejection};
+use uuid::Uuid;
+use crate::services::categories::{create_category, get_categories, update_category, delete_category, create_store, get_stores, update_store, delete_store, get_store_categories, set_store_categories};
+use crate::middlewares::auth::with_scope;
+use crate::models::access_token::Scope;
+use crate::middlewares::{with_body, with_connection};
+use crate::models::GlobalContext;
+
+pub fn categories_router(ctx: &GlobalContext) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+    post_category(ctx)
+        .or(categories(ctx))
+        .or(patch_category(ctx))
+        .or(remove_category(ctx))
+        .or(post_store(ctx))
+        .or(stores(ctx))
+        .or(patch_store(ctx))
+        .or(remove_store(ctx))
+        .or(store_categories(ctx))
+        .or(put_store_categories(ctx))
+}
+
+fn post_category(ctx: &GlobalContext) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+    warp::path!("category")
+        .and(warp::post())
+        .and(with_scope(ctx, Scope::ListsWrite))
+        .and(with_connection(&ctx.pg_pool))
+        .and(with_body())
+        .and_then(create_category)
+}
+
+fn categories(ctx: &GlobalContext) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+    warp::path!("category")
+        .and(warp::get())
+        .and(with_scope(ctx, Scope::ListsRead))
+        .and(with_connection(&ctx.pg_pool))
+        .and_then(get_categories)
+}
+
+fn patch_category(ctx: &GlobalContext) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+    warp::path!("category" / Uuid)
+        .and(warp::patch())
+        .and(with_scope(ctx, Scope::ListsWrite))
+        .and(with_connection(&ctx.pg_pool))
+        .and(with_body())
+        .and_then(update_category)
+}
+
+fn remove_category(ctx: &GlobalContext) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+    warp::path!("category" / Uuid)
+        .and(warp::delete())
+        .and(with_scope(ctx, Scope::ListsWrite))
+        .and(with_connection(&ctx.pg_pool))
+        .and_then(delete_category)
+}
+
+fn post_store(ctx: &GlobalContext) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+    warp::path!("store")
+        .and(warp::post())
+        .and(with_scope(ctx, Scope::ListsWrite))
+        .and(with_connection(&ctx.pg_pool))
+        .and(with_body())
+        .and_then(create_store)
+}
+
+fn stores(ctx: &GlobalContext) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+    warp::path!("store")
+        .and(warp::get())
+        .and(with_scope(ctx, Scope::ListsRead))
+        .and(with_connection(&ctx.pg_pool))
+        .and_then(get_stores)
+}
+
+fn patch_store(ctx: &GlobalContext) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+    warp::path!("store" / Uuid)
+        .and(warp::patch())
+        .and(with_scope(ctx, Scope::ListsWrite))
+        .and(with_connection(&ctx.pg_pool))
+        .and(with_body())
+        .and_then(update_store)
+}
+
+fn remove_store(ctx: &GlobalContext) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+    warp::path!("store" / Uuid)
+        .and(warp::delete())
+        .and(with_scope(ctx, Scope::ListsWrite))
+        .and(with_connection(&ctx.pg_pool))
+        .and_then(delete_store)
+}
+
+fn store_categories(ctx: &GlobalContext) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+    warp::path!("store" / Uuid / "categories")
+        .and(warp::get())
+        .and(with_scope(ctx, Scope::ListsRead))
+        .and(with_connection(&ctx.pg_pool))
+        .and_then(get_store_categories)
+}
+
+fn put_store_categories(ctx: &GlobalContext) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+    warp::path!("store" / Uuid / "categories")
+        .and(warp::put())
+        .and(with_scope(ctx, Scope::ListsWrite))
+        .and(with_connection(&ctx.pg_pool))
+        .and(with_body())
+        .and_then(set_store_categories)
+}
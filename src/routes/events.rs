@@ -0,0 +1,37 @@
+use warp::{Filter, Reply, Rejection};
+use uuid::Uuid;
+use crate::services::events::{list_socket, list_event_stream};
+use crate::middlewares::{with_connection, with_events};
+use crate::middlewares::auth::with_socket_scope;
+use crate::models::access_token::Scope;
+use crate::models::GlobalContext;
+
+pub fn events_router(ctx: &GlobalContext) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+    socket(ctx)
+        .or(event_stream(ctx))
+}
+
+fn socket(ctx: &GlobalContext) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+    let pg_pool = ctx.pg_pool.clone();
+    warp::path!("shopping_list" / Uuid / "ws")
+        .and(warp::get())
+        .and(with_socket_scope(ctx, Scope::ItemsRead))
+        .and(with_connection(&ctx.pg_pool))
+        .and(warp::any().map(move || pg_pool.clone()))
+        .and(with_events(&ctx.events))
+        .and(warp::ws())
+        .and_then(list_socket)
+}
+
+fn event_stream(ctx: &GlobalContext) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+    let pg_pool = ctx.pg_pool.clone();
+    warp::path!("shopping_list" / Uuid / "events")
+        .and(warp::get())
+        .and(with_socket_scope(ctx, Scope::ItemsRead))
+        .and(with_connection(&ctx.pg_pool))
+        .and(with_connection(&ctx.redis_pool))
+        .and(warp::any().map(move || pg_pool.clone()))
+        .and(with_events(&ctx.events))
+        .and(warp::sse::last_event_id::<String>())
+        .and_then(list_event_stream)
+}
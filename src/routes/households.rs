@@ -0,0 +1,98 @@
+use warp::Filter;
+use warp::{Reply,Rejection};
+use uuid::Uuid;
+use crate::services::households::{create_household, get_households, update_household, delete_household, get_members, add_member, update_member, remove_member, set_list_household};
+use crate::middlewares::auth::with_scope;
+use crate::models::access_token::Scope;
+use crate::middlewares::{with_body, with_connection};
+use crate::models::GlobalContext;
+
+pub fn households_router(ctx: &GlobalContext) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+    post_household(ctx)
+        .or(households(ctx))
+        .or(patch_household(ctx))
+        .or(remove_household(ctx))
+        .or(members(ctx))
+        .or(post_member(ctx))
+        .or(patch_member(ctx))
+        .or(delete_member(ctx))
+        .or(list_household(ctx))
+}
+
+fn post_household(ctx: &GlobalContext) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+    warp::path!("household")
+        .and(warp::post())
+        .and(with_scope(ctx, Scope::ListsWrite))
+        .and(with_connection(&ctx.pg_pool))
+        .and(with_body())
+        .and_then(create_household)
+}
+
+fn households(ctx: &GlobalContext) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+    warp::path!("household")
+        .and(warp::get())
+        .and(with_scope(ctx, Scope::ListsRead))
+        .and(with_connection(&ctx.pg_pool))
+        .and_then(get_households)
+}
+
+fn patch_household(ctx: &GlobalContext) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+    warp::path!("household" / Uuid)
+        .and(warp::patch())
+        .and(with_scope(ctx, Scope::ListsWrite))
+        .and(with_connection(&ctx.pg_pool))
+        .and(with_body())
+        .and_then(update_household)
+}
+
+fn remove_household(ctx: &GlobalContext) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+    warp::path!("household" / Uuid)
+        .and(warp::delete())
+        .and(with_scope(ctx, Scope::ListsWrite))
+        .and(with_connection(&ctx.pg_pool))
+        .and_then(delete_household)
+}
+
+fn members(ctx: &GlobalContext) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+    warp::path!("household" / Uuid / "members")
+        .and(warp::get())
+        .and(with_scope(ctx, Scope::ListsRead))
+        .and(with_connection(&ctx.pg_pool))
+        .and_then(get_members)
+}
+
+fn post_member(ctx: &GlobalContext) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+    warp::path!("household" / Uuid / "members")
+        .and(warp::post())
+        .and(with_scope(ctx, Scope::ListsWrite))
+        .and(with_connection(&ctx.pg_pool))
+        .and(with_body())
+        .and_then(add_member)
+}
+
+fn patch_member(ctx: &GlobalContext) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+    warp::path!("household" / Uuid / "members")
+        .and(warp::patch())
+        .and(with_scope(ctx, Scope::ListsWrite))
+        .and(with_connection(&ctx.pg_pool))
+        .and(with_body())
+        .and_then(update_member)
+}
+
+fn delete_member(ctx: &GlobalContext) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+    warp::path!("household" / Uuid / "members" / Uuid)
+        .and(warp::delete())
+        .and(with_scope(ctx, Scope::ListsWrite))
+        .and(with_connection(&ctx.pg_pool))
+        .and_then(remove_member)
+}
+
+fn list_household(ctx: &GlobalContext) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+    warp::path!("shopping_list" / Uuid / "household")
+        .and(warp::put())
+        .and(with_scope(ctx, Scope::ListsWrite))
+        .and(with_connection(&ctx.pg_pool))
+        .and(with_connection(&ctx.redis_pool))
+        .and(with_body())
+        .and_then(set_list_household)
+}
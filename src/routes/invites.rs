@@ -0,0 +1,52 @@
+use warp::Filter;
+use warp::{Reply,Rejection};
+use uuid::Uuid;
+use crate::services::invites::{create_invite, get_invites, delete_invite, accept_invite};
+use crate::middlewares::auth::with_scope;
+use crate::models::access_token::Scope;
+use crate::middlewares::{with_body, with_connection};
+use crate::models::GlobalContext;
+
+pub fn invites_router(ctx: &GlobalContext) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+    post_invite(ctx)
+        .or(invites(ctx))
+        .or(revoke_invite(ctx))
+        .or(accept(ctx))
+}
+
+fn post_invite(ctx: &GlobalContext) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+    warp::path!("shopping_list" / Uuid / "invite")
+        .and(warp::path::end())
+        .and(warp::post())
+        .and(with_scope(ctx, Scope::ListsWrite))
+        .and(with_connection(&ctx.pg_pool))
+        .and(with_body())
+        .and_then(create_invite)
+}
+
+fn invites(ctx: &GlobalContext) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+    warp::path!("shopping_list" / Uuid / "invite")
+        .and(warp::path::end())
+        .and(warp::get())
+        .and(with_scope(ctx, Scope::ListsRead))
+        .and(with_connection(&ctx.pg_pool))
+        .and_then(get_invites)
+}
+
+fn revoke_invite(ctx: &GlobalContext) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+    warp::path!("shopping_list" / Uuid / "invite" / Uuid)
+        .and(warp::path::end())
+        .and(warp::delete())
+        .and(with_scope(ctx, Scope::ListsWrite))
+        .and(with_connection(&ctx.pg_pool))
+        .and_then(delete_invite)
+}
+
+fn accept(ctx: &GlobalContext) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+    warp::path!("invite" / String / "accept")
+        .and(warp::path::end())
+        .and(warp::post())
+        .and(with_scope(ctx, Scope::ListsWrite))
+        .and(with_connection(&ctx.pg_pool))
+        .and_then(accept_invite)
+}
@@ -1,8 +1,10 @@
 use warp::{Filter, Rejection, Reply};
-use crate::services::items::{create_items, get_items as get_items_handler, update_item, delete_item as delete_item_handler};
+use crate::services::items::{create_items, get_items as get_items_handler, get_item as get_item_handler, update_item, delete_item as delete_item_handler, batch_items, clear_bought, reset_bought, move_item, merge_duplicates};
 use uuid::Uuid;
 use crate::middlewares::{with_vec_body, with_body, with_connection, with_query};
-use crate::middlewares::auth::with_auth;
+use crate::middlewares::auth::with_scope;
+use crate::middlewares::precondition::with_if_match;
+use crate::models::access_token::Scope;
 use crate::models::GlobalContext;
 
 pub fn items_router(ctx: &GlobalContext) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
@@ -10,23 +12,38 @@ pub fn items_router(ctx: &GlobalContext) -> impl Filter<Extract = impl Reply, Er
         .or(add_items(ctx))
         .or(get_items(ctx))
         .or(delete_item(ctx))
-        .or(warp::get().and(with_item_id_path()).map(|i1, i2| format!("{} {}", i1, i2)))
+        .or(get_item(ctx))
+        .or(batch(ctx))
+        .or(post_clear_bought(ctx))
+        .or(post_reset_bought(ctx))
+        .or(post_merge(ctx))
+        .or(put_position(ctx))
 }
 
 fn get_items(ctx: &GlobalContext) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone  {
     warp::get()
         .and(with_path())
-        .and(with_auth(&ctx.redis_pool))
+        .and(with_scope(ctx, Scope::ItemsRead))
         .and(with_connection(&ctx.pg_pool))
         .and(with_query())
         .and_then(get_items_handler)
 }
 
+fn get_item(ctx: &GlobalContext) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+    warp::get()
+        .and(with_item_id_path())
+        .and(with_scope(ctx, Scope::ItemsRead))
+        .and(with_connection(&ctx.pg_pool))
+        .and_then(get_item_handler)
+}
+
 fn add_items(ctx: &GlobalContext) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
     warp::post()
         .and(with_path())
-        .and(with_auth(&ctx.redis_pool))
+        .and(with_scope(ctx, Scope::ItemsWrite))
         .and(with_connection(&ctx.pg_pool))
+        .and(with_connection(&ctx.redis_pool))
+        .and(with_query())
         .and(with_vec_body())
         .and_then(create_items)
 }
@@ -34,8 +51,10 @@ fn add_items(ctx: &GlobalContext) -> impl Filter<Extract = impl Reply, Error = R
 fn patch_item(ctx: &GlobalContext) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
     warp::patch()
         .and(with_item_id_path())
-        .and(with_auth(&ctx.redis_pool))
+        .and(with_scope(ctx, Scope::ItemsWrite))
         .and(with_connection(&ctx.pg_pool))
+        .and(with_connection(&ctx.redis_pool))
+        .and(with_if_match())
         .and(with_body())
         .and_then(update_item)
 }
@@ -43,11 +62,61 @@ fn patch_item(ctx: &GlobalContext) -> impl Filter<Extract = impl Reply, Error =
 fn delete_item(ctx: &GlobalContext) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
     warp::delete()
         .and(with_item_id_path())
-        .and(with_auth(&ctx.redis_pool))
+        .and(with_scope(ctx, Scope::ItemsWrite))
         .and(with_connection(&ctx.pg_pool))
+        .and(with_connection(&ctx.redis_pool))
+        .and(with_if_match())
         .and_then(delete_item_handler)
 }
 
+fn batch(ctx: &GlobalContext) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+    warp::post()
+        .and(warp::path!("shopping_list" / Uuid / "item" / "batch"))
+        .and(with_scope(ctx, Scope::ItemsWrite))
+        .and(with_connection(&ctx.pg_pool))
+        .and(with_connection(&ctx.redis_pool))
+        .and(with_vec_body())
+        .and_then(batch_items)
+}
+
+fn post_clear_bought(ctx: &GlobalContext) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+    warp::post()
+        .and(warp::path!("shopping_list" / Uuid / "item" / "clear_bought"))
+        .and(with_scope(ctx, Scope::ItemsWrite))
+        .and(with_connection(&ctx.pg_pool))
+        .and(with_connection(&ctx.redis_pool))
+        .and_then(clear_bought)
+}
+
+fn post_reset_bought(ctx: &GlobalContext) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+    warp::post()
+        .and(warp::path!("shopping_list" / Uuid / "item" / "reset_bought"))
+        .and(with_scope(ctx, Scope::ItemsWrite))
+        .and(with_connection(&ctx.pg_pool))
+        .and(with_connection(&ctx.redis_pool))
+        .and_then(reset_bought)
+}
+
+fn post_merge(ctx: &GlobalContext) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+    warp::post()
+        .and(warp::path!("shopping_list" / Uuid / "item" / "merge"))
+        .and(with_scope(ctx, Scope::ItemsWrite))
+        .and(with_connection(&ctx.pg_pool))
+        .and(with_connection(&ctx.redis_pool))
+        .and_then(merge_duplicates)
+}
+
+fn put_position(ctx: &GlobalContext) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+    warp::put()
+        .and(warp::path!("shopping_list" / Uuid / "item" / Uuid / "position"))
+        .and(with_scope(ctx, Scope::ItemsWrite))
+        .and(with_connection(&ctx.pg_pool))
+        .and(with_connection(&ctx.redis_pool))
+        .and(with_if_match())
+        .and(with_body())
+        .and_then(move_item)
+}
+
 fn with_path() -> impl Filter<Extract = (Uuid,), Error = Rejection> + Copy {
     warp::path!("shopping_list" / Uuid / "item")
         .and(warp::path::end())
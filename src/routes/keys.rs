@@ -0,0 +1,12 @@
+use warp::{Filter, Reply, Rejection};
+use crate::services::keys::get_jwks;
+use crate::middlewares::auth::with_keys;
+use crate::models::GlobalContext;
+
+pub fn keys_router(ctx: &GlobalContext) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+    warp::path!(".well-known" / "jwks.json")
+        .and(warp::path::end())
+        .and(warp::get())
+        .and(with_keys(&ctx.keys))
+        .and_then(get_jwks)
+}
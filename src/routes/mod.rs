@@ -3,6 +3,13 @@ use crate::routes::user::user_router;
 use crate::routes::items::items_router;
 use crate::routes::sharing::sharing_router;
 use crate::routes::shopping_list::shopping_list_router;
+use crate::routes::keys::keys_router;
+use crate::routes::invites::invites_router;
+use crate::routes::households::households_router;
+use crate::routes::events::events_router;
+use crate::routes::sync::sync_router;
+use crate::routes::categories::categories_router;
+use crate::routes::prices::prices_router;
 use crate::middlewares::error::handle_rejection;
 use std::convert::Infallible;
 use crate::models::GlobalContext;
@@ -12,12 +19,26 @@ pub mod shopping_list;
 pub mod items;
 pub mod user;
 pub mod sharing;
+pub mod keys;
+pub mod invites;
+pub mod households;
+pub mod events;
+pub mod sync;
+pub mod categories;
+pub mod prices;
 
 pub fn router(ctx: &GlobalContext) -> impl Filter<Extract = impl Reply, Error = Infallible> + Clone {
     user_router(ctx)
         .or(items_router(ctx))
         .or(sharing_router(ctx))
+        .or(invites_router(ctx))
+        .or(households_router(ctx))
+        .or(events_router(ctx))
+        .or(sync_router(ctx))
+        .or(categories_router(ctx))
+        .or(prices_router(ctx))
         .or(shopping_list_router(ctx))
+        .or(keys_router(ctx))
         .with(cors())
         .with(warp::log("debug"))
         .recover(handle_rejection)
@@ -28,7 +49,7 @@ fn cors() -> Builder {
     // TODO load origin from env
     // let allowed_origins = vec!["http://localhost"];
     let allowed_methods = vec!["GET", "POST", "PUT", "PATCH", "DELETE", "OPTIONS"];
-    let allowed_headers = vec!["0","1","2","3","4","5","6","7","8","9", "10", "Authorization", "User-Agent", "Sec-Fetch-Mode", "Content-Type", "Referer", "Origin", "Accept", "Access-Control-Request-Method", "Access-Control-Request-Headers"];
+    let allowed_headers = vec!["0","1","2","3","4","5","6","7","8","9", "10", "Authorization", "User-Agent", "Sec-Fetch-Mode", "Content-Type", "Referer", "Origin", "Accept", "Access-Control-Request-Method", "Access-Control-Request-Headers", "Idempotency-Key"];
 
     warp::cors()
         // .allow_origins(allowed_origins)
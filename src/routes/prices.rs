@@ -0,0 +1,18 @@
+use warp::{Filter, Rejection, Reply};
+use crate::services::prices::get_product_prices;
+use crate::middlewares::with_connection;
+use crate::middlewares::auth::with_scope;
+use crate::models::access_token::Scope;
+use crate::models::GlobalContext;
+
+pub fn prices_router(ctx: &GlobalContext) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+    product_prices(ctx)
+}
+
+fn product_prices(ctx: &GlobalContext) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+    warp::get()
+        .and(warp::path!("products" / String / "prices"))
+        .and(with_scope(ctx, Scope::ItemsRead))
+        .and(with_connection(&ctx.pg_pool))
+        .and_then(get_product_prices)
+}
@@ -1,8 +1,9 @@
 use warp::Filter;
 use warp::{Reply,Rejection};
 use uuid::Uuid;
-use crate::services::shopping_list::{get_list_sharing, share_list, stop_sharing_list};
-use crate::middlewares::auth::with_auth;
+use crate::services::shopping_list::{get_list_sharing, share_list, stop_sharing_list, update_sharing};
+use crate::middlewares::auth::with_scope;
+use crate::models::access_token::Scope;
 use crate::middlewares::{with_body, with_connection};
 use crate::models::GlobalContext;
 
@@ -10,22 +11,32 @@ pub fn sharing_router(ctx: &GlobalContext) -> impl Filter<Extract = impl Reply,
     share_shopping_list(ctx)
         .or(remove_sharing(ctx))
         .or(get_sharing(ctx))
+        .or(patch_sharing(ctx))
 }
 
 fn share_shopping_list(ctx: &GlobalContext) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
     warp::post()
         .and(with_path())
         .and(with_body())
-        .and(with_auth(&ctx.redis_pool))
+        .and(with_scope(ctx, Scope::ListsWrite))
         .and(with_connection(&ctx.pg_pool))
         .and_then(share_list)
 }
 
+fn patch_sharing(ctx: &GlobalContext) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+    warp::patch()
+        .and(with_path())
+        .and(with_body())
+        .and(with_scope(ctx, Scope::ListsWrite))
+        .and(with_connection(&ctx.pg_pool))
+        .and_then(update_sharing)
+}
+
 fn remove_sharing(ctx: &GlobalContext) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
     warp::delete()
         .and(with_path())
         .and(with_body())
-        .and(with_auth(&ctx.redis_pool))
+        .and(with_scope(ctx, Scope::ListsWrite))
         .and(with_connection(&ctx.pg_pool))
         .and_then(stop_sharing_list)
 }
@@ -33,7 +44,7 @@ fn remove_sharing(ctx: &GlobalContext) -> impl Filter<Extract = impl Reply, Erro
 fn get_sharing(ctx: &GlobalContext) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
     warp::get()
         .and(with_path())
-        .and(with_auth(&ctx.redis_pool))
+        .and(with_scope(ctx, Scope::ListsRead))
         .and(with_connection(&ctx.pg_pool))
         .and_then(get_list_sharing)
 }
@@ -1,7 +1,9 @@
 use warp::{Filter, Reply, Rejection};
-use crate::services::shopping_list::{get_shopping_lists,create,update,delete};
+use crate::services::shopping_list::{get_shopping_lists,get_shopping_list,create,update,delete};
 use crate::middlewares::{with_body,with_connection,with_query};
-use crate::middlewares::auth::{with_auth, AuthenticatedUser};
+use crate::middlewares::auth::{with_scope, AuthenticatedUser};
+use crate::middlewares::precondition::with_if_match;
+use crate::models::access_token::Scope;
 use uuid::Uuid;
 use crate::models::GlobalContext;
 
@@ -9,6 +11,7 @@ pub fn shopping_list_router(ctx: &GlobalContext) -> impl Filter<Extract = impl R
     warp::path("shopping_list")
         .and(
             post_list(ctx)
+                .or(get_list(ctx))
                 .or(get_my_lists(ctx))
                 .or(update_list(ctx))
                 .or(delete_list(ctx))
@@ -20,14 +23,22 @@ fn get_my_lists(ctx: &GlobalContext) -> impl Filter<Extract = impl Reply, Error
     warp::get()
         .and(with_connection(&ctx.pg_pool))
         .and(with_query())
-        .and(with_auth(&ctx.redis_pool))
+        .and(with_scope(ctx, Scope::ListsRead))
         .and_then(get_shopping_lists)
 }
 
+fn get_list(ctx: &GlobalContext) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+    warp::get()
+        .and(warp::path!(Uuid))
+        .and(with_scope(ctx, Scope::ListsRead))
+        .and(with_connection(&ctx.pg_pool))
+        .and_then(get_shopping_list)
+}
+
 fn post_list(ctx: &GlobalContext) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
     warp::post()
         .and(with_connection(&ctx.pg_pool))
-        .and(with_auth(&ctx.redis_pool))
+        .and(with_scope(ctx, Scope::ListsWrite))
         .and(with_body())
         .and_then(create)
 }
@@ -35,8 +46,10 @@ fn post_list(ctx: &GlobalContext) -> impl Filter<Extract = impl Reply, Error = R
 fn update_list(ctx: &GlobalContext) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
     warp::patch()
         .and(warp::path!(Uuid))
-        .and(with_auth(&ctx.redis_pool))
+        .and(with_scope(ctx, Scope::ListsWrite))
         .and(with_connection(&ctx.pg_pool))
+        .and(with_connection(&ctx.redis_pool))
+        .and(with_if_match())
         .and(with_body())
         .and_then(update)
 }
@@ -44,7 +57,9 @@ fn update_list(ctx: &GlobalContext) -> impl Filter<Extract = impl Reply, Error =
 fn delete_list(ctx: &GlobalContext) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
     warp::delete()
         .and(warp::path!(Uuid))
-        .and(with_auth(&ctx.redis_pool).map(|user: AuthenticatedUser| user.id))
+        .and(with_scope(ctx, Scope::ListsWrite).map(|user: AuthenticatedUser| user.id))
         .and(with_connection(&ctx.pg_pool))
+        .and(with_connection(&ctx.redis_pool))
+        .and(with_if_match())
         .and_then(delete)
 }
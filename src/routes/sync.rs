@@ -0,0 +1,35 @@
+use warp::{Filter, Rejection, Reply};
+use crate::services::sync::{get_sync as get_sync_handler, apply_sync};
+use crate::middlewares::{with_vec_body, with_connection, with_query};
+use crate::middlewares::auth::with_scope;
+use crate::models::access_token::Scope;
+use crate::models::GlobalContext;
+
+pub fn sync_router(ctx: &GlobalContext) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+    get_sync(ctx)
+        .or(post_sync(ctx))
+}
+
+fn get_sync(ctx: &GlobalContext) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+    warp::get()
+        .and(with_path())
+        .and(with_scope(ctx, Scope::ItemsRead))
+        .and(with_connection(&ctx.pg_pool))
+        .and(with_query())
+        .and_then(get_sync_handler)
+}
+
+fn post_sync(ctx: &GlobalContext) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+    warp::post()
+        .and(with_path())
+        .and(with_scope(ctx, Scope::ItemsWrite))
+        .and(with_connection(&ctx.pg_pool))
+        .and(with_connection(&ctx.redis_pool))
+        .and(with_vec_body())
+        .and_then(apply_sync)
+}
+
+fn with_path() -> impl Filter<Extract = (), Error = Rejection> + Copy {
+    warp::path!("sync")
+        .and(warp::path::end())
+}
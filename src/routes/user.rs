@@ -1,8 +1,11 @@
 use warp::{Filter, Reply, Rejection};
-use crate::services::user::{create_user, delete_user, login_handler, search_user, logout_handler, get_by_id};
-use crate::middlewares::{with_body, with_query, with_connection};
-use crate::middlewares::auth::{with_auth, AuthenticatedUser};
+use crate::services::user::{create_user, delete_user, login_handler, search_user, logout_handler, get_by_id, refresh_token_handler, get_sessions, delete_session, delete_other_sessions, change_password, request_password_reset, reset_password, login_two_factor_handler};
+use crate::services::two_factor::{enroll, confirm_enrollment, disable};
+use crate::services::access_tokens::{create_access_token, get_access_tokens, delete_access_token};
+use crate::middlewares::{with_body, with_query, with_connection, with_notifier, with_client_address};
+use crate::middlewares::auth::{with_auth, with_keys, AuthenticatedUser};
 use crate::models::GlobalContext;
+use uuid::Uuid;
 
 pub fn user_router(ctx: &GlobalContext) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
     post_user(ctx)
@@ -11,6 +14,20 @@ pub fn user_router(ctx: &GlobalContext) -> impl Filter<Extract = impl Reply, Err
         .or(delete_self(ctx))
         .or(login(ctx))
         .or(logout(ctx))
+        .or(refresh(ctx))
+        .or(sessions(ctx))
+        .or(revoke_session(ctx))
+        .or(revoke_other_sessions(ctx))
+        .or(update_password(ctx))
+        .or(password_reset_request(ctx))
+        .or(password_reset(ctx))
+        .or(login_two_factor(ctx))
+        .or(two_factor_enroll(ctx))
+        .or(two_factor_confirm(ctx))
+        .or(two_factor_disable(ctx))
+        .or(post_access_token(ctx))
+        .or(access_tokens(ctx))
+        .or(revoke_access_token(ctx))
 }
 
 fn post_user(ctx: &GlobalContext) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
@@ -19,6 +36,8 @@ fn post_user(ctx: &GlobalContext) -> impl Filter<Extract = impl Reply, Error = R
         .and(warp::post())
         .and(with_connection(&ctx.pg_pool))
         .and(with_connection(&ctx.redis_pool))
+        .and(with_keys(&ctx.keys))
+        .and(warp::header::optional::<String>("user-agent"))
         .and(with_body())
         .and_then(create_user)
 }
@@ -29,25 +48,170 @@ fn login(ctx: &GlobalContext) -> impl Filter<Extract = impl Reply, Error = Rejec
         .and(warp::post())
         .and(with_connection(&ctx.pg_pool))
         .and(with_connection(&ctx.redis_pool))
+        .and(with_keys(&ctx.keys))
+        .and(warp::header::optional::<String>("user-agent"))
+        .and(with_client_address())
         .and(with_body())
         .and_then(login_handler)
 }
 
+fn login_two_factor(ctx: &GlobalContext) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+    warp::path!("user" / "login" / "2fa")
+        .and(warp::path::end())
+        .and(warp::post())
+        .and(with_connection(&ctx.pg_pool))
+        .and(with_connection(&ctx.redis_pool))
+        .and(with_keys(&ctx.keys))
+        .and(warp::header::optional::<String>("user-agent"))
+        .and(with_client_address())
+        .and(with_body())
+        .and_then(login_two_factor_handler)
+}
+
+fn two_factor_enroll(ctx: &GlobalContext) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+    warp::path!("user" / "2fa")
+        .and(warp::path::end())
+        .and(warp::post())
+        .and(with_auth(ctx))
+        .and(with_connection(&ctx.pg_pool))
+        .and(with_connection(&ctx.redis_pool))
+        .and_then(enroll)
+}
+
+fn two_factor_confirm(ctx: &GlobalContext) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+    warp::path!("user" / "2fa" / "confirm")
+        .and(warp::path::end())
+        .and(warp::post())
+        .and(with_auth(ctx))
+        .and(with_connection(&ctx.pg_pool))
+        .and(with_connection(&ctx.redis_pool))
+        .and(with_body())
+        .and_then(confirm_enrollment)
+}
+
+fn two_factor_disable(ctx: &GlobalContext) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+    warp::path!("user" / "2fa")
+        .and(warp::path::end())
+        .and(warp::delete())
+        .and(with_auth(ctx))
+        .and(with_connection(&ctx.pg_pool))
+        .and(with_connection(&ctx.redis_pool))
+        .and(with_body())
+        .and_then(disable)
+}
+
+fn refresh(ctx: &GlobalContext) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+    warp::path!("user" / "token" / "refresh")
+        .and(warp::path::end())
+        .and(warp::post())
+        .and(with_connection(&ctx.redis_pool))
+        .and(with_keys(&ctx.keys))
+        .and(with_body())
+        .and_then(refresh_token_handler)
+}
+
 fn logout(ctx: &GlobalContext) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
     warp::path!("user" / "logout")
         .and(warp::path::end())
         .and(warp::get())
-        .and(with_auth(&ctx.redis_pool))
+        .and(with_auth(ctx))
         .and(with_connection(&ctx.redis_pool))
         .and_then(logout_handler)
 }
 
+fn sessions(ctx: &GlobalContext) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+    warp::path!("user" / "sessions")
+        .and(warp::path::end())
+        .and(warp::get())
+        .and(with_auth(ctx))
+        .and(with_connection(&ctx.redis_pool))
+        .and_then(get_sessions)
+}
+
+fn revoke_session(ctx: &GlobalContext) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+    warp::path!("user" / "sessions" / Uuid)
+        .and(warp::path::end())
+        .and(warp::delete())
+        .and(with_auth(ctx))
+        .and(with_connection(&ctx.redis_pool))
+        .and_then(delete_session)
+}
+
+fn revoke_other_sessions(ctx: &GlobalContext) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+    warp::path!("user" / "sessions")
+        .and(warp::path::end())
+        .and(warp::delete())
+        .and(with_auth(ctx))
+        .and(with_connection(&ctx.redis_pool))
+        .and_then(delete_other_sessions)
+}
+
+fn update_password(ctx: &GlobalContext) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+    warp::path!("user" / "password")
+        .and(warp::path::end())
+        .and(warp::put())
+        .and(with_auth(ctx))
+        .and(with_connection(&ctx.pg_pool))
+        .and(with_connection(&ctx.redis_pool))
+        .and(with_body())
+        .and_then(change_password)
+}
+
+fn password_reset_request(ctx: &GlobalContext) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+    warp::path!("user" / "password" / "reset")
+        .and(warp::path::end())
+        .and(warp::post())
+        .and(with_connection(&ctx.pg_pool))
+        .and(with_connection(&ctx.redis_pool))
+        .and(with_notifier(&ctx.notifier))
+        .and(with_body())
+        .and_then(request_password_reset)
+}
+
+fn password_reset(ctx: &GlobalContext) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+    warp::path!("user" / "password" / "reset" / "confirm")
+        .and(warp::path::end())
+        .and(warp::post())
+        .and(with_connection(&ctx.pg_pool))
+        .and(with_connection(&ctx.redis_pool))
+        .and(with_body())
+        .and_then(reset_password)
+}
+
+fn post_access_token(ctx: &GlobalContext) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+    warp::path!("user" / "tokens")
+        .and(warp::path::end())
+        .and(warp::post())
+        .and(with_auth(ctx))
+        .and(with_connection(&ctx.pg_pool))
+        .and(with_body())
+        .and_then(create_access_token)
+}
+
+fn access_tokens(ctx: &GlobalContext) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+    warp::path!("user" / "tokens")
+        .and(warp::path::end())
+        .and(warp::get())
+        .and(with_auth(ctx))
+        .and(with_connection(&ctx.pg_pool))
+        .and_then(get_access_tokens)
+}
+
+fn revoke_access_token(ctx: &GlobalContext) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+    warp::path!("user" / "tokens" / Uuid)
+        .and(warp::path::end())
+        .and(warp::delete())
+        .and(with_auth(ctx))
+        .and(with_connection(&ctx.pg_pool))
+        .and_then(delete_access_token)
+}
+
 fn current(ctx: &GlobalContext) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
     warp::path!("user" / "current")
         .and(warp::path::end())
         .and(warp::get())
         .and(with_connection(&ctx.pg_pool))
-        .and(with_auth(&ctx.redis_pool))
+        .and(with_auth(ctx))
         .and_then(get_by_id)
 }
 
@@ -56,7 +220,7 @@ fn delete_self(ctx: &GlobalContext) -> impl Filter<Extract = impl Reply, Error =
         .and(warp::path::end())
         .and(warp::delete())
         .and(with_connection(&ctx.pg_pool))
-        .and(with_auth(&ctx.redis_pool).map(|user: AuthenticatedUser| user.id))
+        .and(with_auth(ctx).map(|user: AuthenticatedUser| user.id))
         .and(with_connection(&ctx.redis_pool))
         .and_then(delete_user)
 }
@@ -65,7 +229,7 @@ fn search(ctx: &GlobalContext) -> impl Filter<Extract = impl Reply, Error = Reje
     warp::path("user")
         .and(warp::path::end())
         .and(warp::get())
-        .and(with_auth(&ctx.redis_pool))
+        .and(with_auth(ctx))
         .and(with_query())
         .and(with_query())
         .and(with_connection(&ctx.pg_pool))
@@ -0,0 +1,102 @@
+use rand::Rng;
+use rand::distributions::Alphanumeric;
+use ring::digest::{digest, SHA256};
+use uuid::Uuid;
+use warp::{Reply, Rejection, reject};
+use warp::http::StatusCode;
+use warp::reply::{json, with_status};
+use crate::middlewares::auth::AuthenticatedUser;
+use crate::middlewares::error::HttpError;
+use crate::models::SqlQueryResponse;
+use crate::models::access_token::{AccessTokenDTO, AccessTokenResponse, CreatedAccessTokenResponse, Scope, parse_scopes};
+use crate::services::database::DBConn;
+
+/// Prefix that tells personal access tokens apart from session JWTs.
+pub const ACCESS_TOKEN_PREFIX: &str = "slpat_";
+const ACCESS_TOKEN_LENGTH: usize = 40;
+
+pub struct AccessTokenOwner {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub scopes: Vec<Scope>,
+}
+
+pub async fn create_access_token(user: AuthenticatedUser, db: DBConn, body: AccessTokenDTO) -> Result<impl Reply, Rejection> {
+    let random: String = rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(ACCESS_TOKEN_LENGTH)
+        .map(char::from)
+        .collect();
+    let token = format!("{}{}", ACCESS_TOKEN_PREFIX, random);
+    let scopes: Vec<String> = body.scopes.iter().map(|scope| scope.to_string()).collect();
+
+    let resp = db.query(
+        "INSERT INTO personal_access_token (id, user_id, name, token_hash, scopes, expires_at)
+            VALUES (uuid_generate_v4(), $1, $2, $3, $4, now() + make_interval(days => $5::int))
+            RETURNING *",
+        &[&user.id, &body.name, &hash_token(&token), &scopes, &body.expires_in_days],
+    ).await.map_err(HttpError::Query)?;
+    let row = resp.first().ok_or(HttpError::InternalServerError)?;
+
+    let response = CreatedAccessTokenResponse {
+        access_token: AccessTokenResponse::from_row(row),
+        token,
+    };
+    Ok(with_status(json(&response), StatusCode::CREATED))
+}
+
+pub async fn get_access_tokens(user: AuthenticatedUser, db: DBConn) -> Result<impl Reply, Rejection> {
+    let resp = db.query(
+        "SELECT * FROM personal_access_token WHERE user_id=$1 ORDER BY created_at DESC",
+        &[&user.id],
+    ).await.map_err(HttpError::Query)?;
+
+    let tokens: Vec<AccessTokenResponse> = resp.iter().map(AccessTokenResponse::from_row).collect();
+    Ok(json(&tokens))
+}
+
+pub async fn delete_access_token(token_id: Uuid, user: AuthenticatedUser, db: DBConn) -> Result<impl Reply, Rejection> {
+    let deleted = db.execute(
+        "DELETE FROM personal_access_token WHERE id=$1 AND user_id=$2",
+        &[&token_id, &user.id],
+    ).await.map_err(HttpError::Query)?;
+    if deleted == 0 {
+        return Err(reject::custom(HttpError::NotFound(String::from("Access token not found"))));
+    }
+    Ok(with_status(warp::reply(), StatusCode::NO_CONTENT))
+}
+
+/// Looks up a non expired token and records its use.
+pub async fn find_access_token(token: &str, db: &DBConn) -> Result<Option<AccessTokenOwner>, HttpError> {
+    let resp = db.query(
+        "UPDATE personal_access_token SET last_used_at = now()
+            WHERE token_hash=$1 AND (expires_at IS NULL OR expires_at > now())
+            RETURNING id, user_id, scopes",
+        &[&hash_token(token)],
+    ).await.map_err(HttpError::Query)?;
+
+    Ok(resp.first().map(|row| AccessTokenOwner {
+        id: row.get("id"),
+        user_id: row.get("user_id"),
+        scopes: parse_scopes(row.get("scopes")),
+    }))
+}
+
+/// User of a non expired token, without recording its use.
+pub async fn find_access_token_user(token: &str, db: &DBConn) -> Result<Option<Uuid>, HttpError> {
+    let resp = db.query(
+        "SELECT user_id FROM personal_access_token WHERE token_hash=$1 AND (expires_at IS NULL OR expires_at > now())",
+        &[&hash_token(token)],
+    ).await.map_err(HttpError::Query)?;
+
+    Ok(resp.first().map(|row| row.get("user_id")))
+}
+
+/// Hex encoded SHA-256, enough for random tokens that don't need a slow password hash.
+pub fn hash_token(token: &str) -> String {
+    digest(&SHA256, token.as_bytes())
+        .as_ref()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
@@ -0,0 +1,208 @@
+use tokio_postgres::error::SqlState;
+use uuid::Uuid;
+use warp::{Reply, Rejection, reject};
+use warp::http::StatusCode;
+use warp::reply::{json, with_status};
+use crate::middlewares::auth::AuthenticatedUser;
+use crate::middlewares::error::HttpError;
+use crate::models::SqlQueryResponse;
+use crate::models::category::{CategoryDTO, PartialCategoryDTO, CategoryResponse, StoreDTO, StoreResponse, StoreCategoryOrderDTO};
+use crate::models::item::{Item, PartialItem};
+use crate::services::database::DBConn;
+
+pub async fn create_category(user: AuthenticatedUser, db: DBConn, body: CategoryDTO) -> Result<impl Reply, Rejection> {
+    // appended after the existing categories unless placed explicitly
+    let resp = db.query(
+        "INSERT INTO category (id, owner_id, name, position)
+            VALUES (uuid_generate_v4(), $1, $2, COALESCE($3, (SELECT COALESCE(max(position), 0) + 1 FROM category WHERE owner_id=$1)))
+            ON CONFLICT DO NOTHING
+            RETURNING *",
+        &[&user.id, &body.name, &body.position],
+    ).await.map_err(HttpError::Query)?;
+    let row = resp.first().ok_or_else(|| reject::custom(category_exists()))?;
+
+    Ok(with_status(json(&CategoryResponse::from_row(row)), StatusCode::CREATED))
+}
+
+pub async fn get_categories(user: AuthenticatedUser, db: DBConn) -> Result<impl Reply, Rejection> {
+    let resp = db.query(
+        "SELECT * FROM category WHERE owner_id=$1 ORDER BY position, lower(name)",
+        &[&user.id],
+    ).await.map_err(HttpError::Query)?;
+
+    let categories: Vec<CategoryResponse> = resp.iter().map(CategoryResponse::from_row).collect();
+    Ok(json(&categories))
+}
+
+pub async fn update_category(category_id: Uuid, user: AuthenticatedUser, db: DBConn, body: PartialCategoryDTO) -> Result<impl Reply, Rejection> {
+    let resp = db.query(
+        "UPDATE category SET name=COALESCE($3, name), position=COALESCE($4, position) WHERE id=$1 AND owner_id=$2 RETURNING *",
+        &[&category_id, &user.id, &body.name, &body.position],
+    ).await.map_err(|e| match e.code() {
+        Some(code) if *code == SqlState::UNIQUE_VIOLATION => category_exists(),
+        _ => HttpError::Query(e),
+    })?;
+    let row = resp.first().ok_or_else(|| reject::custom(category_not_found()))?;
+
+    Ok(json(&CategoryResponse::from_row(row)))
+}
+
+/// Items of the category stay on their lists without a category.
+pub async fn delete_category(category_id: Uuid, user: AuthenticatedUser, db: DBConn) -> Result<impl Reply, Rejection> {
+    let deleted = db.execute(
+        "DELETE FROM category WHERE id=$1 AND owner_id=$2",
+        &[&category_id, &user.id],
+    ).await.map_err(HttpError::Query)?;
+    if deleted == 0 {
+        return Err(reject::custom(category_not_found()));
+    }
+
+    Ok(with_status(warp::reply(), StatusCode::NO_CONTENT))
+}
+
+pub async fn create_store(user: AuthenticatedUser, db: DBConn, body: StoreDTO) -> Result<impl Reply, Rejection> {
+    let resp = db.query(
+        "INSERT INTO store (id, owner_id, name) VALUES (uuid_generate_v4(), $1, $2) RETURNING *",
+        &[&user.id, &body.name],
+    ).await.map_err(HttpError::Query)?;
+    let row = resp.first().ok_or(HttpError::InternalServerError)?;
+
+    Ok(with_status(json(&StoreResponse::from_row(row)), StatusCode::CREATED))
+}
+
+pub async fn get_stores(user: AuthenticatedUser, db: DBConn) -> Result<impl Reply, Rejection> {
+    let resp = db.query(
+        "SELECT * FROM store WHERE owner_id=$1 ORDER BY lower(name)",
+        &[&user.id],
+    ).await.map_err(HttpError::Query)?;
+
+    let stores: Vec<StoreResponse> = resp.iter().map(StoreResponse::from_row).collect();
+    Ok(json(&stores))
+}
+
+pub async fn update_store(store_id: Uuid, user: AuthenticatedUser, db: DBConn, body: StoreDTO) -> Result<impl Reply, Rejection> {
+    let resp = db.query(
+        "UPDATE store SET name=$3 WHERE id=$1 AND owner_id=$2 RETURNING *",
+        &[&store_id, &user.id, &body.name],
+    ).await.map_err(HttpError::Query)?;
+    let row = resp.first().ok_or_else(|| reject::custom(store_not_found()))?;
+
+    Ok(json(&StoreResponse::from_row(row)))
+}
+
+pub async fn delete_store(store_id: Uuid, user: AuthenticatedUser, db: DBConn) -> Result<impl Reply, Rejection> {
+    let deleted = db.execute(
+        "DELETE FROM store WHERE id=$1 AND owner_id=$2",
+        &[&store_id, &user.id],
+    ).await.map_err(HttpError::Query)?;
+    if deleted == 0 {
+        return Err(reject::custom(store_not_found()));
+    }
+
+    Ok(with_status(warp::reply(), StatusCode::NO_CONTENT))
+}
+
+/// Categories of the user in the order of the store.
+pub async fn get_store_categories(store_id: Uuid, user: AuthenticatedUser, db: DBConn) -> Result<impl Reply, Rejection> {
+    validate_store_owner(&store_id, &user.id, &db).await?;
+
+    let resp = db.query(
+        "SELECT c.* FROM category c
+            LEFT JOIN store_category sc ON sc.category_id=c.id AND sc.store_id=$1
+            WHERE c.owner_id=$2
+            ORDER BY sc.position NULLS LAST, c.position, lower(c.name)",
+        &[&store_id, &user.id],
+    ).await.map_err(HttpError::Query)?;
+
+    let categories: Vec<CategoryResponse> = resp.iter().map(CategoryResponse::from_row).collect();
+    Ok(json(&categories))
+}
+
+/// Replaces the category order of the store, ids of categories the user doesn't own are ignored.
+pub async fn set_store_categories(store_id: Uuid, user: AuthenticatedUser, mut db: DBConn, body: StoreCategoryOrderDTO) -> Result<impl Reply, Rejection> {
+    validate_store_owner(&store_id, &user.id, &db).await?;
+
+    let transaction = db.transaction().await.map_err(HttpError::Query)?;
+    transaction.execute("DELETE FROM store_category WHERE store_id=$1", &[&store_id]).await.map_err(HttpError::Query)?;
+    transaction.execute(
+        "INSERT INTO store_category (store_id, category_id, position)
+            SELECT $1, c.id, ordered.position::int FROM unnest($2::uuid[]) WITH ORDINALITY AS ordered(id, position)
+            INNER JOIN category c ON c.id=ordered.id AND c.owner_id=$3
+            ON CONFLICT DO NOTHING",
+        &[&store_id, &body.category_ids, &user.id],
+    ).await.map_err(HttpError::Query)?;
+    transaction.commit().await.map_err(HttpError::Query)?;
+
+    Ok(with_status(warp::reply(), StatusCode::NO_CONTENT))
+}
+
+pub async fn validate_store_owner(store_id: &Uuid, user_id: &Uuid, db: &DBConn) -> Result<(), Rejection> {
+    let resp = db.query("SELECT 1 FROM store WHERE id=$1 AND owner_id=$2", &[store_id, user_id])
+        .await.map_err(HttpError::Query)?;
+    if resp.is_empty() {
+        return Err(reject::custom(store_not_found()));
+    }
+    Ok(())
+}
+
+/// Checks the category given to a new item, or gives it the category the user last put
+/// an item of the same name in.
+pub async fn assign_category(user_id: &Uuid, item: &mut Item, db: &DBConn) -> Result<(), Rejection> {
+    match &item.category_id {
+        Some(category_id) => validate_category(category_id, user_id, db).await,
+        None => {
+            let resp = db.query(
+                "SELECT category_id FROM category_assignment WHERE user_id=$1 AND item_name=lower(btrim($2))",
+                &[user_id, &item.name],
+            ).await.map_err(HttpError::Query)?;
+            item.category_id = resp.first().map(|row| row.get("category_id"));
+            Ok(())
+        },
+    }
+}
+
+pub async fn validate_category_changes(user_id: &Uuid, changes: &PartialItem, db: &DBConn) -> Result<(), Rejection> {
+    match &changes.category_id {
+        Some(Some(category_id)) => validate_category(category_id, user_id, db).await,
+        _ => Ok(()),
+    }
+}
+
+/// Remembers the category of the item for the next items of the same name. Only a hint,
+/// so failing to remember it doesn't fail the change of the item.
+pub async fn learn_category(user_id: &Uuid, item: &Item, db: &DBConn) {
+    let category_id = match &item.category_id {
+        Some(category_id) => category_id,
+        None => return,
+    };
+    let learned = db.execute(
+        "INSERT INTO category_assignment (user_id, item_name, category_id) VALUES ($1, lower(btrim($2)), $3)
+            ON CONFLICT (user_id, item_name) DO UPDATE SET category_id=$3, updated_at=now()",
+        &[user_id, &item.name, category_id],
+    ).await;
+    if let Err(e) = learned {
+        println!("Failed to learn category of {} {:?}", item.name, e);
+    }
+}
+
+/// Items can only be put into the categories of the user putting them there.
+async fn validate_category(category_id: &Uuid, user_id: &Uuid, db: &DBConn) -> Result<(), Rejection> {
+    let resp = db.query("SELECT 1 FROM category WHERE id=$1 AND owner_id=$2", &[category_id, user_id])
+        .await.map_err(HttpError::Query)?;
+    if resp.is_empty() {
+        return Err(reject::custom(category_not_found()));
+    }
+    Ok(())
+}
+
+fn category_exists() -> HttpError {
+    HttpError::Conflict(String::from("Category with this name already exists"))
+}
+
+fn category_not_found() -> HttpError {
+    HttpError::NotFound(String::from("Category not found"))
+}
+
+fn store_not_found() -> HttpError {
+    HttpError::NotFound(String::from("Store not found"))
+}
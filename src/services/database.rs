@@ -35,13 +35,18 @@ pub fn init_postgres(tls: NoTls) -> std::result::Result<DBPool, mobc::Error<Erro
 }
 
 pub fn init_redis() -> Result<RedisPool, mobc::Error<Error>> {
-    let url = create_redis_url();
-    let client = redis::Client::open(url).unwrap();
+    let client = init_redis_client();
     let manager = RedisConnectionManager::new(client);
 
     Ok(Pool::builder().build(manager))
 }
 
+/// Client for dedicated connections which can't be shared through the pool, like pub/sub.
+pub fn init_redis_client() -> redis::Client {
+    let url = create_redis_url();
+    redis::Client::open(url).unwrap()
+}
+
 pub async fn get_connection<M: Manager>(pool: Pool<M>, label: &str) -> Result<Connection<M>, Rejection>
     where <M as Manager>::Error: std::fmt::Debug
  {
@@ -0,0 +1,316 @@
+use std::collections::VecDeque;
+use std::convert::Infallible;
+use std::time::Duration;
+use futures::{SinkExt, StreamExt};
+use mobc_redis::redis::{self, AsyncCommands};
+use tokio::sync::broadcast;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::time::{Instant, Interval};
+use uuid::Uuid;
+use warp::{Reply, Rejection};
+use warp::sse::Event;
+use warp::ws::{Message, WebSocket, Ws};
+use crate::middlewares::auth::AuthenticatedUser;
+use crate::middlewares::error::HttpError;
+use crate::models::event::{ListEvent, ListEventMessage};
+use crate::models::sharing::ShareRole;
+use crate::services::database::{DBConn, DBPool, RedisConn, get_connection};
+use crate::services::shopping_list::{get_shopping_list_role, validate_shopping_list_access};
+
+const LIST_EVENTS_CHANNEL: &str = "shopping_list_events";
+const EVENT_BUFFER_SIZE: usize = 1024;
+const RECONNECT_DELAY_SECONDS: u64 = 5;
+// shares can be revoked while a client is connected, so access is checked again periodically
+const ACCESS_RECHECK_SECONDS: u64 = 60;
+// recent events of each list are kept so SSE clients can resume with Last-Event-ID
+const EVENT_HISTORY_LENGTH: usize = 100;
+const EVENT_HISTORY_EXPIRATION_SECONDS: usize = 60 * 60;
+
+/// Events of all shopping lists received by this instance. Every instance subscribes
+/// to the same Redis channel, so changes made through any of them reach all open sockets.
+#[derive(Clone)]
+pub struct ListEvents {
+    sender: broadcast::Sender<ListEventMessage>,
+}
+
+impl ListEvents {
+    pub fn subscribe(&self) -> broadcast::Receiver<ListEventMessage> {
+        self.sender.subscribe()
+    }
+}
+
+/// Events of one list for one client, ends once the list is deleted or the user lost access to it.
+struct ListSubscription {
+    shopping_list_id: Uuid,
+    user_id: Uuid,
+    receiver: broadcast::Receiver<ListEventMessage>,
+    replay: VecDeque<ListEventMessage>,
+    last_replayed_id: Option<(u64, u64)>,
+    recheck: Interval,
+    pg_pool: DBPool,
+    is_finished: bool,
+}
+
+impl ListSubscription {
+    fn new(shopping_list_id: Uuid, user_id: Uuid, events: &ListEvents, pg_pool: DBPool) -> Self {
+        let period = Duration::from_secs(ACCESS_RECHECK_SECONDS);
+        let recheck = tokio::time::interval_at(Instant::now() + period, period);
+        Self {
+            shopping_list_id,
+            user_id,
+            receiver: events.subscribe(),
+            replay: VecDeque::new(),
+            last_replayed_id: None,
+            recheck,
+            pg_pool,
+            is_finished: false,
+        }
+    }
+
+    /// Missed events are sent before live ones, live events already replayed are skipped.
+    fn replay(&mut self, missed: Vec<ListEventMessage>) {
+        self.last_replayed_id = missed.iter().filter_map(|message| parse_event_id(message.id.as_deref())).max();
+        self.replay.extend(missed);
+    }
+
+    async fn next(&mut self) -> Option<ListEventMessage> {
+        if self.is_finished {
+            return None;
+        }
+        if let Some(message) = self.replay.pop_front() {
+            return Some(self.finish_if_deleted(message));
+        }
+
+        loop {
+            tokio::select! {
+                received = self.receiver.recv() => {
+                    match received {
+                        Ok(message) if message.shopping_list_id != self.shopping_list_id => {}
+                        Ok(message) if self.was_replayed(&message) => {}
+                        Ok(message) => return Some(self.finish_if_deleted(message)),
+                        Err(RecvError::Lagged(_skipped)) => return Some(ListEventMessage::resync(self.shopping_list_id)),
+                        Err(RecvError::Closed) => return None,
+                    }
+                }
+                _ = self.recheck.tick() => {
+                    if !has_access(&self.shopping_list_id, &self.user_id, &self.pg_pool).await {
+                        self.is_finished = true;
+                        return None;
+                    }
+                }
+            }
+        }
+    }
+
+    fn was_replayed(&self, message: &ListEventMessage) -> bool {
+        match (self.last_replayed_id, parse_event_id(message.id.as_deref())) {
+            (Some(last_replayed_id), Some(id)) => id <= last_replayed_id,
+            _ => false,
+        }
+    }
+
+    fn finish_if_deleted(&mut self, message: ListEventMessage) -> ListEventMessage {
+        if matches!(message.event, ListEvent::ListDeleted) {
+            self.is_finished = true;
+        }
+        message
+    }
+}
+
+/// Starts forwarding events from Redis, must be called from within the runtime.
+pub fn init_list_events(client: redis::Client) -> ListEvents {
+    let (sender, _) = broadcast::channel(EVENT_BUFFER_SIZE);
+    tokio::spawn(subscribe_list_events(client, sender.clone()));
+    ListEvents { sender }
+}
+
+/// Records the event in the history of the list and publishes it. Failing to do so
+/// doesn't fail the change itself, clients only miss the live update.
+pub async fn publish_list_event(redis: &mut RedisConn, mut message: ListEventMessage) {
+    let history_key = get_history_key(&message.shopping_list_id);
+    let recorded: Result<String, _> = match serde_json::to_string(&message) {
+        Ok(payload) => redis::cmd("XADD")
+            .arg(&history_key)
+            .arg("MAXLEN")
+            .arg("~")
+            .arg(EVENT_HISTORY_LENGTH)
+            .arg("*")
+            .arg("event")
+            .arg(payload)
+            .query_async(&mut **redis)
+            .await,
+        Err(e) => {
+            println!("Failed to serialize list event {:?}", e);
+            return;
+        }
+    };
+    match recorded {
+        Ok(id) => {
+            message.id = Some(id);
+            let expired: Result<(), _> = redis.expire(&history_key, EVENT_HISTORY_EXPIRATION_SECONDS).await;
+            if let Err(e) = expired {
+                println!("Failed to expire list event history {:?}", e);
+            }
+        },
+        Err(e) => println!("Failed to record list event {:?}", e),
+    }
+
+    let payload = match serde_json::to_string(&message) {
+        Ok(payload) => payload,
+        Err(e) => {
+            println!("Failed to serialize list event {:?}", e);
+            return;
+        }
+    };
+    let published: Result<(), _> = redis.publish(LIST_EVENTS_CHANNEL, payload).await;
+    if let Err(e) = published {
+        println!("Failed to publish list event {:?}", e);
+    }
+}
+
+pub async fn list_socket(
+    shopping_list_id: Uuid,
+    user: AuthenticatedUser,
+    db: DBConn,
+    pg_pool: DBPool,
+    events: ListEvents,
+    ws: Ws,
+) -> Result<impl Reply, Rejection> {
+    validate_shopping_list_access(&shopping_list_id, &user.id, ShareRole::VIEWER, &db).await?;
+
+    let subscription = ListSubscription::new(shopping_list_id, user.id, &events, pg_pool);
+    Ok(ws.on_upgrade(move |socket| stream_list_events(socket, subscription)))
+}
+
+/// Same events as the WebSocket, with `Last-Event-ID` replaying the ones missed while disconnected.
+pub async fn list_event_stream(
+    shopping_list_id: Uuid,
+    user: AuthenticatedUser,
+    db: DBConn,
+    mut redis: RedisConn,
+    pg_pool: DBPool,
+    events: ListEvents,
+    last_event_id: Option<String>,
+) -> Result<impl Reply, Rejection> {
+    validate_shopping_list_access(&shopping_list_id, &user.id, ShareRole::VIEWER, &db).await?;
+
+    // subscribed before reading the history, so no event falls in between
+    let mut subscription = ListSubscription::new(shopping_list_id, user.id, &events, pg_pool);
+    if let Some(last_event_id) = last_event_id {
+        match get_missed_events(&shopping_list_id, &last_event_id, &mut redis).await? {
+            Some(missed) => subscription.replay(missed),
+            None => subscription.replay(vec![ListEventMessage::resync(shopping_list_id)]),
+        }
+    }
+
+    let stream = futures::stream::unfold(subscription, |mut subscription| async move {
+        let message = subscription.next().await?;
+        Some((Ok::<Event, Infallible>(to_sse_event(&message)), subscription))
+    });
+    Ok(warp::sse::reply(warp::sse::keep_alive().stream(stream)))
+}
+
+async fn stream_list_events(socket: WebSocket, mut subscription: ListSubscription) {
+    let (mut outgoing, mut incoming) = socket.split();
+
+    loop {
+        tokio::select! {
+            message = subscription.next() => {
+                let text = match message.as_ref().map(serde_json::to_string) {
+                    Some(Ok(text)) => text,
+                    Some(Err(_e)) => continue,
+                    None => break,
+                };
+                if outgoing.send(Message::text(text)).await.is_err() {
+                    break;
+                }
+            }
+            message = incoming.next() => {
+                match message {
+                    Some(Ok(message)) if message.is_close() => break,
+                    Some(Ok(_message)) => {}
+                    _ => break,
+                }
+            }
+        }
+    }
+
+    let _ = outgoing.send(Message::close()).await;
+}
+
+fn to_sse_event(message: &ListEventMessage) -> Event {
+    let event = Event::default()
+        .json_data(message)
+        .unwrap_or_else(|_e| Event::default().comment("invalid event"));
+    match &message.id {
+        Some(id) => event.id(id.as_str()),
+        None => event,
+    }
+}
+
+/// Events recorded after `last_event_id`, `None` when that event is no longer in the history
+/// so it's unknown what was missed.
+async fn get_missed_events(shopping_list_id: &Uuid, last_event_id: &str, redis: &mut RedisConn) -> Result<Option<Vec<ListEventMessage>>, HttpError> {
+    let entries: Vec<(String, Vec<String>)> = redis::cmd("XRANGE")
+        .arg(get_history_key(shopping_list_id))
+        .arg("-")
+        .arg("+")
+        .query_async(&mut **redis)
+        .await
+        .map_err(HttpError::Redis)?;
+
+    let position = match entries.iter().position(|(id, _fields)| id == last_event_id) {
+        Some(position) => position,
+        None => return Ok(None),
+    };
+    let missed = entries[position + 1..]
+        .iter()
+        .filter_map(|(id, fields)| {
+            let mut message: ListEventMessage = serde_json::from_str(fields.get(1)?).ok()?;
+            message.id = Some(id.clone());
+            Some(message)
+        })
+        .collect();
+    Ok(Some(missed))
+}
+
+/// Redis stream ids are `<millis>-<sequence>`.
+fn parse_event_id(id: Option<&str>) -> Option<(u64, u64)> {
+    let (millis, sequence) = id?.split_once('-')?;
+    Some((millis.parse().ok()?, sequence.parse().ok()?))
+}
+
+async fn has_access(shopping_list_id: &Uuid, user_id: &Uuid, pg_pool: &DBPool) -> bool {
+    match get_connection(pg_pool.clone(), "postgres").await {
+        Ok(db) => get_shopping_list_role(shopping_list_id, user_id, &db).await.is_some(),
+        Err(_e) => false,
+    }
+}
+
+async fn subscribe_list_events(client: redis::Client, sender: broadcast::Sender<ListEventMessage>) {
+    loop {
+        if let Err(e) = forward_list_events(&client, &sender).await {
+            println!("List events subscription failed {:?}", e);
+        }
+        tokio::time::sleep(Duration::from_secs(RECONNECT_DELAY_SECONDS)).await;
+    }
+}
+
+async fn forward_list_events(client: &redis::Client, sender: &broadcast::Sender<ListEventMessage>) -> redis::RedisResult<()> {
+    let mut pubsub = client.get_async_connection().await?.into_pubsub();
+    pubsub.subscribe(LIST_EVENTS_CHANNEL).await?;
+    let mut messages = pubsub.on_message();
+    while let Some(message) = messages.next().await {
+        let payload: String = message.get_payload()?;
+        match serde_json::from_str::<ListEventMessage>(&payload) {
+            // no open sockets is not an error
+            Ok(event) => { let _ = sender.send(event); },
+            Err(e) => println!("Invalid list event {:?}", e),
+        }
+    }
+    Ok(())
+}
+
+fn get_history_key(shopping_list_id: &Uuid) -> String {
+    format!("shopping_list_events:{}", shopping_list_id)
+}
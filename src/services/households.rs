@@ -0,0 +1,193 @@
+use uuid::Uuid;
+use warp::{Reply, Rejection, reject};
+use warp::http::StatusCode;
+use warp::reply::{json, with_status};
+use crate::middlewares::auth::AuthenticatedUser;
+use crate::middlewares::error::HttpError;
+use crate::models::{Model, SqlQueryResponse};
+use crate::models::household::{HouseholdDTO, HouseholdResponse, HouseholdMemberDTO, ListHouseholdDTO};
+use crate::models::sharing::{ShareRole, SharedUserResponse, DEFAULT_SHARE_ROLE};
+use crate::models::shopping_list::ShoppingList;
+use crate::services::database::{DBConn, RedisConn};
+use crate::services::events::publish_list_event;
+use crate::models::event::{ListEvent, ListEventMessage};
+use crate::services::shopping_list::has_shopping_list;
+
+/// The owner is a member as well and gets `MANAGER` on the household's lists.
+pub async fn create_household(user: AuthenticatedUser, mut db: DBConn, body: HouseholdDTO) -> Result<impl Reply, Rejection> {
+    let transaction = db.transaction().await.map_err(HttpError::Query)?;
+    let resp = transaction.query(
+        "INSERT INTO household (id, name, owner_id) VALUES (uuid_generate_v4(), $1, $2) RETURNING *, $3::text AS role",
+        &[&body.name, &user.id, &ShareRole::MANAGER.to_string()],
+    ).await.map_err(HttpError::Query)?;
+    let row = resp.first().ok_or(HttpError::InternalServerError)?;
+    let household = HouseholdResponse::from_row(row);
+    transaction.execute(
+        "INSERT INTO household_member (household_id, user_id, role) VALUES ($1, $2, $3)",
+        &[&household.id, &user.id, &household.role.to_string()],
+    ).await.map_err(HttpError::Query)?;
+    transaction.commit().await.map_err(HttpError::Query)?;
+
+    Ok(with_status(json(&household), StatusCode::CREATED))
+}
+
+pub async fn get_households(user: AuthenticatedUser, db: DBConn) -> Result<impl Reply, Rejection> {
+    let resp = db.query(
+        "SELECT h.*, m.role FROM household h
+            INNER JOIN household_member m ON m.household_id=h.id AND m.user_id=$1
+            ORDER BY h.created_at",
+        &[&user.id],
+    ).await.map_err(HttpError::Query)?;
+
+    let households: Vec<HouseholdResponse> = resp.iter().map(HouseholdResponse::from_row).collect();
+    Ok(json(&households))
+}
+
+pub async fn update_household(household_id: Uuid, user: AuthenticatedUser, db: DBConn, body: HouseholdDTO) -> Result<impl Reply, Rejection> {
+    validate_household_owner(&household_id, &user.id, &db).await?;
+
+    let resp = db.query(
+        "UPDATE household h SET name=$2 FROM household_member m
+            WHERE h.id=$1 AND m.household_id=h.id AND m.user_id=$3
+            RETURNING h.*, m.role",
+        &[&household_id, &body.name, &user.id],
+    ).await.map_err(HttpError::Query)?;
+    let row = resp.first().ok_or(HttpError::InternalServerError)?;
+
+    Ok(json(&HouseholdResponse::from_row(row)))
+}
+
+/// Lists of the household stay with their owners, they are only detached from it.
+pub async fn delete_household(household_id: Uuid, user: AuthenticatedUser, db: DBConn) -> Result<impl Reply, Rejection> {
+    validate_household_owner(&household_id, &user.id, &db).await?;
+
+    db.execute("DELETE FROM household WHERE id=$1", &[&household_id]).await.map_err(HttpError::Query)?;
+    Ok(with_status(warp::reply(), StatusCode::NO_CONTENT))
+}
+
+pub async fn get_members(household_id: Uuid, user: AuthenticatedUser, db: DBConn) -> Result<impl Reply, Rejection> {
+    validate_household_membership(&household_id, &user.id, &db).await?;
+
+    let resp = db.query(
+        "SELECT u.*, m.role FROM users u
+            INNER JOIN household_member m ON m.user_id=u.id AND m.household_id=$1
+            ORDER BY u.username",
+        &[&household_id],
+    ).await.map_err(HttpError::Query)?;
+
+    let members: Vec<SharedUserResponse> = resp.iter().map(SharedUserResponse::from_row).collect();
+    Ok(json(&members))
+}
+
+pub async fn add_member(household_id: Uuid, user: AuthenticatedUser, db: DBConn, body: HouseholdMemberDTO) -> Result<impl Reply, Rejection> {
+    validate_household_owner(&household_id, &user.id, &db).await?;
+
+    let role = body.role.unwrap_or(DEFAULT_SHARE_ROLE);
+    let inserted = db.execute(
+        "INSERT INTO household_member (household_id, user_id, role) VALUES ($1, $2, $3) ON CONFLICT DO NOTHING",
+        &[&household_id, &body.target_user_id, &role.to_string()],
+    ).await.map_err(HttpError::Query)?;
+    if inserted == 0 {
+        let msg = String::from("User is already a member of this household");
+        return Err(reject::custom(HttpError::Conflict(msg)));
+    }
+
+    Ok(with_status(warp::reply(), StatusCode::CREATED))
+}
+
+pub async fn update_member(household_id: Uuid, user: AuthenticatedUser, db: DBConn, body: HouseholdMemberDTO) -> Result<impl Reply, Rejection> {
+    validate_household_owner(&household_id, &user.id, &db).await?;
+    if body.target_user_id == user.id {
+        let msg = String::from("Role of the household owner can't be changed");
+        return Err(reject::custom(HttpError::Conflict(msg)));
+    }
+
+    let role = body.role.unwrap_or(DEFAULT_SHARE_ROLE);
+    let updated = db.execute(
+        "UPDATE household_member SET role=$3 WHERE household_id=$1 AND user_id=$2",
+        &[&household_id, &body.target_user_id, &role.to_string()],
+    ).await.map_err(HttpError::Query)?;
+    if updated == 0 {
+        return Err(reject::custom(HttpError::NotFound(String::from("Member not found"))));
+    }
+
+    Ok(with_status(warp::reply(), StatusCode::NO_CONTENT))
+}
+
+/// The owner removes members, any other member may only remove themselves to leave the household.
+pub async fn remove_member(household_id: Uuid, member_id: Uuid, user: AuthenticatedUser, db: DBConn) -> Result<impl Reply, Rejection> {
+    let owner_id = get_household_owner(&household_id, &user.id, &db).await?;
+    if owner_id != user.id && member_id != user.id {
+        let msg = String::from("Only the owner can remove household members");
+        return Err(reject::custom(HttpError::Forbidden(msg)));
+    }
+    if member_id == owner_id {
+        let msg = String::from("Owner can't leave the household, delete it instead");
+        return Err(reject::custom(HttpError::Conflict(msg)));
+    }
+
+    let deleted = db.execute(
+        "DELETE FROM household_member WHERE household_id=$1 AND user_id=$2",
+        &[&household_id, &member_id],
+    ).await.map_err(HttpError::Query)?;
+    if deleted == 0 {
+        return Err(reject::custom(HttpError::NotFound(String::from("Member not found"))));
+    }
+
+    Ok(with_status(warp::reply(), StatusCode::NO_CONTENT))
+}
+
+/// Moves a list into a household of its owner, or out of any household with `householdId: null`.
+pub async fn set_list_household(shopping_list_id: Uuid, user: AuthenticatedUser, db: DBConn, mut redis: RedisConn, body: ListHouseholdDTO) -> Result<impl Reply, Rejection> {
+    if !has_shopping_list(&shopping_list_id, &user.id, &db).await {
+        let msg = String::from("Only the owner can assign a list to a household");
+        return Err(reject::custom(HttpError::Forbidden(msg)));
+    }
+    if let Some(household_id) = &body.household_id {
+        validate_household_membership(household_id, &user.id, &db).await?;
+    }
+
+    let resp = db.query(
+        "UPDATE shopping_list SET household_id=$2, version=version + 1 WHERE id=$1 RETURNING *",
+        &[&shopping_list_id, &body.household_id],
+    ).await.map_err(HttpError::Query)?;
+    let row = resp.first().ok_or(HttpError::InternalServerError)?;
+    let list = ShoppingList::from_row(row);
+
+    let event = ListEvent::ListChanged { list: list.clone() };
+    publish_list_event(&mut redis, ListEventMessage::new(shopping_list_id, user.id, event)).await;
+    Ok(json(&list))
+}
+
+pub async fn validate_household_membership(household_id: &Uuid, user_id: &Uuid, db: &DBConn) -> Result<(), Rejection> {
+    let resp = db.query(
+        "SELECT 1 FROM household_member WHERE household_id=$1 AND user_id=$2",
+        &[household_id, user_id],
+    ).await.map_err(HttpError::Query)?;
+    if resp.is_empty() {
+        return Err(reject::custom(HttpError::NotFound(String::from("Household not found"))));
+    }
+    Ok(())
+}
+
+async fn validate_household_owner(household_id: &Uuid, user_id: &Uuid, db: &DBConn) -> Result<(), Rejection> {
+    let owner_id = get_household_owner(household_id, user_id, db).await?;
+    if owner_id != *user_id {
+        let msg = String::from("Only the owner can manage this household");
+        return Err(reject::custom(HttpError::Forbidden(msg)));
+    }
+    Ok(())
+}
+
+/// Households of other users are reported as not found, so their ids can't be probed.
+async fn get_household_owner(household_id: &Uuid, user_id: &Uuid, db: &DBConn) -> Result<Uuid, Rejection> {
+    let resp = db.query(
+        "SELECT h.owner_id FROM household h
+            INNER JOIN household_member m ON m.household_id=h.id AND m.user_id=$2
+            WHERE h.id=$1",
+        &[household_id, user_id],
+    ).await.map_err(HttpError::Query)?;
+    resp.first()
+        .map(|row| row.get("owner_id"))
+        .ok_or_else(|| reject::custom(HttpError::NotFound(String::from("Household not found"))))
+}
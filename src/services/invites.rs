@@ -0,0 +1,111 @@
+use rand::Rng;
+use rand::distributions::Alphanumeric;
+use uuid::Uuid;
+use warp::{Reply, Rejection, reject};
+use warp::http::StatusCode;
+use warp::reply::{json, with_status};
+use crate::middlewares::auth::AuthenticatedUser;
+use crate::middlewares::error::HttpError;
+use crate::models::{Model, SqlQueryResponse};
+use crate::models::invite::{InviteDTO, InviteResponse, CreatedInviteResponse};
+use crate::models::sharing::{ShareRole, DEFAULT_SHARE_ROLE};
+use crate::models::shopping_list::ShoppingList;
+use crate::services::access_tokens::hash_token;
+use crate::services::database::DBConn;
+use crate::services::shopping_list::{get_shopping_list_role, validate_shopping_list_access};
+
+const INVITE_TOKEN_LENGTH: usize = 32;
+const DEFAULT_INVITE_EXPIRATION_HOURS: i32 = 72;
+
+// an invite is pending until it expires or, when single use, until it was accepted once
+const PENDING_INVITE_CONDITION: &str = "expires_at > now() AND (NOT single_use OR use_count = 0)";
+
+pub async fn create_invite(shopping_list_id: Uuid, user: AuthenticatedUser, db: DBConn, body: InviteDTO) -> Result<impl Reply, Rejection> {
+    validate_shopping_list_access(&shopping_list_id, &user.id, ShareRole::MANAGER, &db).await?;
+
+    let token: String = rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(INVITE_TOKEN_LENGTH)
+        .map(char::from)
+        .collect();
+    let role = body.role.unwrap_or(DEFAULT_SHARE_ROLE);
+    let expires_in_hours = body.expires_in_hours.unwrap_or(DEFAULT_INVITE_EXPIRATION_HOURS);
+    let single_use = body.single_use.unwrap_or(false);
+
+    let resp = db.query(
+        "INSERT INTO shopping_list_invite (id, shopping_list_id, created_by, token_hash, role, single_use, expires_at)
+            VALUES (uuid_generate_v4(), $1, $2, $3, $4, $5, now() + make_interval(hours => $6::int))
+            RETURNING *",
+        &[&shopping_list_id, &user.id, &hash_token(&token), &role.to_string(), &single_use, &expires_in_hours],
+    ).await.map_err(HttpError::Query)?;
+    let row = resp.first().ok_or(HttpError::InternalServerError)?;
+
+    let response = CreatedInviteResponse {
+        invite: InviteResponse::from_row(row),
+        token,
+    };
+    Ok(with_status(json(&response), StatusCode::CREATED))
+}
+
+pub async fn get_invites(shopping_list_id: Uuid, user: AuthenticatedUser, db: DBConn) -> Result<impl Reply, Rejection> {
+    validate_shopping_list_access(&shopping_list_id, &user.id, ShareRole::MANAGER, &db).await?;
+
+    let query = format!(
+        "SELECT * FROM shopping_list_invite WHERE shopping_list_id=$1 AND {} ORDER BY created_at DESC",
+        PENDING_INVITE_CONDITION,
+    );
+    let resp = db.query(query.as_str(), &[&shopping_list_id]).await.map_err(HttpError::Query)?;
+
+    let invites: Vec<InviteResponse> = resp.iter().map(InviteResponse::from_row).collect();
+    Ok(json(&invites))
+}
+
+pub async fn delete_invite(shopping_list_id: Uuid, invite_id: Uuid, user: AuthenticatedUser, db: DBConn) -> Result<impl Reply, Rejection> {
+    validate_shopping_list_access(&shopping_list_id, &user.id, ShareRole::MANAGER, &db).await?;
+
+    let deleted = db.execute(
+        "DELETE FROM shopping_list_invite WHERE id=$1 AND shopping_list_id=$2",
+        &[&invite_id, &shopping_list_id],
+    ).await.map_err(HttpError::Query)?;
+    if deleted == 0 {
+        return Err(reject::custom(HttpError::NotFound(String::from("Invite not found"))));
+    }
+    Ok(with_status(warp::reply(), StatusCode::NO_CONTENT))
+}
+
+/// Shares the list of the invite with the caller. The use is counted in the same statement
+/// that creates the share, so a single use invite can't be accepted twice.
+pub async fn accept_invite(token: String, user: AuthenticatedUser, db: DBConn) -> Result<impl Reply, Rejection> {
+    let token_hash = hash_token(&token);
+    let query = format!("SELECT shopping_list_id FROM shopping_list_invite WHERE token_hash=$1 AND {}", PENDING_INVITE_CONDITION);
+    let resp = db.query(query.as_str(), &[&token_hash]).await.map_err(HttpError::Query)?;
+    let shopping_list_id: Uuid = resp.first()
+        .map(|row| row.get("shopping_list_id"))
+        .ok_or_else(|| reject::custom(HttpError::NotFound(String::from("Invite not found or expired"))))?;
+
+    if get_shopping_list_role(&shopping_list_id, &user.id, &db).await.is_some() {
+        let msg = String::from("You already have access to this list");
+        return Err(reject::custom(HttpError::Conflict(msg)));
+    }
+
+    let query = format!(
+        "WITH invite AS (
+            UPDATE shopping_list_invite SET use_count = use_count + 1
+                WHERE token_hash=$1 AND {}
+                RETURNING shopping_list_id, role
+        )
+        INSERT INTO shopping_list_share (shopping_list_id, target_user_id, role)
+            SELECT shopping_list_id, $2, role FROM invite
+            RETURNING shopping_list_id",
+        PENDING_INVITE_CONDITION,
+    );
+    let accepted = db.execute(query.as_str(), &[&token_hash, &user.id]).await.map_err(HttpError::Query)?;
+    if accepted == 0 {
+        return Err(reject::custom(HttpError::NotFound(String::from("Invite not found or expired"))));
+    }
+
+    let resp = db.query("SELECT * FROM shopping_list WHERE id=$1", &[&shopping_list_id])
+        .await.map_err(HttpError::Query)?;
+    let row = resp.first().ok_or(HttpError::InternalServerError)?;
+    Ok(json(&ShoppingList::from_row(row)))
+}
@@ -1,124 +1,723 @@
-use crate::models::item::{Item, PartialUpdateResponse, PartialItem};
-use crate::services::database::{DBConn};
+use crate::models::item::{Item, PartialUpdateResponse, PartialItem, CreateItemsQuery, ItemError, ItemOperation, BatchItemsResponse, MovedItem, ItemQuery, ItemSort, SortOrder, ItemPositionDTO};
+use crate::services::database::{DBConn, RedisConn};
+use crate::services::events::publish_list_event;
+use crate::models::event::{ListEvent, ListEventMessage};
 use warp::{reply, reject, Rejection};
-use crate::models::{Pagination,Model};
+use crate::models::{QueryResponse, Model};
+use crate::models::category::{CategoryResponse, ItemGroup, GroupedItemsResponse};
 use uuid::Uuid;
 use crate::services::shopping_list::{validate_shopping_list_access};
+use crate::services::categories::{assign_category, validate_category_changes, learn_category, validate_store_owner};
+use crate::services::prices::{estimate_price, record_price};
+use crate::models::sharing::ShareRole;
 use warp::http::StatusCode;
-use std::convert::TryFrom;
-use crate::middlewares::error::HttpError;
+use tokio_postgres::types::ToSql;
+use crate::middlewares::error::{HttpError, error_match};
 use crate::middlewares::auth::AuthenticatedUser;
+use crate::middlewares::precondition::{IfMatch, with_etag, precondition_failed, MAX_UPDATE_ATTEMPTS};
 
-pub async fn get_items(shopping_list_id: Uuid, owner: AuthenticatedUser, db: DBConn, pagination: Pagination) -> Result<impl warp::Reply, Rejection> {
-    validate_shopping_list_access(&shopping_list_id, &owner.id, &db).await?;
+// space left between neighbouring items, see the item positions migration
+const POSITION_GAP: i64 = 65536;
+const UPDATE_ITEM_QUERY: &str = "
+    UPDATE item SET (name, description, current_amount, total_amount, bought, unit, tags, category_id, unit_price, currency, store_id, version)
+        =($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, version + 1) WHERE id=$12 AND version=$13
+        RETURNING *
+";
 
-    let mut data = Vec::new();
+pub async fn get_items(shopping_list_id: Uuid, owner: AuthenticatedUser, db: DBConn, query: ItemQuery) -> Result<impl warp::Reply, Rejection> {
+    validate_shopping_list_access(&shopping_list_id, &owner.id, ShareRole::VIEWER, &db).await?;
+    if let Some(store_id) = &query.store_id {
+        validate_store_owner(store_id, &owner.id, &db).await?;
+    }
+
+    let pagination = query.get_pagination();
     let limit = pagination.get_limit(10);
     let offset = pagination.get_offset();
+    let tags = query.get_tags();
+    let search = query.search.as_deref().map(get_search_pattern);
+
+    let mut conditions = vec![String::from("item.shopping_list_id=$1")];
+    let mut params: Vec<&(dyn ToSql + Sync)> = vec![&shopping_list_id];
+    if let Some(bought) = &query.bought {
+        params.push(bought);
+        conditions.push(format!("item.bought=${}", params.len()));
+    }
+    if !tags.is_empty() {
+        params.push(&tags);
+        conditions.push(format!("item.tags && ${}", params.len()));
+    }
+    if let Some(search) = &search {
+        params.push(search);
+        conditions.push(format!("(item.name ILIKE ${0} OR item.description ILIKE ${0})", params.len()));
+    }
+    let filter = conditions.join(" AND ");
+
+    let total_count = db.query(format!("SELECT count(*)::int FROM item WHERE {}", filter).as_str(), &params)
+        .await.map_err(HttpError::Query)?;
+    let total: i32 = total_count.first().ok_or(HttpError::InternalServerError)?.get(0);
+
+    let sort = match query.sort.unwrap_or(ItemSort::POSITION) {
+        ItemSort::NAME => "lower(item.name)",
+        ItemSort::UNIT => "item.unit",
+        ItemSort::CREATED => "item.created_at",
+        ItemSort::POSITION => "item.position",
+    };
+    let order = match query.order.unwrap_or(SortOrder::ASC) {
+        SortOrder::ASC => "ASC",
+        SortOrder::DESC => "DESC",
+    };
+    let grouped = query.grouped.unwrap_or(false);
+    // categories in the order of the store, then their own order, items without one last
+    let (select, sort) = if !grouped {
+        (String::from("SELECT item.* FROM item"), format!("{} {}", sort, order))
+    } else if let Some(store_id) = &query.store_id {
+        params.push(store_id);
+        let select = format!(
+            "SELECT item.*, c.name AS category_name, c.position AS category_position FROM item
+                LEFT JOIN category c ON c.id=item.category_id
+                LEFT JOIN store_category sc ON sc.category_id=item.category_id AND sc.store_id=${}",
+            params.len(),
+        );
+        (select, format!("c.id IS NULL, sc.position NULLS LAST, c.position, lower(c.name), c.id, {} {}", sort, order))
+    } else {
+        let select = String::from(
+            "SELECT item.*, c.name AS category_name, c.position AS category_position FROM item
+                LEFT JOIN category c ON c.id=item.category_id",
+        );
+        (select, format!("c.id IS NULL, c.position, lower(c.name), c.id, {} {}", sort, order))
+    };
+    params.push(&limit);
+    params.push(&offset);
+    // the id keeps pages stable between items that sort the same
     let rows = db.query(
-        "SELECT * FROM item WHERE shopping_list_id=$1 LIMIT $2::int OFFSET $3::int",
-        &[&shopping_list_id, &limit, &offset],
-    ).await.map_err(|error| reject::custom(HttpError::Query(error) ))?;
+        format!(
+            "{} WHERE {} ORDER BY {}, item.id LIMIT ${}::int OFFSET ${}::int",
+            select, filter, sort, params.len() - 1, params.len(),
+        ).as_str(),
+        &params,
+    ).await.map_err(HttpError::Query)?;
 
+    let to_item = |row| {
+        let mut item = Item::from_row(row);
+        if let Some(system) = query.unit_system {
+            item.convert_to(system);
+        }
+        item
+    };
+    if !grouped {
+        let items: Vec<Item> = rows.iter().map(to_item).collect();
+        return Ok(reply::json(&QueryResponse::new(items, total)));
+    }
+    let mut groups: Vec<ItemGroup> = Vec::new();
     for row in rows.iter() {
-        let one_item = Item::from_row(row);
-        data.push(one_item);
+        let item = to_item(row);
+        match groups.last_mut() {
+            Some(group) if group.category.as_ref().map(|category| category.id) == item.category_id => group.items.push(item),
+            _ => {
+                let category = item.category_id.map(|id| CategoryResponse {
+                    id,
+                    name: row.get("category_name"),
+                    position: row.get("category_position"),
+                });
+                groups.push(ItemGroup { category, items: vec![item] });
+            },
+        }
     }
-
-    Ok(reply::json(&data))
+    Ok(reply::json(&GroupedItemsResponse { groups, total }))
 }
 
-pub async fn create_items(id: Uuid, owner: AuthenticatedUser, db: DBConn, items: Vec<Item>) -> Result<impl warp::Reply, Rejection> {
-    validate_shopping_list_access(&id, &owner.id, &db).await?;
+/// Items are added one by one and failures are reported next to the added items,
+/// unless `atomic` is set, then they're all added at once or the request fails with
+/// the errors of the failing items.
+pub async fn create_items(id: Uuid, owner: AuthenticatedUser, mut db: DBConn, mut redis: RedisConn, query: CreateItemsQuery, mut items: Vec<Item>) -> Result<impl warp::Reply, Rejection> {
+    validate_shopping_list_access(&id, &owner.id, ShareRole::EDITOR, &db).await?;
 
     let mut rows: Vec<Item> = Vec::new();
-    let mut errors: Vec<String> = Vec::new();
-    for item in items.iter() {
-        // let current_amount = f32::try_from(item.current_amount).expect("Failed to cast current_amount");
-        // let total_amount = f32::try_from(item.total_amount).expect("Failed to cast total_amount");
-        let item_rows = db.query(
-            "
-                INSERT INTO item (id, name, description, current_amount, total_amount, bought, unit, tags, shopping_list_id)
-                VALUES (uuid_generate_v4(), $1, $2, $3, $4, $5, $6, $7, $8)
-                RETURNING *
-            ",
-            &[
-                        &item.name,
-                        &item.description,
-                        &item.current_amount,
-                        &item.total_amount,
-                        &item.bought,
-                        &item.unit.to_string().as_str(),
-                        &item.tags,
-                        &id,
-                    ]
-        ).await;
-
-        match item_rows {
-            Ok(item) => {
-                for item_row in item {
-                    let item = Item::from_row(&item_row);
-                    rows.push(item);
-                }
+    let mut errors: Vec<ItemError> = Vec::new();
+    if query.atomic.unwrap_or(false) {
+        // every failing item is reported, with the status of the first failure, and none is added
+        let mut status: Option<StatusCode> = None;
+        for (index, item) in items.iter_mut().enumerate() {
+            if let Err(e) = prepare_item(&owner.id, item, &db).await {
+                let (error_status, error) = to_item_error(index, item, &e);
+                status.get_or_insert(error_status);
+                errors.push(error);
             }
-            Err(e) => {
-                println!("Failed to insert item {} because of Error: {:?}", item.name, e);
-                errors.push(format!("Insert failed for item {}", item.name))
+        }
+        if let Some(status) = status {
+            let response = PartialUpdateResponse { items: Vec::new(), errors, merged: Vec::new() };
+            return Ok(warp::reply::with_status(reply::json(&response), status));
+        }
+        rows = insert_items(&id, &items, &mut db).await?;
+    } else {
+        for (index, item) in items.iter_mut().enumerate() {
+            if let Err(e) = prepare_item(&owner.id, item, &db).await {
+                errors.push(to_item_error(index, item, &e).1);
+                continue;
+            }
+            match insert_item(&id, None, item, &db).await {
+                Ok(item) => rows.push(item),
+                Err(e) => {
+                    println!("Failed to insert item {} because of Error: {:?}", item.name, e);
+                    errors.push(ItemError { index, message: format!("Insert failed for item {}", item.name) })
+                }
             }
         }
     }
+    for item in rows.iter() {
+        learn_category(&owner.id, item, &db).await;
+    }
+
+    let created_ids: Vec<Uuid> = rows.iter().filter_map(get_item_id).collect();
+    let mut result = BatchItemsResponse::default();
+    if query.merge.unwrap_or(false) && !rows.is_empty() {
+        // the items are added already, failing to merge them leaves them as they are
+        match merge_items(&id, Some(&created_ids), &mut db).await {
+            Ok(merge_result) => result = merge_result,
+            Err(e) => println!("Failed to merge items into list {} because of Error: {:?}", id, e),
+        }
+    }
+    // new items end up either merged into an older one or holding the merged amounts
+    rows.retain(|item| !matches!(get_item_id(item), Some(item_id) if result.deleted.contains(&item_id)));
+    for updated in result.updated.into_iter() {
+        match rows.iter_mut().find(|item| item.id == updated.id) {
+            Some(item) => *item = updated,
+            None => {
+                let event = ListEvent::ItemUpdated { item: updated.clone() };
+                publish_list_event(&mut redis, ListEventMessage::new(id, owner.id, event)).await;
+                rows.push(updated);
+            },
+        }
+    }
+    let merged: Vec<Uuid> = result.deleted.into_iter().filter(|item_id| !created_ids.contains(item_id)).collect();
+    for item in rows.iter().filter(|item| matches!(get_item_id(item), Some(item_id) if created_ids.contains(&item_id))) {
+        let event = ListEvent::ItemCreated { item: item.clone() };
+        publish_list_event(&mut redis, ListEventMessage::new(id, owner.id, event)).await;
+    }
+    for item_id in merged.iter() {
+        let event = ListEvent::ItemDeleted { id: *item_id };
+        publish_list_event(&mut redis, ListEventMessage::new(id, owner.id, event)).await;
+    }
 
     let response = PartialUpdateResponse {
         items: rows,
-        errors
+        errors,
+        merged,
     };
     Ok(warp::reply::with_status( reply::json(&response), StatusCode::OK))
 }
 
-pub async fn update_item(shopping_list_id: Uuid, item_id: Uuid, owner: AuthenticatedUser, db: DBConn, item: PartialItem) -> Result<impl warp::Reply, Rejection> {
-    validate_shopping_list_access(&shopping_list_id, &owner.id, &db).await?;
+fn to_item_error(index: usize, item: &Item, rejection: &Rejection) -> (StatusCode, ItemError) {
+    match rejection.find::<HttpError>() {
+        Some(HttpError::NotFound(message)) => {
+            (StatusCode::NOT_FOUND, ItemError { index, message: format!("{} for item {}", message, item.name) })
+        },
+        error => {
+            let status = error.map_or(StatusCode::INTERNAL_SERVER_ERROR, |error| error_match(error).0);
+            (status, ItemError { index, message: format!("Insert failed for item {}", item.name) })
+        },
+    }
+}
+
+pub async fn get_item(shopping_list_id: Uuid, item_id: Uuid, owner: AuthenticatedUser, db: DBConn) -> Result<impl warp::Reply, Rejection> {
+    validate_shopping_list_access(&shopping_list_id, &owner.id, ShareRole::VIEWER, &db).await?;
+
+    let item = find_item(&shopping_list_id, &item_id, &db).await?.ok_or_else(warp::reject::not_found)?;
+    let version = item.version;
+    Ok(with_etag(reply::json(&item), version))
+}
+
+pub async fn update_item(shopping_list_id: Uuid, item_id: Uuid, owner: AuthenticatedUser, db: DBConn, mut redis: RedisConn, if_match: IfMatch, item: PartialItem) -> Result<impl warp::Reply, Rejection> {
+    validate_shopping_list_access(&shopping_list_id, &owner.id, ShareRole::EDITOR, &db).await?;
+
+    validate_item_changes(&owner.id, &item, &db).await?;
+    let (previous, updated_item) = change_item(&shopping_list_id, &item_id, &if_match, &item, &db).await?;
+    if item.category_id.is_some() {
+        learn_category(&owner.id, &updated_item, &db).await;
+    }
+    record_price(&owner.id, &previous, &updated_item, &db).await;
+    let version = updated_item.version;
+    let event = ListEvent::ItemUpdated { item: updated_item.clone() };
+    publish_list_event(&mut redis, ListEventMessage::new(shopping_list_id, owner.id, event)).await;
+    Ok(with_etag(reply::with_status(reply::json(&updated_item), StatusCode::OK), version))
+}
+
+pub async fn delete_item(shopping_list_id: Uuid, item_id: Uuid, owner: AuthenticatedUser, db: DBConn, mut redis: RedisConn, if_match: IfMatch) -> Result<impl warp::Reply, Rejection> {
+    validate_shopping_list_access(&shopping_list_id, &owner.id, ShareRole::EDITOR, &db).await?;
+
+    if remove_item(&shopping_list_id, &item_id, &if_match, &db).await? {
+        let event = ListEvent::ItemDeleted { id: item_id };
+        publish_list_event(&mut redis, ListEventMessage::new(shopping_list_id, owner.id, event)).await;
+    }
+    Ok(reply::with_status(reply::json(&()), StatusCode::NO_CONTENT))
+}
+
+/// Creates the item with the id chosen by the client when given, so creating it again
+/// (an offline client retrying) returns the existing item instead of a duplicate.
+pub async fn insert_item(shopping_list_id: &Uuid, item_id: Option<Uuid>, item: &Item, db: &DBConn) -> Result<Item, HttpError> {
+    let item_rows = db.query(
+        "
+            INSERT INTO item (id, name, description, current_amount, total_amount, bought, unit, tags, category_id, unit_price, currency, store_id, shopping_list_id)
+            VALUES (COALESCE($13, uuid_generate_v4()), $1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12)
+            ON CONFLICT (id) DO NOTHING
+            RETURNING *
+        ",
+        &[
+            &item.name,
+            &item.description,
+            &item.current_amount,
+            &item.total_amount,
+            &item.bought,
+            &item.unit.to_string().as_str(),
+            &item.tags,
+            &item.category_id,
+            &item.unit_price,
+            &item.currency,
+            &item.store_id,
+            shopping_list_id,
+            &item_id,
+        ]
+    ).await.map_err(HttpError::Query)?;
+    if let Some(row) = item_rows.first() {
+        return Ok(Item::from_row(row));
+    }
+
+    let existing_id = item_id.ok_or(HttpError::InternalServerError)?;
+    find_item(shopping_list_id, &existing_id, db).await?
+        .ok_or_else(|| HttpError::Conflict(String::from("Item id is already used")))
+}
+
+/// Places the item between the anchor and its neighbour. Only when there is no room left
+/// between them the whole list is spread out again, which clients learn through a resync.
+pub async fn move_item(shopping_list_id: Uuid, item_id: Uuid, owner: AuthenticatedUser, mut db: DBConn, mut redis: RedisConn, if_match: IfMatch, body: ItemPositionDTO) -> Result<impl warp::Reply, Rejection> {
+    validate_shopping_list_access(&shopping_list_id, &owner.id, ShareRole::EDITOR, &db).await?;
+    let (anchor_id, before) = match (body.before, body.after) {
+        (Some(anchor_id), _) => (anchor_id, true),
+        (None, Some(anchor_id)) => (anchor_id, false),
+        (None, None) => return Err(reject::custom(HttpError::InternalServerError)),
+    };
+    if anchor_id == item_id {
+        let msg = String::from("Item can't be moved next to itself");
+        return Err(reject::custom(HttpError::Conflict(msg)));
+    }
+
+    let transaction = db.transaction().await.map_err(HttpError::Query)?;
+    // one reorder of the list at a time, so two moves can't pick the same spot
+    transaction.execute("SELECT 1 FROM shopping_list WHERE id=$1 FOR UPDATE", &[&shopping_list_id])
+        .await.map_err(HttpError::Query)?;
+    let existing = transaction.query("SELECT * FROM item WHERE id=$1 AND shopping_list_id=$2 FOR UPDATE", &[&item_id, &shopping_list_id])
+        .await.map_err(HttpError::Query)?;
+    let existing = existing.first().map(Item::from_row).ok_or_else(warp::reject::not_found)?;
+    if !if_match.matches(existing.version) {
+        return Err(precondition_failed(&existing, existing.version));
+    }
+
+    let mut rebalanced = false;
+    let position = loop {
+        let anchor = transaction.query("SELECT position FROM item WHERE id=$1 AND shopping_list_id=$2", &[&anchor_id, &shopping_list_id])
+            .await.map_err(HttpError::Query)?;
+        let anchor: i64 = anchor.first()
+            .map(|row| row.get("position"))
+            .ok_or_else(|| reject::custom(HttpError::NotFound(String::from("Anchor item not found"))))?;
+        let neighbour_query = if before {
+            "SELECT max(position) FROM item WHERE shopping_list_id=$1 AND id<>$2 AND position < $3"
+        } else {
+            "SELECT min(position) FROM item WHERE shopping_list_id=$1 AND id<>$2 AND position > $3"
+        };
+        let neighbour = transaction.query(neighbour_query, &[&shopping_list_id, &item_id, &anchor])
+            .await.map_err(HttpError::Query)?;
+        let neighbour: Option<i64> = neighbour.first().and_then(|row| row.get(0));
+
+        match neighbour {
+            None if before => break anchor - POSITION_GAP,
+            None => break anchor + POSITION_GAP,
+            Some(neighbour) if (anchor - neighbour).abs() > 1 => break (anchor + neighbour) / 2,
+            Some(_neighbour) if !rebalanced => {
+                transaction.execute(
+                    "UPDATE item SET position = ranked.rank * $2 FROM (
+                        SELECT id, row_number() OVER (ORDER BY position, id) AS rank FROM item WHERE shopping_list_id=$1
+                    ) AS ranked WHERE item.id = ranked.id",
+                    &[&shopping_list_id, &POSITION_GAP],
+                ).await.map_err(HttpError::Query)?;
+                rebalanced = true;
+            },
+            Some(_neighbour) => return Err(reject::custom(HttpError::InternalServerError)),
+        }
+    };
+
+    let moved = transaction.query(
+        "UPDATE item SET position=$2, version=version + 1 WHERE id=$1 RETURNING *",
+        &[&item_id, &position],
+    ).await.map_err(HttpError::Query)?;
+    let moved = Item::from_row(moved.first().ok_or(HttpError::InternalServerError)?);
+    transaction.commit().await.map_err(HttpError::Query)?;
+
+    let event = if rebalanced {
+        ListEventMessage::resync(shopping_list_id)
+    } else {
+        ListEventMessage::new(shopping_list_id, owner.id, ListEvent::ItemUpdated { item: moved.clone() })
+    };
+    publish_list_event(&mut redis, event).await;
+    let version = moved.version;
+    Ok(with_etag(reply::json(&moved), version))
+}
+
+/// Applies all operations in one transaction, none of them is applied when any fails.
+pub async fn batch_items(shopping_list_id: Uuid, owner: AuthenticatedUser, mut db: DBConn, mut redis: RedisConn, operations: Vec<ItemOperation>) -> Result<impl warp::Reply, Rejection> {
+    validate_shopping_list_access(&shopping_list_id, &owner.id, ShareRole::EDITOR, &db).await?;
+    let mut target_list_ids: Vec<Uuid> = operations.iter()
+        .filter_map(|operation| match operation {
+            ItemOperation::Move { target_list_id, .. } => Some(*target_list_id),
+            _ => None,
+        })
+        .collect();
+    target_list_ids.sort();
+    target_list_ids.dedup();
+    for target_list_id in target_list_ids.iter() {
+        validate_shopping_list_access(target_list_id, &owner.id, ShareRole::EDITOR, &db).await?;
+    }
+
+    for operation in operations.iter() {
+        if let ItemOperation::Update { changes, .. } = operation {
+            validate_item_changes(&owner.id, changes, &db).await?;
+        }
+    }
+
+    let mut response = BatchItemsResponse::default();
+    let mut categorized: Vec<Item> = Vec::new();
+    let mut bought: Vec<(Item, Item)> = Vec::new();
+    let transaction = db.transaction().await.map_err(HttpError::Query)?;
+    for (index, operation) in operations.iter().enumerate() {
+        // locked, so the version checked here is the one changed
+        let rows = transaction.query(
+            "SELECT * FROM item WHERE id=$1 AND shopping_list_id=$2 FOR UPDATE",
+            &[operation.item_id(), &shopping_list_id],
+        ).await.map_err(HttpError::Query)?;
+        let mut existing = rows.first()
+            .map(Item::from_row)
+            .ok_or_else(|| reject::custom(HttpError::NotFound(format!("Item at index {} not found", index))))?;
+        if !IfMatch::from_version(operation.base_version()).matches(existing.version) {
+            return Err(precondition_failed(&existing, existing.version));
+        }
+
+        match operation {
+            ItemOperation::Update { item_id, changes, .. } => {
+                let read_version = existing.version;
+                let previous = existing.clone();
+                existing.apply_changes(changes);
+                let updated = transaction.query(
+                    UPDATE_ITEM_QUERY,
+                    &[
+                        &existing.name,
+                        &existing.description,
+                        &existing.current_amount,
+                        &existing.total_amount,
+                        &existing.bought,
+                        &existing.unit.to_string().as_str(),
+                        &existing.tags,
+                        &existing.category_id,
+                        &existing.unit_price,
+                        &existing.currency,
+                        &existing.store_id,
+                        item_id,
+                        &read_version,
+                    ]
+                ).await.map_err(HttpError::Query)?;
+                let updated = Item::from_row(updated.first().ok_or(HttpError::InternalServerError)?);
+                if changes.category_id.is_some() {
+                    categorized.push(updated.clone());
+                }
+                if updated.bought && !previous.bought {
+                    bought.push((previous, updated.clone()));
+                }
+                response.updated.push(updated);
+            },
+            ItemOperation::Delete { item_id, .. } => {
+                transaction.execute("DELETE FROM item WHERE id=$1", &[item_id]).await.map_err(HttpError::Query)?;
+                response.deleted.push(*item_id);
+            },
+            ItemOperation::Move { item_id, target_list_id, .. } => {
+                let moved = transaction.query(
+                    "UPDATE item SET shopping_list_id=$2, version=version + 1 WHERE id=$1 RETURNING *",
+                    &[item_id, target_list_id],
+                ).await.map_err(HttpError::Query)?;
+                let row = moved.first().ok_or(HttpError::InternalServerError)?;
+                response.moved.push(MovedItem { shopping_list_id: *target_list_id, item: Item::from_row(row) });
+            },
+        }
+    }
+    transaction.commit().await.map_err(HttpError::Query)?;
+
+    for item in categorized.iter() {
+        learn_category(&owner.id, item, &db).await;
+    }
+    for (previous, item) in bought.iter() {
+        record_price(&owner.id, previous, item, &db).await;
+    }
+    for item in response.updated.iter() {
+        let event = ListEvent::ItemUpdated { item: item.clone() };
+        publish_list_event(&mut redis, ListEventMessage::new(shopping_list_id, owner.id, event)).await;
+    }
+    for moved in response.moved.iter() {
+        if let Some(id) = moved.item.id.as_ref().and_then(|id| Uuid::parse_str(id).ok()) {
+            let event = ListEvent::ItemDeleted { id };
+            publish_list_event(&mut redis, ListEventMessage::new(shopping_list_id, owner.id, event)).await;
+        }
+        let event = ListEvent::ItemCreated { item: moved.item.clone() };
+        publish_list_event(&mut redis, ListEventMessage::new(moved.shopping_list_id, owner.id, event)).await;
+    }
+    for id in response.deleted.iter() {
+        let event = ListEvent::ItemDeleted { id: *id };
+        publish_list_event(&mut redis, ListEventMessage::new(shopping_list_id, owner.id, event)).await;
+    }
+    Ok(reply::json(&response))
+}
+
+/// Removes everything that was bought, e.g. after unpacking the groceries.
+pub async fn clear_bought(shopping_list_id: Uuid, owner: AuthenticatedUser, db: DBConn, mut redis: RedisConn) -> Result<impl warp::Reply, Rejection> {
+    validate_shopping_list_access(&shopping_list_id, &owner.id, ShareRole::EDITOR, &db).await?;
+
+    let rows = db.query(
+        "DELETE FROM item WHERE shopping_list_id=$1 AND bought RETURNING id",
+        &[&shopping_list_id],
+    ).await.map_err(HttpError::Query)?;
+    let deleted: Vec<Uuid> = rows.iter().map(|row| row.get("id")).collect();
+
+    for id in deleted.iter() {
+        let event = ListEvent::ItemDeleted { id: *id };
+        publish_list_event(&mut redis, ListEventMessage::new(shopping_list_id, owner.id, event)).await;
+    }
+    Ok(reply::json(&BatchItemsResponse { deleted, ..BatchItemsResponse::default() }))
+}
+
+/// Marks every item as not bought, so a recurring list can be used again.
+pub async fn reset_bought(shopping_list_id: Uuid, owner: AuthenticatedUser, db: DBConn, mut redis: RedisConn) -> Result<impl warp::Reply, Rejection> {
+    validate_shopping_list_access(&shopping_list_id, &owner.id, ShareRole::EDITOR, &db).await?;
+
+    let rows = db.query(
+        "UPDATE item SET bought=false, current_amount=0, version=version + 1
+            WHERE shopping_list_id=$1 AND (bought OR current_amount <> 0)
+            RETURNING *",
+        &[&shopping_list_id],
+    ).await.map_err(HttpError::Query)?;
+    let updated: Vec<Item> = rows.iter().map(Item::from_row).collect();
+
+    for item in updated.iter() {
+        let event = ListEvent::ItemUpdated { item: item.clone() };
+        publish_list_event(&mut redis, ListEventMessage::new(shopping_list_id, owner.id, event)).await;
+    }
+    Ok(reply::json(&BatchItemsResponse { updated, ..BatchItemsResponse::default() }))
+}
+
+/// Merges the duplicates of the list into the first of them, see `Item::merge_key`.
+pub async fn merge_duplicates(shopping_list_id: Uuid, owner: AuthenticatedUser, mut db: DBConn, mut redis: RedisConn) -> Result<impl warp::Reply, Rejection> {
+    validate_shopping_list_access(&shopping_list_id, &owner.id, ShareRole::EDITOR, &db).await?;
+
+    let response = merge_items(&shopping_list_id, None, &mut db).await?;
+    for item in response.updated.iter() {
+        let event = ListEvent::ItemUpdated { item: item.clone() };
+        publish_list_event(&mut redis, ListEventMessage::new(shopping_list_id, owner.id, event)).await;
+    }
+    for id in response.deleted.iter() {
+        let event = ListEvent::ItemDeleted { id: *id };
+        publish_list_event(&mut redis, ListEventMessage::new(shopping_list_id, owner.id, event)).await;
+    }
+    Ok(reply::json(&response))
+}
+
+/// Merges every group of duplicates into its item that comes first in the list, when `only`
+/// is given just the groups containing one of those items.
+async fn merge_items(shopping_list_id: &Uuid, only: Option<&[Uuid]>, db: &mut DBConn) -> Result<BatchItemsResponse, HttpError> {
+    let transaction = db.transaction().await.map_err(HttpError::Query)?;
+    // no item of the list is added or changed while the duplicates are combined
+    transaction.execute("SELECT 1 FROM shopping_list WHERE id=$1 FOR UPDATE", &[shopping_list_id])
+        .await.map_err(HttpError::Query)?;
+    let rows = transaction.query(
+        "SELECT * FROM item WHERE shopping_list_id=$1 ORDER BY position, id FOR UPDATE",
+        &[shopping_list_id],
+    ).await.map_err(HttpError::Query)?;
+
+    let mut groups: Vec<Vec<Item>> = Vec::new();
+    for item in rows.iter().map(Item::from_row) {
+        let key = item.merge_key();
+        match groups.iter_mut().find(|group| group[0].merge_key() == key) {
+            Some(group) => group.push(item),
+            None => groups.push(vec![item]),
+        }
+    }
+
+    let mut response = BatchItemsResponse::default();
+    for group in groups.into_iter().filter(|group| group.len() > 1) {
+        let ids: Vec<Uuid> = group.iter().filter_map(get_item_id).collect();
+        if let Some(only) = only {
+            if !ids.iter().any(|id| only.contains(id)) {
+                continue;
+            }
+        }
+        let mut items = group.into_iter();
+        let mut target = items.next().ok_or(HttpError::InternalServerError)?;
+        for duplicate in items {
+            target.merge(&duplicate);
+        }
+        let updated = transaction.query(
+            UPDATE_ITEM_QUERY,
+            &[
+                &target.name,
+                &target.description,
+                &target.current_amount,
+                &target.total_amount,
+                &target.bought,
+                &target.unit.to_string().as_str(),
+                &target.tags,
+                &target.category_id,
+                &target.unit_price,
+                &target.currency,
+                &target.store_id,
+                &ids[0],
+                &target.version,
+            ]
+        ).await.map_err(HttpError::Query)?;
+        response.updated.push(Item::from_row(updated.first().ok_or(HttpError::InternalServerError)?));
+        response.deleted.extend_from_slice(&ids[1..]);
+    }
+    transaction.execute("DELETE FROM item WHERE id = ANY($1)", &[&response.deleted])
+        .await.map_err(HttpError::Query)?;
+    transaction.commit().await.map_err(HttpError::Query)?;
+    Ok(response)
+}
+
+/// Completes a new item before it is added: rounds its amounts, checks its category and store
+/// and fills in what was learned about the product.
+pub async fn prepare_item(user_id: &Uuid, item: &mut Item, db: &DBConn) -> Result<(), Rejection> {
+    item.round_amounts();
+    assign_category(user_id, item, db).await?;
+    if let Some(store_id) = &item.store_id {
+        validate_store_owner(store_id, user_id, db).await?;
+    }
+    estimate_price(user_id, item, db).await?;
+    Ok(())
+}
+
+/// Changes can only point to categories and stores of the user making them.
+pub async fn validate_item_changes(user_id: &Uuid, changes: &PartialItem, db: &DBConn) -> Result<(), Rejection> {
+    validate_category_changes(user_id, changes, db).await?;
+    if let Some(Some(store_id)) = &changes.store_id {
+        validate_store_owner(store_id, user_id, db).await?;
+    }
+    Ok(())
+}
+
+fn get_item_id(item: &Item) -> Option<Uuid> {
+    item.id.as_deref().and_then(|id| Uuid::parse_str(id).ok())
+}
+
+/// All items in one insert, nothing is added when any of them fails.
+async fn insert_items(shopping_list_id: &Uuid, items: &[Item], db: &mut DBConn) -> Result<Vec<Item>, HttpError> {
+    if items.is_empty() {
+        return Ok(Vec::new());
+    }
+    let units: Vec<String> = items.iter().map(|item| item.unit.to_string()).collect();
+    let mut values: Vec<String> = Vec::new();
+    let mut params: Vec<&(dyn ToSql + Sync)> = vec![shopping_list_id];
+    for (item, unit) in items.iter().zip(units.iter()) {
+        let first = params.len() + 1;
+        let placeholders: Vec<String> = (first..first + 11).map(|index| format!("${}", index)).collect();
+        values.push(format!("(uuid_generate_v4(), {}, $1)", placeholders.join(", ")));
+        params.extend_from_slice(&[&item.name, &item.description, &item.current_amount, &item.total_amount, &item.bought, unit, &item.tags, &item.category_id, &item.unit_price, &item.currency, &item.store_id]);
+    }
 
-    let db_resp = db.query("SELECT * FROM item WHERE id=$1", &[&item_id]).await.expect("Item get query failed");
-    let existing_row = db_resp.get(0);
-    if let Some(row) = existing_row {
-        let mut existing = Item::from_row(row);
-        existing.apply_changes(&item);
-        let current_amount = f32::try_from(existing.current_amount).expect("Failed to cast current_amount");
-        let total_amount = f32::try_from(existing.total_amount).expect("Failed to cast total_amount");
+    let transaction = db.transaction().await.map_err(HttpError::Query)?;
+    let item_rows = transaction.query(
+        format!(
+            "INSERT INTO item (id, name, description, current_amount, total_amount, bought, unit, tags, category_id, unit_price, currency, store_id, shopping_list_id)
+                VALUES {} RETURNING *",
+            values.join(", "),
+        ).as_str(),
+        &params,
+    ).await.map_err(HttpError::Query)?;
+    transaction.commit().await.map_err(HttpError::Query)?;
+    Ok(item_rows.iter().map(Item::from_row).collect())
+}
+
+/// The update only applies to the version it was computed from. When a concurrent change
+/// slips in between, a conditional request fails and an unconditional one is applied again.
+/// Returns the item as it was before the update next to the updated one.
+pub async fn change_item(shopping_list_id: &Uuid, item_id: &Uuid, if_match: &IfMatch, item: &PartialItem, db: &DBConn) -> Result<(Item, Item), Rejection> {
+    for _attempt in 0..MAX_UPDATE_ATTEMPTS {
+        let mut existing = find_item(shopping_list_id, item_id, db).await?.ok_or_else(warp::reject::not_found)?;
+        if !if_match.matches(existing.version) {
+            return Err(precondition_failed(&existing, existing.version));
+        }
+        let read_version = existing.version;
+        let previous = existing.clone();
+        existing.apply_changes(item);
 
-        let update_request = db.query(
-            "
-            UPDATE item SET (name, description, current_amount, total_amount, bought, unit, tags)
-                =($1, $2, $3, $4, $5, $6, $7) WHERE id=$8
-            ",
+        let updated = db.query(
+            UPDATE_ITEM_QUERY,
             &[
                 &existing.name,
                 &existing.description,
-                &current_amount,
-                &total_amount,
+                &existing.current_amount,
+                &existing.total_amount,
                 &existing.bought,
                 &existing.unit.to_string().as_str(),
                 &existing.tags,
-                &item_id,
+                &existing.category_id,
+                &existing.unit_price,
+                &existing.currency,
+                &existing.store_id,
+                item_id,
+                &read_version,
             ]
-        ).await;
-        match update_request {
-            Ok(_r) => Ok(reply::with_status(reply::json(&existing), StatusCode::OK)),
-            Err(e) => Err(warp::reject::custom(HttpError::Query(e))),
+        ).await.map_err(HttpError::Query)?;
+
+        if let Some(row) = updated.first() {
+            return Ok((previous, Item::from_row(row)));
         }
-    } else {
-        Err(warp::reject::not_found())
     }
+
+    let msg = String::from("Item is being changed concurrently, try again");
+    Err(warp::reject::custom(HttpError::Conflict(msg)))
 }
 
-pub async fn delete_item(shopping_list_id: Uuid, item_id: Uuid, owner: AuthenticatedUser, db: DBConn) -> Result<impl warp::Reply, Rejection> {
-    validate_shopping_list_access(&shopping_list_id, &owner.id, &db).await?;
+/// Returns whether the item was deleted, deleting a missing item isn't an error.
+pub async fn remove_item(shopping_list_id: &Uuid, item_id: &Uuid, if_match: &IfMatch, db: &DBConn) -> Result<bool, Rejection> {
+    let deleted = if if_match.is_present() {
+        let existing = find_item(shopping_list_id, item_id, db).await?;
+        match existing {
+            Some(existing) if !if_match.matches(existing.version) => {
+                return Err(precondition_failed(&existing, existing.version));
+            },
+            Some(existing) => db.execute(
+                "DELETE FROM item WHERE id=$1 AND shopping_list_id=$2 AND version=$3",
+                &[item_id, shopping_list_id, &existing.version],
+            ).await.map_err(HttpError::Query)?,
+            None => 0,
+        }
+    } else {
+        db.execute("DELETE FROM item WHERE id=$1 AND shopping_list_id=$2", &[item_id, shopping_list_id])
+            .await.map_err(HttpError::Query)?
+    };
 
-    let query_result = db.query("DELETE FROM item WHERE id=$1", &[&item_id]).await;
-    match query_result {
-        Ok(_row) => {
-            Ok(reply::with_status(reply::json(&()), StatusCode::NO_CONTENT))
-        },
-        Err(e) => Err(warp::reject::custom(HttpError::Query(e)))
+    if deleted == 0 && if_match.is_present() {
+        // changed between the check and the delete
+        if let Some(existing) = find_item(shopping_list_id, item_id, db).await? {
+            return Err(precondition_failed(&existing, existing.version));
+        }
     }
+    Ok(deleted > 0)
+}
+
+/// Matches the term anywhere, `%` and `_` in it are taken literally.
+fn get_search_pattern(term: &str) -> String {
+    let escaped = term.replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_");
+    format!("%{}%", escaped)
+}
+
+async fn find_item(shopping_list_id: &Uuid, item_id: &Uuid, db: &DBConn) -> Result<Option<Item>, HttpError> {
+    let rows = db.query(
+        "SELECT * FROM item WHERE id=$1 AND shopping_list_id=$2",
+        &[item_id, shopping_list_id],
+    ).await.map_err(HttpError::Query)?;
+    Ok(rows.first().map(Item::from_row))
 }
@@ -0,0 +1,164 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use jsonwebtoken::{DecodingKey, EncodingKey};
+use serde_derive::Serialize;
+use simple_asn1::{from_der, ASN1Block};
+use warp::{Reply, Rejection};
+use warp::reply::json;
+
+const PRIVATE_KEY_ENV_KEY: &str = "JWT_KEY_PRIVATE";
+const PRIVATE_KEY_PATH_ENV_KEY: &str = "JWT_KEY_PRIVATE_PATH";
+const PUBLIC_KEY_ENV_KEY: &str = "JWT_KEY_PUBLIC";
+const PUBLIC_KEY_PATH_ENV_KEY: &str = "JWT_KEY_PUBLIC_PATH";
+const KEY_ID_ENV_KEY: &str = "JWT_KEY_ID";
+const RETIRED_KEYS_ENV_KEY: &str = "JWT_RETIRED_KEYS";
+
+const DEFAULT_PRIVATE_KEY_PATH: &str = "jwtRS256.key";
+const DEFAULT_PUBLIC_KEY_PATH: &str = "jwtRS256.key.pub";
+const DEFAULT_KEY_ID: &str = "default";
+
+#[derive(Debug)]
+pub enum KeyError {
+    Io(String, std::io::Error),
+    InvalidKey(String),
+}
+
+/// Keys used to sign and verify JWTs. Only one key signs new tokens,
+/// but tokens signed by any of the verification keys are accepted,
+/// so a key can be rotated without logging everybody out.
+#[derive(Clone)]
+pub struct KeyStore {
+    inner: Arc<KeyStoreInner>,
+}
+
+struct KeyStoreInner {
+    signing_key_id: String,
+    signing_key: EncodingKey,
+    verification_keys: HashMap<String, VerificationKey>,
+}
+
+struct VerificationKey {
+    decoding_key: DecodingKey<'static>,
+    jwk: Jwk,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct Jwk {
+    kty: String,
+    #[serde(rename = "use")]
+    key_use: String,
+    alg: String,
+    kid: String,
+    n: String,
+    e: String,
+}
+
+#[derive(Debug, Serialize)]
+struct JwkSet<'a> {
+    keys: Vec<&'a Jwk>,
+}
+
+impl KeyStore {
+    pub fn signing_key_id(&self) -> &str {
+        self.inner.signing_key_id.as_str()
+    }
+
+    pub fn signing_key(&self) -> &EncodingKey {
+        &self.inner.signing_key
+    }
+
+    /// Tokens without a `kid` header were issued before key rotation and are checked against the signing key.
+    pub fn verification_key(&self, key_id: Option<&str>) -> Option<&DecodingKey<'static>> {
+        let key_id = key_id.unwrap_or_else(|| self.signing_key_id());
+        self.inner.verification_keys.get(key_id).map(|key| &key.decoding_key)
+    }
+}
+
+/// Loads the signing key pair from `JWT_KEY_PRIVATE`/`JWT_KEY_PUBLIC` (PEM content)
+/// or from `JWT_KEY_PRIVATE_PATH`/`JWT_KEY_PUBLIC_PATH`, and the public keys of retired
+/// key pairs from `JWT_RETIRED_KEYS` as a comma separated list of `kid=path`.
+pub fn init_keys() -> Result<KeyStore, KeyError> {
+    let signing_key_id = std::env::var(KEY_ID_ENV_KEY).unwrap_or_else(|_| String::from(DEFAULT_KEY_ID));
+    let private_pem = load_pem(PRIVATE_KEY_ENV_KEY, PRIVATE_KEY_PATH_ENV_KEY, DEFAULT_PRIVATE_KEY_PATH)?;
+    let public_pem = load_pem(PUBLIC_KEY_ENV_KEY, PUBLIC_KEY_PATH_ENV_KEY, DEFAULT_PUBLIC_KEY_PATH)?;
+
+    let signing_key = EncodingKey::from_rsa_pem(private_pem.as_bytes())
+        .map_err(|e| KeyError::InvalidKey(format!("{}: {:?}", signing_key_id, e)))?;
+
+    let mut verification_keys = HashMap::new();
+    verification_keys.insert(signing_key_id.clone(), parse_public_key(&signing_key_id, &public_pem)?);
+
+    let retired_keys = std::env::var(RETIRED_KEYS_ENV_KEY).unwrap_or_default();
+    for entry in retired_keys.split(',').map(str::trim).filter(|entry| !entry.is_empty()) {
+        let (kid, path) = entry.split_once('=')
+            .ok_or_else(|| KeyError::InvalidKey(format!("{} must be a list of kid=path, got {}", RETIRED_KEYS_ENV_KEY, entry)))?;
+        let pem = read_file(path)?;
+        verification_keys.insert(String::from(kid), parse_public_key(kid, &pem)?);
+    }
+
+    Ok(KeyStore {
+        inner: Arc::new(KeyStoreInner {
+            signing_key_id,
+            signing_key,
+            verification_keys,
+        }),
+    })
+}
+
+pub async fn get_jwks(keys: KeyStore) -> Result<impl Reply, Rejection> {
+    let mut jwks: Vec<&Jwk> = keys.inner.verification_keys.values().map(|key| &key.jwk).collect();
+    jwks.sort_by(|a, b| a.kid.cmp(&b.kid));
+    Ok(json(&JwkSet { keys: jwks }))
+}
+
+fn load_pem(env_key: &str, path_env_key: &str, default_path: &str) -> Result<String, KeyError> {
+    match std::env::var(env_key) {
+        Ok(pem) if !pem.is_empty() => Ok(pem.replace("\\n", "\n")),
+        _ => {
+            let path = std::env::var(path_env_key).unwrap_or_else(|_| String::from(default_path));
+            read_file(&path)
+        }
+    }
+}
+
+fn read_file(path: &str) -> Result<String, KeyError> {
+    std::fs::read_to_string(path).map_err(|e| KeyError::Io(String::from(path), e))
+}
+
+fn parse_public_key(kid: &str, public_pem: &str) -> Result<VerificationKey, KeyError> {
+    let invalid_key = || KeyError::InvalidKey(format!("{} is not a RSA public key", kid));
+    let pem = pem::parse(public_pem).map_err(|_e| invalid_key())?;
+
+    // "PUBLIC KEY" wraps the PKCS#1 "RSA PUBLIC KEY" sequence in a bit string
+    let pkcs1 = match pem.tag.as_str() {
+        "RSA PUBLIC KEY" => pem.contents,
+        "PUBLIC KEY" => match from_der(&pem.contents).map_err(|_e| invalid_key())?.first() {
+            Some(ASN1Block::Sequence(_, blocks)) => match blocks.get(1) {
+                Some(ASN1Block::BitString(_, _, bytes)) => bytes.clone(),
+                _ => return Err(invalid_key()),
+            },
+            _ => return Err(invalid_key()),
+        },
+        _ => return Err(invalid_key()),
+    };
+
+    let (modulus, exponent) = match from_der(&pkcs1).map_err(|_e| invalid_key())?.first() {
+        Some(ASN1Block::Sequence(_, blocks)) => match (blocks.first(), blocks.get(1)) {
+            (Some(ASN1Block::Integer(_, n)), Some(ASN1Block::Integer(_, e))) => (n.to_bytes_be().1, e.to_bytes_be().1),
+            _ => return Err(invalid_key()),
+        },
+        _ => return Err(invalid_key()),
+    };
+
+    let jwk = Jwk {
+        kty: String::from("RSA"),
+        key_use: String::from("sig"),
+        alg: String::from("RS256"),
+        kid: String::from(kid),
+        n: base64::encode_config(&modulus, base64::URL_SAFE_NO_PAD),
+        e: base64::encode_config(&exponent, base64::URL_SAFE_NO_PAD),
+    };
+    let decoding_key = DecodingKey::from_rsa_components(&jwk.n, &jwk.e).into_static();
+
+    Ok(VerificationKey { decoding_key, jwk })
+}
@@ -0,0 +1,118 @@
+use std::net::SocketAddr;
+use mobc_redis::redis::AsyncCommands;
+use crate::middlewares::error::HttpError;
+use crate::services::database::RedisConn;
+
+const MAX_ATTEMPTS_PER_USER_ENV_KEY: &str = "LOGIN_MAX_ATTEMPTS_PER_USER";
+const MAX_ATTEMPTS_PER_IP_ENV_KEY: &str = "LOGIN_MAX_ATTEMPTS_PER_IP";
+const ATTEMPT_WINDOW_ENV_KEY: &str = "LOGIN_ATTEMPT_WINDOW_SECONDS";
+const TRUST_PROXY_ENV_KEY: &str = "TRUST_PROXY";
+const PROXY_HOPS_ENV_KEY: &str = "TRUSTED_PROXY_HOPS";
+
+const DEFAULT_MAX_ATTEMPTS_PER_USER: usize = 5;
+const DEFAULT_MAX_ATTEMPTS_PER_IP: usize = 20;
+const DEFAULT_ATTEMPT_WINDOW_SECONDS: usize = 15 * 60;
+const DEFAULT_PROXY_HOPS: usize = 1;
+const BASE_LOCKOUT_SECONDS: usize = 30;
+const MAX_LOCKOUT_SECONDS: usize = 60 * 60;
+
+/// Failed login attempts are counted per username and per client ip,
+/// once either of them reaches its limit further logins are locked out
+/// for a period that doubles with every additional failure.
+pub struct LoginThrottle {
+    user_key: String,
+    ip_key: Option<String>,
+}
+
+impl LoginThrottle {
+    pub fn new(username: &str, client: &ClientAddress) -> Self {
+        Self {
+            user_key: format!("login_attempts:user:{}", username.to_lowercase()),
+            ip_key: client.0.as_ref().map(|ip| format!("login_attempts:ip:{}", ip)),
+        }
+    }
+
+    pub async fn check(&self, redis: &mut RedisConn) -> Result<(), HttpError> {
+        let mut retry_after = 0;
+        for key in self.keys() {
+            let ttl: isize = redis.ttl(get_lock_key(key)).await.map_err(HttpError::Redis)?;
+            if ttl > retry_after {
+                retry_after = ttl;
+            }
+        }
+        if retry_after > 0 {
+            let msg = String::from("Too many failed login attempts");
+            return Err(HttpError::TooManyRequests(msg, retry_after as u64));
+        }
+        Ok(())
+    }
+
+    pub async fn register_failure(&self, redis: &mut RedisConn) -> Result<(), HttpError> {
+        let window = get_env_number(ATTEMPT_WINDOW_ENV_KEY, DEFAULT_ATTEMPT_WINDOW_SECONDS);
+        let limits = [
+            (Some(&self.user_key), get_env_number(MAX_ATTEMPTS_PER_USER_ENV_KEY, DEFAULT_MAX_ATTEMPTS_PER_USER)),
+            (self.ip_key.as_ref(), get_env_number(MAX_ATTEMPTS_PER_IP_ENV_KEY, DEFAULT_MAX_ATTEMPTS_PER_IP)),
+        ];
+        for (key, max_attempts) in limits.iter() {
+            if let Some(key) = key {
+                let failures: usize = redis.incr(key.as_str(), 1).await.map_err(HttpError::Redis)?;
+                let _: () = redis.expire(key.as_str(), window).await.map_err(HttpError::Redis)?;
+                if failures >= *max_attempts {
+                    let lockout = get_lockout_seconds(failures - max_attempts);
+                    let _: () = redis.set_ex(get_lock_key(key), failures, lockout).await.map_err(HttpError::Redis)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Only the username counter is reset, a successful login shouldn't let an ip guess further.
+    pub async fn reset(&self, redis: &mut RedisConn) -> Result<(), HttpError> {
+        redis.del::<_, ()>(&[self.user_key.clone(), get_lock_key(&self.user_key)]).await.map_err(HttpError::Redis)
+    }
+
+    fn keys(&self) -> impl Iterator<Item = &String> {
+        std::iter::once(&self.user_key).chain(self.ip_key.iter())
+    }
+}
+
+/// Client ip of the request, taken from `X-Forwarded-For` when `TRUST_PROXY` is enabled.
+/// Every proxy appends the address it got the request from, so only the entries added by
+/// the `TRUSTED_PROXY_HOPS` proxies in front of the server can be trusted, the ones before
+/// them are sent by the client.
+pub struct ClientAddress(Option<String>);
+
+impl ClientAddress {
+    pub fn new(remote: Option<SocketAddr>, forwarded_for: Option<String>) -> Self {
+        let trust_proxy = dotenv::var(TRUST_PROXY_ENV_KEY).map(|value| value == "true").unwrap_or(false);
+        let hops = get_env_number(PROXY_HOPS_ENV_KEY, DEFAULT_PROXY_HOPS).max(1);
+        let forwarded_ip = forwarded_for
+            .filter(|_| trust_proxy)
+            .and_then(|header| get_forwarded_ip(&header, hops))
+            .filter(|ip| !ip.is_empty());
+        Self(forwarded_ip.or_else(|| remote.map(|addr| addr.ip().to_string())))
+    }
+}
+
+/// Entry added by the outermost trusted proxy, or the first one when there are fewer entries.
+fn get_forwarded_ip(header: &str, hops: usize) -> Option<String> {
+    let ips: Vec<&str> = header.split(',').map(str::trim).collect();
+    let index = ips.len().saturating_sub(hops);
+    ips.get(index).map(|ip| String::from(*ip))
+}
+
+fn get_lock_key(key: &str) -> String {
+    format!("{}:lock", key)
+}
+
+fn get_lockout_seconds(failures_over_limit: usize) -> usize {
+    let multiplier = 1usize << failures_over_limit.min(16);
+    (BASE_LOCKOUT_SECONDS * multiplier).min(MAX_LOCKOUT_SECONDS)
+}
+
+fn get_env_number(env_key: &str, default: usize) -> usize {
+    dotenv::var(env_key)
+        .ok()
+        .and_then(|value| value.parse::<usize>().ok())
+        .unwrap_or(default)
+}
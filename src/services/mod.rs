@@ -2,3 +2,15 @@ pub mod shopping_list;
 pub mod items;
 pub mod database;
 pub mod user;
+pub mod keys;
+pub mod notifier;
+pub mod login_throttle;
+pub mod totp;
+pub mod two_factor;
+pub mod access_tokens;
+pub mod invites;
+pub mod households;
+pub mod events;
+pub mod sync;
+pub mod categories;
+pub mod prices;
@@ -0,0 +1,29 @@
+use async_trait::async_trait;
+use std::sync::Arc;
+use crate::models::user::UserResponse;
+
+#[derive(Debug)]
+pub struct NotifierError(pub String);
+
+/// Delivers messages to users outside of the API, e.g. password reset tokens.
+#[async_trait]
+pub trait Notifier {
+    async fn send_password_reset(&self, user: &UserResponse, token: &str) -> Result<(), NotifierError>;
+}
+
+pub type SharedNotifier = Arc<dyn Notifier + Send + Sync>;
+
+/// Prints notifications to stdout, meant for local development and testing.
+pub struct LogNotifier;
+
+#[async_trait]
+impl Notifier for LogNotifier {
+    async fn send_password_reset(&self, user: &UserResponse, token: &str) -> Result<(), NotifierError> {
+        println!("Password reset token for user {} ({}): {}", user.username, user.id, token);
+        Ok(())
+    }
+}
+
+pub fn init_notifier() -> SharedNotifier {
+    Arc::new(LogNotifier)
+}
@@ -0,0 +1,150 @@
+use std::str::FromStr;
+use percent_encoding::percent_decode_str;
+use rust_decimal::Decimal;
+use uuid::Uuid;
+use warp::{Reply, Rejection, reject};
+use warp::reply::json;
+use crate::middlewares::auth::AuthenticatedUser;
+use crate::middlewares::error::HttpError;
+use crate::models::item::{Item, normalize_name};
+use crate::models::price::{PricePoint, PriceTrend, ProductPricesResponse};
+use crate::models::unit::Unit;
+use crate::services::database::DBConn;
+
+// enough for years of weekly shopping
+const MAX_PRICE_POINTS: i64 = 500;
+
+/// Prices the user paid for the product, with their trend and the store it was cheapest in
+/// the last time it was bought there.
+pub async fn get_product_prices(name: String, user: AuthenticatedUser, db: DBConn) -> Result<impl Reply, Rejection> {
+    let name = percent_decode_str(&name).decode_utf8()
+        .map_err(|_e| reject::custom(HttpError::NotFound(String::from("Product not found"))))?;
+    let product_name = normalize_name(&name);
+
+    let rows = db.query(
+        "SELECT p.*, s.name AS store_name FROM price_history p
+            LEFT JOIN store s ON s.id=p.store_id
+            WHERE p.user_id=$1 AND p.product_name=$2
+            ORDER BY p.recorded_at DESC
+            LIMIT $3",
+        &[&user.id, &product_name, &MAX_PRICE_POINTS],
+    ).await.map_err(HttpError::Query)?;
+    let latest = rows.first()
+        .ok_or_else(|| reject::custom(HttpError::NotFound(String::from("No prices recorded for this product"))))?;
+    let unit = Unit::from_str(latest.get("unit")).map_err(|_e| HttpError::InternalServerError)?;
+    let currency: Option<String> = latest.get("currency");
+
+    let mut prices: Vec<PricePoint> = Vec::new();
+    for row in rows.iter() {
+        let row_currency: Option<String> = row.get("currency");
+        let unit_price = Unit::from_str(row.get("unit"))
+            .ok()
+            .and_then(|row_unit| row_unit.convert_price(row.get("unit_price"), &unit));
+        match unit_price {
+            Some(unit_price) if row_currency == currency => prices.push(PricePoint {
+                unit_price,
+                currency: row_currency,
+                store_id: row.get("store_id"),
+                store_name: row.get("store_name"),
+                recorded_at: row.get("recorded_at"),
+            }),
+            _ => {},
+        }
+    }
+
+    let (trend, change_percent) = match (prices.first(), prices.last()) {
+        (Some(latest), Some(oldest)) if prices.len() > 1 => get_trend(oldest.unit_price, latest.unit_price),
+        _ => (None, None),
+    };
+    // prices are newest first, so the first one of a store is its latest
+    let mut cheapest_store: Option<PricePoint> = None;
+    let mut seen_stores: Vec<Uuid> = Vec::new();
+    for price in prices.iter() {
+        let store_id = match price.store_id {
+            Some(store_id) if !seen_stores.contains(&store_id) => store_id,
+            _ => continue,
+        };
+        seen_stores.push(store_id);
+        match &cheapest_store {
+            Some(cheapest) if cheapest.unit_price <= price.unit_price => {},
+            _ => cheapest_store = Some(price.clone()),
+        }
+    }
+
+    Ok(json(&ProductPricesResponse {
+        name: product_name,
+        unit: Some(unit),
+        currency,
+        prices,
+        trend,
+        change_percent,
+        cheapest_store,
+    }))
+}
+
+fn get_trend(oldest: Decimal, latest: Decimal) -> (Option<PriceTrend>, Option<Decimal>) {
+    let trend = if latest > oldest {
+        PriceTrend::UP
+    } else if latest < oldest {
+        PriceTrend::DOWN
+    } else {
+        PriceTrend::STABLE
+    };
+    let change_percent = (latest - oldest)
+        .checked_mul(Decimal::from(100))
+        .and_then(|change| change.checked_div(oldest))
+        .map(|change| change.round_dp(1));
+    (Some(trend), change_percent)
+}
+
+/// Gives an item without a price the latest price paid for the product, preferring the price
+/// of the store the item is bought in.
+pub async fn estimate_price(user_id: &Uuid, item: &mut Item, db: &DBConn) -> Result<(), HttpError> {
+    if item.unit_price.is_some() {
+        return Ok(());
+    }
+    let rows = db.query(
+        "SELECT unit, unit_price, currency FROM price_history
+            WHERE user_id=$1 AND product_name=$2 AND ($3::text IS NULL OR currency=$3)
+            ORDER BY store_id IS NOT DISTINCT FROM $4 DESC, recorded_at DESC
+            LIMIT 1",
+        &[user_id, &normalize_name(&item.name), &item.currency, &item.store_id],
+    ).await.map_err(HttpError::Query)?;
+
+    if let Some(row) = rows.first() {
+        let unit_price = Unit::from_str(row.get("unit"))
+            .ok()
+            .and_then(|unit| unit.convert_price(row.get("unit_price"), &item.unit));
+        if unit_price.is_some() {
+            item.unit_price = unit_price;
+            item.currency = row.get("currency");
+        }
+    }
+    Ok(())
+}
+
+/// Records the price of an item that was just bought. Like learned categories, the history
+/// is a hint, so failing to record it doesn't fail the change of the item.
+pub async fn record_price(user_id: &Uuid, previous: &Item, item: &Item, db: &DBConn) {
+    let unit_price = match item.unit_price {
+        Some(unit_price) if item.bought && !previous.bought => unit_price,
+        _ => return,
+    };
+    let item_id = item.id.as_deref().and_then(|id| Uuid::parse_str(id).ok());
+    let recorded = db.execute(
+        "INSERT INTO price_history (id, user_id, product_name, store_id, item_id, unit, unit_price, currency)
+            VALUES (uuid_generate_v4(), $1, $2, $3, $4, $5, $6, $7)",
+        &[
+            user_id,
+            &normalize_name(&item.name),
+            &item.store_id,
+            &item_id,
+            &item.unit.to_string().as_str(),
+            &unit_price,
+            &item.currency,
+        ],
+    ).await;
+    if let Err(e) = recorded {
+        println!("Failed to record price of {} {:?}", item.name, e);
+    }
+}
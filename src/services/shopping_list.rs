@@ -1,5 +1,7 @@
 use crate::models::shopping_list::{ShoppingList, PartialShoppingList, PartialShoppingListDTO};
-use crate::services::database::{DBConn};
+use crate::services::database::{DBConn, RedisConn};
+use crate::services::events::publish_list_event;
+use crate::models::event::{ListEvent, ListEventMessage};
 use warp::{Reply, Rejection};
 use uuid::Uuid;
 use crate::models::{QueryResponse, Pagination, Model, SqlQueryResponse};
@@ -7,91 +9,160 @@ use warp::http::StatusCode;
 use warp::reply::{Response,json};
 use crate::middlewares::auth::AuthenticatedUser;
 use crate::middlewares::error::HttpError;
-use crate::models::sharing::ShareListBody;
-use crate::models::user::UserResponse;
+use crate::middlewares::precondition::{IfMatch, with_etag, precondition_failed, MAX_UPDATE_ATTEMPTS};
+use crate::models::sharing::{ShareListBody, ShareRole, SharedUserResponse, DEFAULT_SHARE_ROLE};
+use std::str::FromStr;
+use crate::services::households::validate_household_membership;
+use crate::models::price::{ListEstimate, CurrencyTotal};
 
 pub async fn get_shopping_lists(db: DBConn, pagination: Pagination, owner: AuthenticatedUser) -> Result<impl Reply, Rejection> {
     let limit = pagination.get_limit(10);
     let offset = pagination.get_offset();
     // TODO mark shared? provide owner name?
     let db_response = db.query(
-        "SELECT
-            distinct on (l.id) id, 
-            l.*
-         FROM shopping_list l
-         LEFT JOIN shopping_list_share sh ON sh.shopping_list_id=l.id
-         WHERE 
-            l.owner_id=$1 OR sh.target_user_id=$1
+        "SELECT l.* FROM shopping_list l
+         WHERE
+            l.owner_id=$1 OR
+            EXISTS (SELECT 1 FROM shopping_list_share sh WHERE sh.shopping_list_id=l.id AND sh.target_user_id=$1) OR
+            EXISTS (SELECT 1 FROM household_member m WHERE m.household_id=l.household_id AND m.user_id=$1)
          LIMIT $2::int OFFSET $3::int",
         &[&owner.id, &limit, &offset],
     ).await.map_err(|e| HttpError::Query(e))?;
     let total_count = db.query(
         "SELECT count(l.id)::int FROM shopping_list l
-                    LEFT JOIN shopping_list_share sh ON sh.shopping_list_id=l.id
-                    WHERE l.owner_id=$1 OR sh.target_user_id=$1",
+                    WHERE
+                        l.owner_id=$1 OR
+                        EXISTS (SELECT 1 FROM shopping_list_share sh WHERE sh.shopping_list_id=l.id AND sh.target_user_id=$1) OR
+                        EXISTS (SELECT 1 FROM household_member m WHERE m.household_id=l.household_id AND m.user_id=$1)",
         &[&owner.id],
     ).await.map_err(|e| HttpError::Query(e))?;
 
     let total: i32 = total_count.get(0).expect("count failed").get(0);
-    let shopping_lists: Vec<ShoppingList> = db_response.iter().map(|row| ShoppingList::from_row(row)).collect();
+    let mut shopping_lists: Vec<ShoppingList> = db_response.iter().map(ShoppingList::from_row).collect();
+    add_estimates(&mut shopping_lists, &db).await?;
     let response: QueryResponse<ShoppingList> = QueryResponse::new(shopping_lists, total);
 
     Ok(json(&response))
 }
 
 pub async fn create(db: DBConn, owner: AuthenticatedUser, shopping_list: PartialShoppingListDTO) -> Result<impl Reply, Rejection> {
-    let resp = db.query(
-        "INSERT INTO shopping_list (id, title, description, owner_id) VALUES (uuid_generate_v4(), $1, $2, $3) RETURNING *",
-        &[&shopping_list.title.as_str(), &shopping_list.description.as_str(), &owner.id]
-    ).await;
-    match resp {
-        Ok(r) => {
-            let sh_row = r.get(0).expect("insert failed");
-            let created_shopping_list = ShoppingList::from_row(sh_row);
-
-            Ok(json(&created_shopping_list))
-        },
-        Err(e) => Err(warp::reject::custom(HttpError::Query(e)))
+    if let Some(household_id) = &shopping_list.household_id {
+        validate_household_membership(household_id, &owner.id, &db).await?;
     }
+    let created_shopping_list = insert_shopping_list(None, &owner.id, &shopping_list, &db).await?;
+    Ok(json(&created_shopping_list))
 }
 
-pub async fn update(id: Uuid, owner: AuthenticatedUser, db: DBConn, shopping_list: PartialShoppingList) -> Result<impl Reply, Rejection> {
-    validate_shopping_list_access(&id, &owner.id, &db).await?;
+pub async fn get_shopping_list(id: Uuid, owner: AuthenticatedUser, db: DBConn) -> Result<impl Reply, Rejection> {
+    validate_shopping_list_access(&id, &owner.id, ShareRole::VIEWER, &db).await?;
 
-    let existing = db.query(
-      "SELECT * FROM shopping_list WHERE id=$1",
-      &[&id]
-    ).await.map_err(|e| HttpError::Query(e))?;
-    let opt_row = existing.get(0);
-    if let Some(row) = opt_row {
-        let mut existing_shopping_list = ShoppingList::from_row(&row);
-        existing_shopping_list.apply_changes(&shopping_list);
-
-        db.query(
-            "UPDATE shopping_list SET (title,description) = ($2,$3) WHERE id = $1",
-            &[&id, &existing_shopping_list.title, &existing_shopping_list.description],
-        ).await.map_err(|e| HttpError::Query(e))?;
-
-        Ok(warp::reply::with_status(json(&existing_shopping_list), StatusCode::OK))
-    } else {
-        Err(warp::reject::not_found())
-    }
+    let shopping_list = find_shopping_list(&id, &db).await?.ok_or_else(warp::reject::not_found)?;
+    let version = shopping_list.version;
+    let mut shopping_lists = vec![shopping_list];
+    add_estimates(&mut shopping_lists, &db).await?;
+    Ok(with_etag(json(&shopping_lists[0]), version))
+}
+
+pub async fn update(id: Uuid, owner: AuthenticatedUser, db: DBConn, mut redis: RedisConn, if_match: IfMatch, shopping_list: PartialShoppingList) -> Result<impl Reply, Rejection> {
+    validate_shopping_list_access(&id, &owner.id, ShareRole::MANAGER, &db).await?;
+
+    let updated_shopping_list = change_shopping_list(&id, &if_match, &shopping_list, &db).await?;
+    let version = updated_shopping_list.version;
+    let event = ListEvent::ListChanged { list: updated_shopping_list.clone() };
+    publish_list_event(&mut redis, ListEventMessage::new(id, owner.id, event)).await;
+    Ok(with_etag(warp::reply::with_status(json(&updated_shopping_list), StatusCode::OK), version))
 }
 
-pub async fn delete(list_id: Uuid, owner_id: Uuid, mut db: DBConn) -> Result<impl Reply, Rejection> {
+pub async fn delete(list_id: Uuid, owner_id: Uuid, mut db: DBConn, mut redis: RedisConn, if_match: IfMatch) -> Result<impl Reply, Rejection> {
     let has = has_shopping_list(&list_id, &owner_id, &db).await;
     if !has {
         let msg = String::from("Unauthorized");
         return Err(warp::reject::custom(HttpError::Unauthorized(msg)))
     }
 
-    let transaction = db.transaction().await.map_err(|e| HttpError::Query(e))?;
+    remove_shopping_list(&list_id, &owner_id, &if_match, &mut db).await?;
+    publish_list_event(&mut redis, ListEventMessage::new(list_id, owner_id, ListEvent::ListDeleted)).await;
+    Ok(warp::reply::with_status(Response::new("".into()), StatusCode::NO_CONTENT))
+}
 
-    transaction.query("DELETE FROM item WHERE shopping_list_id=$1", &[&list_id]).await.map_err(|e| HttpError::Query(e))?;
-    transaction.query("DELETE FROM shopping_list WHERE id=$1 AND owner_id=$2", &[&list_id, &owner_id]).await.map_err(|e| HttpError::Query(e))?;
+/// Like items, a list created again with the id chosen by the client returns the existing list.
+pub async fn insert_shopping_list(id: Option<Uuid>, owner_id: &Uuid, shopping_list: &PartialShoppingListDTO, db: &DBConn) -> Result<ShoppingList, HttpError> {
+    let resp = db.query(
+        "INSERT INTO shopping_list (id, title, description, owner_id, household_id, budget, currency)
+            VALUES (COALESCE($5, uuid_generate_v4()), $1, $2, $3, $4, $6, $7)
+            ON CONFLICT (id) DO NOTHING
+            RETURNING *",
+        &[
+            &shopping_list.title.as_str(),
+            &shopping_list.description.as_str(),
+            owner_id,
+            &shopping_list.household_id,
+            &id,
+            &shopping_list.budget,
+            &shopping_list.currency,
+        ]
+    ).await.map_err(HttpError::Query)?;
+    if let Some(row) = resp.first() {
+        return Ok(ShoppingList::from_row(row));
+    }
 
-    transaction.commit().await.map_err(|e| HttpError::Query(e))?;
-    Ok(warp::reply::with_status(Response::new("".into()), StatusCode::NO_CONTENT))
+    let existing_id = id.ok_or(HttpError::InternalServerError)?;
+    let existing = db.query(
+        "SELECT * FROM shopping_list WHERE id=$1 AND owner_id=$2",
+        &[&existing_id, owner_id],
+    ).await.map_err(HttpError::Query)?;
+    existing.first()
+        .map(ShoppingList::from_row)
+        .ok_or_else(|| HttpError::Conflict(String::from("Shopping list id is already used")))
+}
+
+/// Same as with items, the update only applies to the version it was computed from.
+pub async fn change_shopping_list(id: &Uuid, if_match: &IfMatch, shopping_list: &PartialShoppingList, db: &DBConn) -> Result<ShoppingList, Rejection> {
+    for _attempt in 0..MAX_UPDATE_ATTEMPTS {
+        let mut existing_shopping_list = find_shopping_list(id, db).await?.ok_or_else(warp::reject::not_found)?;
+        if !if_match.matches(existing_shopping_list.version) {
+            return Err(precondition_failed(&existing_shopping_list, existing_shopping_list.version));
+        }
+        let read_version = existing_shopping_list.version;
+        existing_shopping_list.apply_changes(shopping_list);
+
+        let updated = db.query(
+            "UPDATE shopping_list SET (title,description,budget,currency,version) = ($2,$3,$4,$5,version + 1) WHERE id = $1 AND version = $6 RETURNING *",
+            &[
+                id,
+                &existing_shopping_list.title,
+                &existing_shopping_list.description,
+                &existing_shopping_list.budget,
+                &existing_shopping_list.currency,
+                &read_version,
+            ],
+        ).await.map_err(HttpError::Query)?;
+
+        if let Some(row) = updated.first() {
+            return Ok(ShoppingList::from_row(row));
+        }
+    }
+
+    let msg = String::from("Shopping list is being changed concurrently, try again");
+    Err(warp::reject::custom(HttpError::Conflict(msg)))
+}
+
+/// Deletes the list with all its items, returns whether it still existed.
+pub async fn remove_shopping_list(list_id: &Uuid, owner_id: &Uuid, if_match: &IfMatch, db: &mut DBConn) -> Result<bool, Rejection> {
+    let transaction = db.transaction().await.map_err(HttpError::Query)?;
+
+    // locked, so the version can't change until the list is gone
+    let existing = transaction.query("SELECT * FROM shopping_list WHERE id=$1 FOR UPDATE", &[list_id]).await.map_err(HttpError::Query)?;
+    if let Some(existing) = existing.first().map(ShoppingList::from_row) {
+        if !if_match.matches(existing.version) {
+            return Err(precondition_failed(&existing, existing.version));
+        }
+    }
+    transaction.query("DELETE FROM item WHERE shopping_list_id=$1", &[list_id]).await.map_err(HttpError::Query)?;
+    let deleted = transaction.execute("DELETE FROM shopping_list WHERE id=$1 AND owner_id=$2", &[list_id, owner_id]).await.map_err(HttpError::Query)?;
+
+    transaction.commit().await.map_err(HttpError::Query)?;
+    Ok(deleted > 0)
 }
 
 pub async fn share_list(
@@ -101,11 +172,12 @@ pub async fn share_list(
     db: DBConn,
 ) -> Result<impl Reply, Rejection> {
     let target_user_id = share_list_body.target_user_id;
+    let role = share_list_body.role.unwrap_or(DEFAULT_SHARE_ROLE);
 
-   let has = has_shopping_list(&shopping_list_id, &owner.id, &db).await;
-    if !has {
-        let msg = String::from("Not allowed to share this list");
-        return Err(warp::reject::custom(HttpError::Unauthorized(msg)));
+    validate_sharing_access(&shopping_list_id, &owner.id, &db).await?;
+    if has_shopping_list(&shopping_list_id, &target_user_id, &db).await {
+        let msg = String::from("Can't share list with its owner");
+        return Err(warp::reject::custom(HttpError::Conflict(msg)));
     }
     let is_already_shared = is_shared(&shopping_list_id, &target_user_id, &db).await;
     if is_already_shared {
@@ -114,25 +186,42 @@ pub async fn share_list(
     }
 
     db.query(
-        "INSERT INTO shopping_list_share (shopping_list_id, target_user_id) VALUES ($1, $2)",
-        &[&shopping_list_id, &target_user_id],
-    ).await.map_err(|e| HttpError::Query(e))?;
+        "INSERT INTO shopping_list_share (shopping_list_id, target_user_id, role) VALUES ($1, $2, $3)",
+        &[&shopping_list_id, &target_user_id, &role.to_string()],
+    ).await.map_err(HttpError::Query)?;
 
     Ok(warp::reply::with_status(warp::reply(),StatusCode::CREATED))
 }
 
-pub async fn stop_sharing_list(
+pub async fn update_sharing(
     shopping_list_id: Uuid,
     share_list_body: ShareListBody,
     owner: AuthenticatedUser,
     db: DBConn,
 ) -> Result<impl Reply, Rejection> {
-    let has = has_shopping_list(&shopping_list_id, &owner.id, &db).await;
-    if !has {
-        let msg = String::from("Not allowed to share this list");
-        return Err(warp::reject::custom(HttpError::Unauthorized(msg)));
+    validate_sharing_access(&shopping_list_id, &owner.id, &db).await?;
+
+    let role = share_list_body.role.unwrap_or(DEFAULT_SHARE_ROLE);
+    let updated = db.execute(
+        "UPDATE shopping_list_share SET role=$3 WHERE shopping_list_id=$1 AND target_user_id=$2",
+        &[&shopping_list_id, &share_list_body.target_user_id, &role.to_string()],
+    ).await.map_err(HttpError::Query)?;
+    if updated == 0 {
+        let msg = String::from("List is not shared with this user");
+        return Err(warp::reject::custom(HttpError::NotFound(msg)));
     }
 
+    Ok(warp::reply::with_status(warp::reply(),StatusCode::NO_CONTENT))
+}
+
+pub async fn stop_sharing_list(
+    shopping_list_id: Uuid,
+    share_list_body: ShareListBody,
+    owner: AuthenticatedUser,
+    db: DBConn,
+) -> Result<impl Reply, Rejection> {
+    validate_sharing_access(&shopping_list_id, &owner.id, &db).await?;
+
     db.query(
         "DELETE FROM shopping_list_share WHERE shopping_list_id=$1 AND target_user_id=$2",
         &[&shopping_list_id, &share_list_body.target_user_id],
@@ -146,24 +235,61 @@ pub async fn get_list_sharing(
     owner: AuthenticatedUser,
     db: DBConn,
 ) -> Result<impl Reply, Rejection> {
-    let has = has_shopping_list(&shopping_list_id, &owner.id, &db).await;
-    if !has {
-        let msg = String::from("Not allowed to share this list");
-        return Err(warp::reject::custom(HttpError::Unauthorized(msg)));
-    }
+    validate_sharing_access(&shopping_list_id, &owner.id, &db).await?;
 
     let db_response = db.query(
         "
-        SELECT u.* FROM users u
+        SELECT u.*, sls.role FROM users u
         INNER JOIN shopping_list_share sls ON u.id = sls.target_user_id AND sls.shopping_list_id = $1
         ",
         &[&shopping_list_id],
     ).await.map_err(|e| HttpError::Query(e))?;
 
-    let users: Vec<UserResponse> = db_response.iter().map(UserResponse::from_row).collect();
+    let users: Vec<SharedUserResponse> = db_response.iter().map(SharedUserResponse::from_row).collect();
     Ok(warp::reply::json(&users))
 }
 
+/// Sums the unit prices of the items of each list, per currency. The bought part of an item
+/// that isn't bought completely is what was already put in the cart.
+async fn add_estimates(shopping_lists: &mut [ShoppingList], db: &DBConn) -> Result<(), HttpError> {
+    let ids: Vec<Uuid> = shopping_lists.iter().filter_map(ShoppingList::get_id).collect();
+    let rows = db.query(
+        "SELECT i.shopping_list_id, COALESCE(i.currency, l.currency) AS currency, count(*) AS items,
+                i.unit_price IS NOT NULL AS priced,
+                round(COALESCE(sum(i.unit_price * i.total_amount), 0), 2) AS estimated,
+                round(COALESCE(sum(i.unit_price * CASE WHEN i.bought THEN i.total_amount
+                    ELSE LEAST(i.current_amount, i.total_amount) END), 0), 2) AS bought
+            FROM item i INNER JOIN shopping_list l ON l.id=i.shopping_list_id
+            WHERE i.shopping_list_id = ANY($1)
+            GROUP BY i.shopping_list_id, COALESCE(i.currency, l.currency), i.unit_price IS NOT NULL
+            ORDER BY currency",
+        &[&ids],
+    ).await.map_err(HttpError::Query)?;
+
+    for shopping_list in shopping_lists.iter_mut() {
+        let id = shopping_list.get_id();
+        let mut estimate = ListEstimate::default();
+        for row in rows.iter().filter(|row| Some(row.get::<_, Uuid>("shopping_list_id")) == id) {
+            if row.get("priced") {
+                estimate.totals.push(CurrencyTotal::from_row(row));
+            } else {
+                estimate.unpriced_items += row.get::<_, i64>("items");
+            }
+        }
+        if let Some(budget) = shopping_list.budget {
+            estimate.over_budget = estimate.totals.iter()
+                .any(|total| total.currency == shopping_list.currency && total.estimated > budget);
+        }
+        shopping_list.estimate = Some(estimate);
+    }
+    Ok(())
+}
+
+async fn find_shopping_list(id: &Uuid, db: &DBConn) -> Result<Option<ShoppingList>, HttpError> {
+    let rows = db.query("SELECT * FROM shopping_list WHERE id=$1", &[id]).await.map_err(HttpError::Query)?;
+    Ok(rows.first().map(ShoppingList::from_row))
+}
+
 pub async fn has_shopping_list(id: &Uuid, owner_id: &Uuid, db: &DBConn) -> bool {
     let response = db.query("SELECT count(id) > 0 AS has FROM shopping_list WHERE id=$1 AND owner_id=$2", &[id, owner_id]).await;
     match response {
@@ -179,36 +305,58 @@ pub async fn has_shopping_list(id: &Uuid, owner_id: &Uuid, db: &DBConn) -> bool
     }
 }
 
-async fn has_access_to_hopping_list(shopping_list_id: &Uuid, user_id: &Uuid, db: &DBConn) -> bool {
+/// Role of the user on the list, `None` when the list isn't accessible to them at all.
+/// Access through a direct share and through a household may both exist, the higher role wins.
+pub async fn get_shopping_list_role(shopping_list_id: &Uuid, user_id: &Uuid, db: &DBConn) -> Option<ShareRole> {
     let response = db.query(
-        "SELECT count(id) > 0 AS has
-                  FROM shopping_list l
-                      LEFT JOIN shopping_list_share sh ON sh.shopping_list_id=l.id
-                  WHERE
-                      l.id=$1 AND
-                      (l.owner_id=$2 OR sh.target_user_id=$2)",
+        "SELECT 'OWNER' AS role FROM shopping_list WHERE id=$1 AND owner_id=$2
+                  UNION ALL
+                  SELECT role FROM shopping_list_share WHERE shopping_list_id=$1 AND target_user_id=$2
+                  UNION ALL
+                  SELECT m.role FROM shopping_list l
+                      INNER JOIN household_member m ON m.household_id=l.household_id AND m.user_id=$2
+                  WHERE l.id=$1",
         &[shopping_list_id, user_id],
     ).await;
     match response {
-        Ok(has_row) => {
-            has_row
-                .get(0)
-                .map_or(false, |row| row.get("has"))
+        Ok(rows) => {
+            rows
+                .iter()
+                .filter_map(|row| ShareRole::from_str(row.get("role")).ok())
+                .max()
         }
         Err(_e) => {
-            println!("has_shopping_list query failed {:?}", _e);
-            false
+            println!("get_shopping_list_role query failed {:?}", _e);
+            None
         }
     }
 }
 
-pub async fn validate_shopping_list_access(shopping_list_id: &Uuid, user_id: &Uuid, db: &DBConn) -> Result<bool, Rejection> {
-    let has = has_access_to_hopping_list(shopping_list_id, user_id, db).await;
-    if !has {
-        let msg = String::from("Can't access this shopping list");
-        return Err(warp::reject::custom(HttpError::Forbidden(msg)));
+/// Rejects users without at least the required role on the list and returns their actual role.
+pub async fn validate_shopping_list_access(shopping_list_id: &Uuid, user_id: &Uuid, required_role: ShareRole, db: &DBConn) -> Result<ShareRole, Rejection> {
+    let role = get_shopping_list_role(shopping_list_id, user_id, db).await;
+    match role {
+        Some(role) if role >= required_role => Ok(role),
+        Some(_role) => {
+            let msg = String::from("Not allowed to modify this shopping list");
+            Err(warp::reject::custom(HttpError::Forbidden(msg)))
+        },
+        None => {
+            let msg = String::from("Can't access this shopping list");
+            Err(warp::reject::custom(HttpError::Forbidden(msg)))
+        },
+    }
+}
+
+async fn validate_sharing_access(shopping_list_id: &Uuid, user_id: &Uuid, db: &DBConn) -> Result<ShareRole, Rejection> {
+    let role = get_shopping_list_role(shopping_list_id, user_id, db).await;
+    match role {
+        Some(role) if role >= ShareRole::MANAGER => Ok(role),
+        _ => {
+            let msg = String::from("Not allowed to share this list");
+            Err(warp::reject::custom(HttpError::Unauthorized(msg)))
+        },
     }
-    Ok(true)
 }
 
 async fn is_shared(list_id: &Uuid, user_id: &Uuid, db: &DBConn) -> bool {
@@ -0,0 +1,263 @@
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::time::Duration;
+use chrono::Utc;
+use tokio_postgres::IsolationLevel;
+use uuid::Uuid;
+use warp::{Reply, Rejection};
+use warp::reply::json;
+use crate::middlewares::auth::AuthenticatedUser;
+use crate::middlewares::error::{HttpError, error_match};
+use crate::middlewares::precondition::IfMatch;
+use crate::models::{Model, SqlQueryResponse};
+use crate::models::access_token::Scope;
+use crate::models::event::{ListEvent, ListEventMessage};
+use crate::models::item::Item;
+use crate::models::sharing::ShareRole;
+use crate::models::shopping_list::ShoppingList;
+use crate::models::sync::{SyncQuery, SyncCursor, SyncResponse, SyncItem, SyncShare, Tombstone, SyncMutation, SyncMutationDTO, SyncMutationResult};
+use crate::services::database::{DBConn, DBPool, RedisConn, get_connection};
+use crate::services::events::publish_list_event;
+use crate::services::households::validate_household_membership;
+use crate::services::items::{insert_item, change_item, remove_item, prepare_item, validate_item_changes};
+use crate::services::categories::learn_category;
+use crate::services::prices::record_price;
+use crate::services::shopping_list::{validate_shopping_list_access, has_shopping_list, insert_shopping_list, change_shopping_list, remove_shopping_list};
+
+// deletes are only known for this long, older cursors get everything again
+const TOMBSTONE_RETENTION_DAYS: i64 = 30;
+const TOMBSTONE_CLEANUP_INTERVAL_SECONDS: u64 = 60 * 60;
+
+/// Everything that changed since the cursor on the lists the user can access. Lists the user
+/// gained access to since then, through a share or a household, are sent as a whole.
+pub async fn get_sync(user: AuthenticatedUser, mut db: DBConn, query: SyncQuery) -> Result<impl Reply, Rejection> {
+    let now = Utc::now().timestamp();
+    let since = query.since
+        .and_then(|since| SyncCursor::from_str(&since).ok())
+        .filter(|cursor| now - cursor.issued_at < TOMBSTONE_RETENTION_DAYS * 24 * 60 * 60);
+    let reset = since.is_none();
+    let since_xmin = since.map_or(0, |cursor| cursor.xmin);
+
+    // one snapshot for all reads, so the response is consistent with its cursor
+    let transaction = db.build_transaction()
+        .isolation_level(IsolationLevel::RepeatableRead)
+        .read_only(true)
+        .start()
+        .await
+        .map_err(HttpError::Query)?;
+    // the first query takes the snapshot, transactions from its xmin on may still commit after it
+    let snapshot = transaction.query(
+        "SELECT pg_snapshot_xmin(pg_current_snapshot())::text::bigint AS xmin",
+        &[],
+    ).await.map_err(HttpError::Query)?;
+    let xmin: i64 = snapshot.first().ok_or(HttpError::InternalServerError)?.get("xmin");
+
+    let access_rows = transaction.query(
+        "SELECT id AS shopping_list_id, 'OWNER' AS role, 0::bigint AS change_xid FROM shopping_list WHERE owner_id=$1
+            UNION ALL
+            SELECT shopping_list_id, role, change_xid FROM shopping_list_share WHERE target_user_id=$1
+            UNION ALL
+            SELECT l.id, m.role, GREATEST(m.change_xid, l.household_change_xid) FROM shopping_list l
+                INNER JOIN household_member m ON m.household_id=l.household_id AND m.user_id=$1",
+        &[&user.id],
+    ).await.map_err(HttpError::Query)?;
+    // highest role on each list, and when the user got access to it at all
+    let mut access: HashMap<Uuid, (ShareRole, i64)> = HashMap::new();
+    for row in access_rows.iter() {
+        let role = match ShareRole::from_str(row.get("role")) {
+            Ok(role) => role,
+            Err(_e) => continue,
+        };
+        let gained_xid: i64 = row.get("change_xid");
+        let entry = access.entry(row.get("shopping_list_id")).or_insert((role, gained_xid));
+        *entry = (entry.0.max(role), entry.1.min(gained_xid));
+    }
+
+    let list_ids: Vec<Uuid> = access.keys().copied().collect();
+    let new_list_ids: Vec<Uuid> = access.iter()
+        .filter(|(_id, (_role, gained_xid))| *gained_xid >= since_xmin)
+        .map(|(id, _access)| *id)
+        .collect();
+    let managed_list_ids: Vec<Uuid> = access.iter()
+        .filter(|(_id, (role, _gained_xid))| *role >= ShareRole::MANAGER)
+        .map(|(id, _access)| *id)
+        .collect();
+
+    let list_rows = transaction.query(
+        "SELECT * FROM shopping_list WHERE id = ANY($1) AND (change_xid >= $2 OR id = ANY($3))",
+        &[&list_ids, &since_xmin, &new_list_ids],
+    ).await.map_err(HttpError::Query)?;
+    let item_rows = transaction.query(
+        "SELECT * FROM item WHERE shopping_list_id = ANY($1) AND (change_xid >= $2 OR shopping_list_id = ANY($3))
+            ORDER BY change_id",
+        &[&list_ids, &since_xmin, &new_list_ids],
+    ).await.map_err(HttpError::Query)?;
+    let share_rows = transaction.query(
+        "SELECT u.*, sh.shopping_list_id, sh.role FROM shopping_list_share sh
+            INNER JOIN users u ON u.id=sh.target_user_id
+            WHERE sh.shopping_list_id = ANY($1) AND (sh.change_xid >= $2 OR sh.shopping_list_id = ANY($3))",
+        &[&managed_list_ids, &since_xmin, &new_list_ids],
+    ).await.map_err(HttpError::Query)?;
+    let deleted = if reset {
+        Vec::new()
+    } else {
+        let tombstone_rows = transaction.query(
+            "SELECT * FROM tombstone WHERE change_xid >= $1 AND (
+                (entity_type = 'ITEM' AND shopping_list_id = ANY($2)) OR
+                (entity_type = 'SHARE' AND shopping_list_id = ANY($3)) OR
+                (entity_type = 'LIST' AND $4 = ANY(user_ids))
+            ) ORDER BY change_id",
+            &[&since_xmin, &list_ids, &managed_list_ids, &user.id],
+        ).await.map_err(HttpError::Query)?;
+        tombstone_rows.iter().map(Tombstone::from_row).collect()
+    };
+    transaction.commit().await.map_err(HttpError::Query)?;
+
+    let response = SyncResponse {
+        cursor: SyncCursor { xmin, issued_at: now }.to_string(),
+        reset,
+        list_ids,
+        lists: list_rows.iter().map(ShoppingList::from_row).collect(),
+        items: item_rows.iter().map(|row| SyncItem {
+            shopping_list_id: row.get("shopping_list_id"),
+            item: Item::from_row(row),
+        }).collect(),
+        shares: share_rows.iter().map(SyncShare::from_row).collect(),
+        deleted,
+    };
+    Ok(json(&response))
+}
+
+/// Applies queued offline mutations in order. Each one is checked and applied on its own,
+/// so a failing one is reported in its result without stopping the others.
+pub async fn apply_sync(user: AuthenticatedUser, mut db: DBConn, mut redis: RedisConn, mutations: Vec<SyncMutationDTO>) -> Result<impl Reply, Rejection> {
+    let mut results: Vec<SyncMutationResult> = Vec::new();
+    for SyncMutationDTO { id, mutation } in mutations {
+        let result = match apply_mutation(&user, &id, &mutation, &mut db, &mut redis).await {
+            Ok(result) => result,
+            Err(rejection) => to_failed_result(&id, rejection),
+        };
+        results.push(result);
+    }
+    Ok(json(&results))
+}
+
+async fn apply_mutation(user: &AuthenticatedUser, id: &str, mutation: &SyncMutation, db: &mut DBConn, redis: &mut RedisConn) -> Result<SyncMutationResult, Rejection> {
+    let mut result = SyncMutationResult::new(id, 200);
+    match mutation {
+        SyncMutation::CreateList { shopping_list_id, list } => {
+            validate_scope(user, Scope::ListsWrite)?;
+            if let Some(household_id) = &list.household_id {
+                validate_household_membership(household_id, &user.id, db).await?;
+            }
+            let created = insert_shopping_list(Some(*shopping_list_id), &user.id, list, db).await?;
+            result.status = 201;
+            result.list = Some(created);
+        },
+        SyncMutation::UpdateList { shopping_list_id, base_version, changes } => {
+            validate_scope(user, Scope::ListsWrite)?;
+            validate_shopping_list_access(shopping_list_id, &user.id, ShareRole::MANAGER, db).await?;
+            let if_match = IfMatch::from_version(*base_version);
+            let updated = change_shopping_list(shopping_list_id, &if_match, changes, db).await?;
+            let event = ListEvent::ListChanged { list: updated.clone() };
+            publish_list_event(redis, ListEventMessage::new(*shopping_list_id, user.id, event)).await;
+            result.list = Some(updated);
+        },
+        SyncMutation::DeleteList { shopping_list_id, base_version } => {
+            validate_scope(user, Scope::ListsWrite)?;
+            if !has_shopping_list(shopping_list_id, &user.id, db).await {
+                let msg = String::from("Only the owner can delete a shopping list");
+                return Err(warp::reject::custom(HttpError::Forbidden(msg)));
+            }
+            let if_match = IfMatch::from_version(*base_version);
+            if remove_shopping_list(shopping_list_id, &user.id, &if_match, db).await? {
+                publish_list_event(redis, ListEventMessage::new(*shopping_list_id, user.id, ListEvent::ListDeleted)).await;
+            }
+            result.status = 204;
+        },
+        SyncMutation::CreateItem { shopping_list_id, item_id, item } => {
+            validate_shopping_list_access(shopping_list_id, &user.id, ShareRole::EDITOR, db).await?;
+            let mut item = item.clone();
+            prepare_item(&user.id, &mut item, db).await?;
+            let created = insert_item(shopping_list_id, Some(*item_id), &item, db).await?;
+            learn_category(&user.id, &created, db).await;
+            let event = ListEvent::ItemCreated { item: created.clone() };
+            publish_list_event(redis, ListEventMessage::new(*shopping_list_id, user.id, event)).await;
+            result.status = 201;
+            result.item = Some(created);
+        },
+        SyncMutation::UpdateItem { shopping_list_id, item_id, base_version, changes } => {
+            validate_shopping_list_access(shopping_list_id, &user.id, ShareRole::EDITOR, db).await?;
+            let if_match = IfMatch::from_version(*base_version);
+            validate_item_changes(&user.id, changes, db).await?;
+            let (previous, updated) = change_item(shopping_list_id, item_id, &if_match, changes, db).await?;
+            if changes.category_id.is_some() {
+                learn_category(&user.id, &updated, db).await;
+            }
+            record_price(&user.id, &previous, &updated, db).await;
+            let event = ListEvent::ItemUpdated { item: updated.clone() };
+            publish_list_event(redis, ListEventMessage::new(*shopping_list_id, user.id, event)).await;
+            result.item = Some(updated);
+        },
+        SyncMutation::DeleteItem { shopping_list_id, item_id, base_version } => {
+            validate_shopping_list_access(shopping_list_id, &user.id, ShareRole::EDITOR, db).await?;
+            let if_match = IfMatch::from_version(*base_version);
+            if remove_item(shopping_list_id, item_id, &if_match, db).await? {
+                let event = ListEvent::ItemDeleted { id: *item_id };
+                publish_list_event(redis, ListEventMessage::new(*shopping_list_id, user.id, event)).await;
+            }
+            result.status = 204;
+        },
+    }
+    Ok(result)
+}
+
+/// Batches are accepted with `items:write`, changing lists needs `lists:write` as well.
+fn validate_scope(user: &AuthenticatedUser, scope: Scope) -> Result<(), Rejection> {
+    if !user.has_scope(scope) {
+        let msg = format!("Token is missing the {} scope", scope);
+        return Err(warp::reject::custom(HttpError::Forbidden(msg)));
+    }
+    Ok(())
+}
+
+fn to_failed_result(id: &str, rejection: Rejection) -> SyncMutationResult {
+    let mut result = SyncMutationResult::new(id, 404);
+    if rejection.is_not_found() {
+        result.message = Some(String::from("Not found"));
+    } else if let Some(HttpError::PreconditionFailed(current, _version)) = rejection.find::<HttpError>() {
+        result.status = 412;
+        result.message = Some(String::from("Resource was modified"));
+        result.current = Some(current.clone());
+    } else if let Some(e) = rejection.find::<HttpError>() {
+        let (status, message, _errors) = error_match(e);
+        result.status = status.as_u16();
+        result.message = Some(String::from(message));
+    } else {
+        result.status = 500;
+        result.message = Some(String::from("Internal server error"));
+    }
+    result
+}
+
+/// Drops tombstones past their retention, cursors that old get a full sync instead.
+pub fn init_tombstone_cleanup(pg_pool: DBPool) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(Duration::from_secs(TOMBSTONE_CLEANUP_INTERVAL_SECONDS));
+        loop {
+            interval.tick().await;
+            if let Err(e) = delete_expired_tombstones(&pg_pool).await {
+                println!("Failed to delete expired tombstones {:?}", e);
+            }
+        }
+    });
+}
+
+async fn delete_expired_tombstones(pg_pool: &DBPool) -> Result<(), Rejection> {
+    let db = get_connection(pg_pool.clone(), "postgres").await?;
+    db.execute(
+        "DELETE FROM tombstone WHERE deleted_at < now() - make_interval(days => $1::int)",
+        &[&(TOMBSTONE_RETENTION_DAYS as i32)],
+    ).await.map_err(HttpError::Query)?;
+    Ok(())
+}
@@ -0,0 +1,156 @@
+use ring::hmac;
+use rand::Rng;
+use percent_encoding::{utf8_percent_encode, NON_ALPHANUMERIC};
+
+const ISSUER: &str = "shopping_list";
+const SECRET_LENGTH: usize = 20;
+const STEP_SECONDS: u64 = 30;
+const DIGITS: u32 = 6;
+// codes of the previous and next time step are accepted to tolerate clock drift
+const ALLOWED_DRIFT_STEPS: u64 = 1;
+const BASE32_ALPHABET: &[u8; 32] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ234567";
+
+pub fn generate_secret() -> Vec<u8> {
+    let secret: [u8; SECRET_LENGTH] = rand::thread_rng().gen();
+    secret.to_vec()
+}
+
+pub fn get_otpauth_uri(secret: &[u8], username: &str) -> String {
+    let label = utf8_percent_encode(username, NON_ALPHANUMERIC);
+    format!(
+        "otpauth://totp/{issuer}:{label}?secret={secret}&issuer={issuer}&algorithm=SHA1&digits={digits}&period={period}",
+        issuer = ISSUER,
+        label = label,
+        secret = encode_base32(secret),
+        digits = DIGITS,
+        period = STEP_SECONDS,
+    )
+}
+
+/// Checks the code against RFC 6238 TOTP with HMAC-SHA1 and returns the time step it belongs to,
+/// callers use the step to reject a code that was already used.
+pub fn verify_code(secret: &[u8], code: &str, unix_seconds: u64) -> Option<u64> {
+    let code = code.trim();
+    if code.len() != DIGITS as usize || !code.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    let current_step = unix_seconds / STEP_SECONDS;
+    let first_step = current_step.saturating_sub(ALLOWED_DRIFT_STEPS);
+    (first_step..=current_step + ALLOWED_DRIFT_STEPS)
+        .find(|step| get_code(secret, *step) == code)
+}
+
+fn get_code(secret: &[u8], step: u64) -> String {
+    let key = hmac::Key::new(hmac::HMAC_SHA1_FOR_LEGACY_USE_ONLY, secret);
+    let tag = hmac::sign(&key, &step.to_be_bytes());
+    let hash = tag.as_ref();
+    let offset = (hash[hash.len() - 1] & 0x0f) as usize;
+    let binary = u32::from_be_bytes([hash[offset] & 0x7f, hash[offset + 1], hash[offset + 2], hash[offset + 3]]);
+    format!("{:0width$}", binary % 10u32.pow(DIGITS), width = DIGITS as usize)
+}
+
+pub fn encode_base32(data: &[u8]) -> String {
+    let mut encoded = String::new();
+    let mut buffer: u32 = 0;
+    let mut bits = 0;
+    for byte in data {
+        buffer = (buffer << 8) | *byte as u32;
+        bits += 8;
+        while bits >= 5 {
+            bits -= 5;
+            encoded.push(BASE32_ALPHABET[((buffer >> bits) & 0x1f) as usize] as char);
+        }
+    }
+    if bits > 0 {
+        encoded.push(BASE32_ALPHABET[((buffer << (5 - bits)) & 0x1f) as usize] as char);
+    }
+    encoded
+}
+
+pub fn decode_base32(encoded: &str) -> Option<Vec<u8>> {
+    let mut decoded = Vec::new();
+    let mut buffer: u32 = 0;
+    let mut bits = 0;
+    for c in encoded.trim_end_matches('=').chars() {
+        let value = BASE32_ALPHABET.iter().position(|a| *a as char == c.to_ascii_uppercase())?;
+        buffer = (buffer << 5) | value as u32;
+        bits += 5;
+        if bits >= 8 {
+            bits -= 8;
+            decoded.push((buffer >> bits) as u8);
+        }
+    }
+    Some(decoded)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // secret of the SHA-1 test vectors in RFC 6238 appendix B
+    const RFC_SECRET: &[u8] = b"12345678901234567890";
+
+    #[test]
+    fn get_code_matches_rfc_6238_vectors() {
+        // the RFC lists 8 digit codes, ours are their last 6 digits
+        let vectors = [
+            (59, "287082"),
+            (1111111109, "081804"),
+            (1111111111, "050471"),
+            (1234567890, "005924"),
+            (2000000000, "279037"),
+            (20000000000, "353130"),
+        ];
+        for (unix_seconds, code) in vectors.iter() {
+            assert_eq!(get_code(RFC_SECRET, unix_seconds / STEP_SECONDS), *code, "T={}", unix_seconds);
+        }
+    }
+
+    #[test]
+    fn verify_code_accepts_one_step_of_drift() {
+        let step = 1111111111 / STEP_SECONDS;
+        let code = get_code(RFC_SECRET, step);
+        let step_start = step * STEP_SECONDS;
+
+        assert_eq!(verify_code(RFC_SECRET, &code, step_start), Some(step));
+        assert_eq!(verify_code(RFC_SECRET, &code, step_start - 1), Some(step));
+        assert_eq!(verify_code(RFC_SECRET, &code, step_start - STEP_SECONDS), Some(step));
+        assert_eq!(verify_code(RFC_SECRET, &code, step_start + 2 * STEP_SECONDS - 1), Some(step));
+        assert_eq!(verify_code(RFC_SECRET, &code, step_start - STEP_SECONDS - 1), None);
+        assert_eq!(verify_code(RFC_SECRET, &code, step_start + 2 * STEP_SECONDS), None);
+    }
+
+    #[test]
+    fn verify_code_rejects_malformed_codes() {
+        assert_eq!(verify_code(RFC_SECRET, " 287082 ", 59), Some(1));
+        assert_eq!(verify_code(RFC_SECRET, "94287082", 59), None);
+        assert_eq!(verify_code(RFC_SECRET, "28708", 59), None);
+        assert_eq!(verify_code(RFC_SECRET, "28708a", 59), None);
+    }
+
+    #[test]
+    fn base32_matches_rfc_4648_vectors() {
+        // padding is left out when encoding and ignored when decoding
+        let vectors = [
+            ("", ""),
+            ("f", "MY======"),
+            ("fo", "MZXQ===="),
+            ("foo", "MZXW6==="),
+            ("foob", "MZXW6YQ="),
+            ("fooba", "MZXW6YTB"),
+            ("foobar", "MZXW6YTBOI======"),
+        ];
+        for (data, encoded) in vectors.iter() {
+            assert_eq!(encode_base32(data.as_bytes()), encoded.trim_end_matches('='));
+            assert_eq!(decode_base32(encoded).as_deref(), Some(data.as_bytes()));
+            assert_eq!(decode_base32(&encoded.to_lowercase()).as_deref(), Some(data.as_bytes()));
+        }
+    }
+
+    #[test]
+    fn base32_round_trips_secrets() {
+        let secret = generate_secret();
+        assert_eq!(decode_base32(&encode_base32(&secret)), Some(secret));
+        assert_eq!(decode_base32("MZXW1"), None);
+    }
+}
@@ -0,0 +1,203 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+use mobc_redis::redis::{self, AsyncCommands};
+use rand::Rng;
+use rand::distributions::Alphanumeric;
+use uuid::Uuid;
+use warp::{Reply, Rejection, reject};
+use warp::http::StatusCode;
+use warp::reply::{json, with_status};
+use crate::middlewares::auth::AuthenticatedUser;
+use crate::middlewares::error::HttpError;
+use crate::models::two_factor::{TwoFactorEnrollmentResponse, RecoveryCodesResponse, TwoFactorCodeDTO};
+use crate::services::database::{DBConn, RedisConn};
+use crate::services::access_tokens::hash_token;
+use crate::services::login_throttle::{LoginThrottle, ClientAddress};
+use crate::services::totp::{generate_secret, get_otpauth_uri, verify_code, encode_base32, decode_base32};
+
+const ENROLLMENT_EXPIRATION_SECONDS: usize = 10 * 60;
+const CHALLENGE_EXPIRATION_SECONDS: usize = 5 * 60;
+const CHALLENGE_MAX_ATTEMPTS: usize = 5;
+const CHALLENGE_LENGTH: usize = 48;
+// long enough to cover every time step accepted by verify_code
+const USED_CODE_EXPIRATION_SECONDS: usize = 120;
+const RECOVERY_CODE_COUNT: usize = 10;
+const RECOVERY_CODE_LENGTH: usize = 10;
+
+/// Starts enrollment with a new secret, it's only stored on the user once confirmed with a valid code.
+pub async fn enroll(user: AuthenticatedUser, db: DBConn, mut redis: RedisConn) -> Result<impl Reply, Rejection> {
+    let resp = db.query(
+        "SELECT username, totp_enabled FROM users WHERE id=$1",
+        &[&user.id],
+    ).await.map_err(HttpError::Query)?;
+    let row = resp.first()
+        .ok_or_else(|| reject::custom(HttpError::NotFound(String::from("User not found"))))?;
+    let totp_enabled: bool = row.get("totp_enabled");
+    if totp_enabled {
+        let msg = String::from("Two factor authentication is already enabled");
+        return Err(reject::custom(HttpError::Conflict(msg)));
+    }
+
+    let secret = generate_secret();
+    let username: String = row.get("username");
+    let _: () = redis.set_ex(get_redis_enrollment_key(&user.id), encode_base32(&secret), ENROLLMENT_EXPIRATION_SECONDS)
+        .await.map_err(HttpError::Redis)?;
+
+    let response = TwoFactorEnrollmentResponse {
+        secret: encode_base32(&secret),
+        otpauth_uri: get_otpauth_uri(&secret, &username),
+    };
+    Ok(with_status(json(&response), StatusCode::CREATED))
+}
+
+pub async fn confirm_enrollment(user: AuthenticatedUser, db: DBConn, mut redis: RedisConn, body: TwoFactorCodeDTO) -> Result<impl Reply, Rejection> {
+    let enrollment_key = get_redis_enrollment_key(&user.id);
+    let pending_secret: Option<String> = redis.get(&enrollment_key).await.map_err(HttpError::Redis)?;
+    let encoded_secret = pending_secret
+        .ok_or_else(|| reject::custom(HttpError::NotFound(String::from("No pending two factor enrollment"))))?;
+    let secret = decode_base32(&encoded_secret).ok_or(HttpError::InternalServerError)?;
+
+    let step = verify_code(&secret, &body.code, get_unix_seconds())
+        .ok_or_else(|| reject::custom(HttpError::Unauthorized(String::from("Invalid code"))))?;
+    mark_code_used(&user.id, step, &mut redis).await?;
+
+    let recovery_codes = generate_recovery_codes();
+    let recovery_code_hashes: Vec<String> = recovery_codes.iter().map(|code| hash_recovery_code(code)).collect();
+    db.execute(
+        "UPDATE users SET (totp_secret, totp_enabled, recovery_codes) = ($2, true, $3) WHERE id=$1",
+        &[&user.id, &encoded_secret, &recovery_code_hashes],
+    ).await.map_err(HttpError::Query)?;
+    redis.del::<_, ()>(&enrollment_key).await.map_err(HttpError::Redis)?;
+
+    Ok(json(&RecoveryCodesResponse { recovery_codes }))
+}
+
+pub async fn disable(user: AuthenticatedUser, db: DBConn, mut redis: RedisConn, body: TwoFactorCodeDTO) -> Result<impl Reply, Rejection> {
+    let is_valid = verify_second_factor(&user.id, &body.code, &db, &mut redis).await?;
+    if !is_valid {
+        return Err(reject::custom(HttpError::Unauthorized(String::from("Invalid code"))));
+    }
+
+    db.execute(
+        "UPDATE users SET (totp_secret, totp_enabled, recovery_codes) = (NULL, false, '{}') WHERE id=$1",
+        &[&user.id],
+    ).await.map_err(HttpError::Query)?;
+
+    Ok(with_status(warp::reply(), StatusCode::NO_CONTENT))
+}
+
+/// Issued after a valid password when the user has two factor authentication enabled,
+/// the challenge is exchanged for a session token together with a valid code.
+/// Refused while the username or client is locked out, as the password alone isn't a login yet.
+pub async fn create_challenge(user_id: &Uuid, username: &str, client: &ClientAddress, redis: &mut RedisConn) -> Result<String, HttpError> {
+    LoginThrottle::new(username, client).check(redis).await?;
+
+    let challenge: String = rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(CHALLENGE_LENGTH)
+        .map(char::from)
+        .collect();
+    // the username is kept to count wrong codes towards the login throttle of the user
+    let value = format!("{}:{}", user_id, username);
+    let _: () = redis.set_ex(get_redis_challenge_key(&challenge), value, CHALLENGE_EXPIRATION_SECONDS)
+        .await.map_err(HttpError::Redis)?;
+    Ok(challenge)
+}
+
+/// Returns the user of the challenge when the code is valid. The challenge is dropped
+/// after it was used or after too many wrong codes, wrong codes also count as failed logins.
+pub async fn complete_challenge(challenge: &str, code: &str, client: &ClientAddress, db: &DBConn, redis: &mut RedisConn) -> Result<Uuid, HttpError> {
+    let challenge_key = get_redis_challenge_key(challenge);
+    let attempts_key = format!("{}:attempts", challenge_key);
+    let value: Option<String> = redis.get(&challenge_key).await.map_err(HttpError::Redis)?;
+    let (user_id, username) = value.as_deref()
+        .and_then(|value| value.split_once(':'))
+        .and_then(|(id, username)| Uuid::parse_str(id).ok().map(|id| (id, username)))
+        .ok_or_else(|| HttpError::Unauthorized(String::from("Invalid or expired challenge")))?;
+
+    let throttle = LoginThrottle::new(username, client);
+    throttle.check(redis).await?;
+
+    if verify_second_factor(&user_id, code, db, redis).await? {
+        redis.del::<_, ()>(&[challenge_key, attempts_key]).await.map_err(HttpError::Redis)?;
+        throttle.reset(redis).await?;
+        return Ok(user_id);
+    }
+
+    throttle.register_failure(redis).await?;
+    let attempts: usize = redis.incr(&attempts_key, 1).await.map_err(HttpError::Redis)?;
+    let _: () = redis.expire(&attempts_key, CHALLENGE_EXPIRATION_SECONDS).await.map_err(HttpError::Redis)?;
+    if attempts >= CHALLENGE_MAX_ATTEMPTS {
+        redis.del::<_, ()>(&[challenge_key, attempts_key]).await.map_err(HttpError::Redis)?;
+    }
+    Err(HttpError::Unauthorized(String::from("Invalid code")))
+}
+
+/// Accepts either a current TOTP code or an unused recovery code, which is consumed.
+async fn verify_second_factor(user_id: &Uuid, code: &str, db: &DBConn, redis: &mut RedisConn) -> Result<bool, HttpError> {
+    let resp = db.query(
+        "SELECT totp_secret FROM users WHERE id=$1 AND totp_enabled",
+        &[user_id],
+    ).await.map_err(HttpError::Query)?;
+    let encoded_secret: Option<String> = resp.first().and_then(|row| row.get("totp_secret"));
+    let secret = match encoded_secret.as_deref().and_then(decode_base32) {
+        Some(secret) => secret,
+        None => return Ok(false),
+    };
+
+    if let Some(step) = verify_code(&secret, code, get_unix_seconds()) {
+        return mark_code_used(user_id, step, redis).await.map(|_| true).or(Ok(false));
+    }
+
+    let used_recovery_code = db.execute(
+        "UPDATE users SET recovery_codes = array_remove(recovery_codes, $2) WHERE id=$1 AND $2 = ANY(recovery_codes)",
+        &[user_id, &hash_recovery_code(code)],
+    ).await.map_err(HttpError::Query)?;
+    Ok(used_recovery_code > 0)
+}
+
+/// Fails when the code of this time step was used before, so an observed code can't be replayed.
+async fn mark_code_used(user_id: &Uuid, step: u64, redis: &mut RedisConn) -> Result<(), HttpError> {
+    let is_new: Option<String> = redis::cmd("SET")
+        .arg(format!("totp_used:{}:{}", user_id, step))
+        .arg(1)
+        .arg("NX")
+        .arg("EX")
+        .arg(USED_CODE_EXPIRATION_SECONDS)
+        .query_async(&mut **redis)
+        .await
+        .map_err(HttpError::Redis)?;
+    is_new.map(|_| ()).ok_or_else(|| HttpError::Unauthorized(String::from("Code was already used")))
+}
+
+fn generate_recovery_codes() -> Vec<String> {
+    (0..RECOVERY_CODE_COUNT)
+        .map(|_| {
+            let code: String = rand::thread_rng()
+                .sample_iter(&Alphanumeric)
+                .take(RECOVERY_CODE_LENGTH)
+                .map(|c| char::from(c).to_ascii_lowercase())
+                .collect();
+            format!("{}-{}", &code[..RECOVERY_CODE_LENGTH / 2], &code[RECOVERY_CODE_LENGTH / 2..])
+        })
+        .collect()
+}
+
+fn hash_recovery_code(code: &str) -> String {
+    let normalized: String = code.chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .map(|c| c.to_ascii_lowercase())
+        .collect();
+    hash_token(&normalized)
+}
+
+fn get_unix_seconds() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or_default()
+}
+
+fn get_redis_enrollment_key(user_id: &Uuid) -> String {
+    format!("totp_enrollment:{}", user_id)
+}
+
+fn get_redis_challenge_key(challenge: &str) -> String {
+    format!("2fa_challenge:{}", challenge)
+}
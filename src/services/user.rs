@@ -1,22 +1,33 @@
-use crate::models::user::{User, LoginDTO, UserResponse, SearchQuery, UserResponseWithSharing};
+use crate::models::user::{User, LoginDTO, UserResponse, SearchQuery, UserResponseWithSharing, RefreshTokenDTO, SessionResponse, ChangePasswordDTO, PasswordResetRequestDTO, PasswordResetDTO};
 use warp::reply::{with_status, json};
 use warp::{Reply,Rejection,reject};
 use crate::services::database::{DBConn, RedisConn};
+use crate::services::keys::KeyStore;
+use crate::services::notifier::SharedNotifier;
+use crate::services::login_throttle::{LoginThrottle, ClientAddress};
+use crate::services::two_factor::{create_challenge, complete_challenge};
+use crate::models::two_factor::{TwoFactorChallengeResponse, TwoFactorLoginDTO};
+use mobc_redis::redis::{self, AsyncCommands};
+use rand::distributions::Alphanumeric;
 use argon2::{hash_encoded, verify_encoded, Config};
 use warp::hyper::StatusCode;
 use crate::middlewares::error::HttpError;
-use crate::middlewares::auth::{create_token, delete_user_tokens, AuthenticatedUser};
+use crate::middlewares::auth::{create_token, delete_user_tokens, refresh_token, revoke_session, revoke_other_sessions, get_user_sessions, AuthenticatedUser, SessionTokens};
 use uuid::Uuid;
 use serde::Serialize;
 use tokio_postgres::types::ToSql;
 use crate::models::{Pagination, QueryResponse, SqlQueryResponse};
-use crate::services::shopping_list::has_shopping_list;
+use crate::services::shopping_list::get_shopping_list_role;
+use crate::models::sharing::ShareRole;
 use rand::Rng;
 
-pub async fn create_user(db: DBConn, redis: RedisConn, user: User) -> Result<impl Reply, Rejection>  {
-    let config = Config::default();
-    let salt: [u8; 32] = rand::thread_rng().gen();
-    let hash = hash_encoded(&user.password.as_bytes(), &salt, &config).unwrap();
+const DUMMY_PASSWORD_HASH: &str = "$argon2i$v=19$m=4096,t=3,p=1$c2hvcHBpbmdfbGlzdF9kdW1teV9zYWx0$wgmSzTXHDAvZe+m2bfH9J9DBpQuXB1wys/PaJKRmb6U";
+const PASSWORD_RESET_EXPIRATION_ENV_KEY: &str = "PASSWORD_RESET_EXPIRE_MILLIS";
+const DEFAULT_PASSWORD_RESET_EXPIRATION: usize = 30 * 60 * 1000;
+const PASSWORD_RESET_TOKEN_LENGTH: usize = 48;
+
+pub async fn create_user(db: DBConn, redis: RedisConn, keys: KeyStore, user_agent: Option<String>, user: User) -> Result<impl Reply, Rejection>  {
+    let hash = hash_password(&user.password);
 
     let has_user_response = db.query("SELECT count(u.username) > 0 as has_user FROM users u WHERE u.username=$1", &[&user.username])
         .await.map_err(|e| HttpError::Query(e))?;
@@ -33,14 +44,11 @@ pub async fn create_user(db: DBConn, redis: RedisConn, user: User) -> Result<imp
 
     let sh_row = resp.get(0).expect("insert failed");
     let id = sh_row.get("id");
-    let token_result = get_token(id, redis).await;
+    let token_result = get_token(id, user_agent, &keys, redis).await;
 
     match token_result {
-        Ok(token) => {
-            let user_response = TokenResponse {
-                id,
-                token,
-            };
+        Ok(tokens) => {
+            let user_response = TokenResponse::new(id, tokens);
             Ok(with_status(json(&user_response), StatusCode::CREATED))
         },
         Err(e) => {
@@ -54,38 +62,180 @@ pub async fn create_user(db: DBConn, redis: RedisConn, user: User) -> Result<imp
     
 }
 
-pub async fn login_handler(db: DBConn, redis: RedisConn, credentials: LoginDTO) -> Result<impl Reply, Rejection> {
+pub async fn login_handler(
+    db: DBConn,
+    mut redis: RedisConn,
+    keys: KeyStore,
+    user_agent: Option<String>,
+    client: ClientAddress,
+    credentials: LoginDTO,
+) -> Result<impl Reply, Rejection> {
+    let throttle = LoginThrottle::new(&credentials.username, &client);
+    throttle.check(&mut redis).await?;
+
     let resp = db.query(
-        "SELECT id, password FROM users WHERE username=$1",
+        "SELECT id, password, totp_enabled FROM users WHERE username=$1",
         &[&credentials.username.as_str()]
     ).await.map_err(|e| reject::custom(HttpError::Query(e)))?;
-    let row = resp.get(0)
-        .ok_or(false)
-        .map_err(|_f| reject::custom(HttpError::Unauthorized(String::from("Invalid username"))))?;
-
-    let hash: String = row.get(1);
 
+    // unknown usernames still go through a password check, so they take as long to reject as wrong passwords
+    let hash: String = resp.first().map_or(String::from(DUMMY_PASSWORD_HASH), |row| row.get(1));
     let is_password_valid = verify_encoded(&hash, credentials.password.as_bytes())
-        .map_err(reject_password)?;
+        .map_err(|_e| HttpError::InternalServerError)?;
 
-    match is_password_valid {
-        true => {
+    match resp.first() {
+        Some(row) if is_password_valid => {
             let id: Uuid = row.get(0);
-            let token_response = get_token(id, redis).await.map(|token| TokenResponse{ token, id })?;
+            let totp_enabled: bool = row.get(2);
+            // with two factor authentication the throttle is only reset once the code is valid
+            if totp_enabled {
+                let challenge = create_challenge(&id, &credentials.username, &client, &mut redis).await?;
+                let challenge_response = TwoFactorChallengeResponse {
+                    id,
+                    two_factor_required: true,
+                    challenge,
+                };
+                return Ok(with_status(
+                    json(&challenge_response),
+                    StatusCode::OK,
+                ));
+            }
+            throttle.reset(&mut redis).await?;
+            let token_response = get_token(id, user_agent, &keys, redis).await.map(|tokens| TokenResponse::new(id, tokens))?;
             Ok(with_status(
                 json(&token_response),
                 StatusCode::OK,
             ))
         },
-        false => Err(reject_password("")),
+        _ => {
+            throttle.register_failure(&mut redis).await?;
+            Err(reject::custom(HttpError::Unauthorized(String::from("Invalid username or password"))))
+        },
     }
 }
 
+pub async fn login_two_factor_handler(
+    db: DBConn,
+    mut redis: RedisConn,
+    keys: KeyStore,
+    user_agent: Option<String>,
+    client: ClientAddress,
+    body: TwoFactorLoginDTO,
+) -> Result<impl Reply, Rejection> {
+    let id = complete_challenge(&body.challenge, &body.code, &client, &db, &mut redis).await?;
+    let token_response = get_token(id, user_agent, &keys, redis).await.map(|tokens| TokenResponse::new(id, tokens))?;
+    Ok(with_status(
+        json(&token_response),
+        StatusCode::OK,
+    ))
+}
+
+pub async fn refresh_token_handler(redis: RedisConn, keys: KeyStore, body: RefreshTokenDTO) -> Result<impl Reply, Rejection> {
+    let (id, tokens) = refresh_token(&body.refresh_token, &keys, redis).await?;
+    Ok(with_status(
+        json(&TokenResponse::new(id, tokens)),
+        StatusCode::OK,
+    ))
+}
+
 pub async fn logout_handler(user: AuthenticatedUser, redis: RedisConn) -> Result<impl Reply, Rejection> {
-    delete_user_tokens(&user.id, redis).await?;
+    revoke_session(&user.id, &user.session_id, redis).await?;
     Ok(warp::reply())
 }
 
+pub async fn get_sessions(user: AuthenticatedUser, redis: RedisConn) -> Result<impl Reply, Rejection> {
+    let sessions: Vec<SessionResponse> = get_user_sessions(&user.id, redis).await?
+        .into_iter()
+        .map(|session| SessionResponse {
+            id: session.id,
+            created_at: session.created_at,
+            last_seen: session.last_seen,
+            user_agent: session.user_agent,
+            current: session.id == user.session_id,
+        })
+        .collect();
+    Ok(json(&sessions))
+}
+
+pub async fn delete_session(session_id: Uuid, user: AuthenticatedUser, redis: RedisConn) -> Result<impl Reply, Rejection> {
+    revoke_session(&user.id, &session_id, redis).await?;
+    Ok(with_status(warp::reply(), StatusCode::NO_CONTENT))
+}
+
+pub async fn delete_other_sessions(user: AuthenticatedUser, redis: RedisConn) -> Result<impl Reply, Rejection> {
+    revoke_other_sessions(&user.id, &user.session_id, redis).await?;
+    Ok(with_status(warp::reply(), StatusCode::NO_CONTENT))
+}
+
+pub async fn change_password(user: AuthenticatedUser, db: DBConn, redis: RedisConn, body: ChangePasswordDTO) -> Result<impl Reply, Rejection> {
+    let resp = db.query(
+        "SELECT password FROM users WHERE id=$1",
+        &[&user.id],
+    ).await.map_err(HttpError::Query)?;
+    let row = resp.first()
+        .ok_or_else(|| reject::custom(HttpError::NotFound(String::from("User not found"))))?;
+
+    let hash: String = row.get("password");
+    let is_password_valid = verify_encoded(&hash, body.old_password.as_bytes())
+        .map_err(reject_password)?;
+    if !is_password_valid {
+        return Err(reject_password(""));
+    }
+
+    update_password(&user.id, &body.new_password, &db).await?;
+    revoke_other_sessions(&user.id, &user.session_id, redis).await?;
+
+    Ok(with_status(warp::reply(), StatusCode::NO_CONTENT))
+}
+
+/// Always answers with 202 so the endpoint can't be used to find out which usernames exist.
+pub async fn request_password_reset(db: DBConn, mut redis: RedisConn, notifier: SharedNotifier, body: PasswordResetRequestDTO) -> Result<impl Reply, Rejection> {
+    let resp = db.query(
+        "SELECT * FROM users WHERE username=$1",
+        &[&body.username],
+    ).await.map_err(HttpError::Query)?;
+
+    if let Some(row) = resp.first() {
+        let user = UserResponse::from_row(row);
+        let token: String = rand::thread_rng()
+            .sample_iter(&Alphanumeric)
+            .take(PASSWORD_RESET_TOKEN_LENGTH)
+            .map(char::from)
+            .collect();
+        let expiration = dotenv::var(PASSWORD_RESET_EXPIRATION_ENV_KEY)
+            .ok()
+            .and_then(|value| value.parse::<usize>().ok())
+            .unwrap_or(DEFAULT_PASSWORD_RESET_EXPIRATION);
+
+        let _: () = redis.pset_ex(get_redis_password_reset_key(&token), user.id.to_string(), expiration)
+            .await.map_err(HttpError::Redis)?;
+        if let Err(e) = notifier.send_password_reset(&user, &token).await {
+            println!("Failed to deliver password reset token to {}: {:?}", user.id, e);
+        }
+    }
+
+    Ok(with_status(warp::reply(), StatusCode::ACCEPTED))
+}
+
+pub async fn reset_password(db: DBConn, mut redis: RedisConn, body: PasswordResetDTO) -> Result<impl Reply, Rejection> {
+    // GET and DEL run in one transaction, so a token can be used only once
+    let (user_id, _deleted): (Option<String>, i32) = redis::pipe()
+        .atomic()
+        .get(get_redis_password_reset_key(&body.token))
+        .del(get_redis_password_reset_key(&body.token))
+        .query_async(&mut *redis)
+        .await
+        .map_err(HttpError::Redis)?;
+    let user_id = user_id
+        .and_then(|id| Uuid::parse_str(id.as_str()).ok())
+        .ok_or_else(|| reject::custom(HttpError::Unauthorized(String::from("Invalid or expired reset token"))))?;
+
+    update_password(&user_id, &body.new_password, &db).await?;
+    delete_user_tokens(&user_id, redis).await?;
+
+    Ok(with_status(warp::reply(), StatusCode::NO_CONTENT))
+}
+
 pub async fn get_by_id(db: DBConn, user: AuthenticatedUser) -> Result<impl Reply, Rejection> {
     let resp = db.query(
         "SELECT * FROM users WHERE id=$1",
@@ -132,8 +282,8 @@ pub async fn search_user(
 
 
     if let Some(id) = filters.for_list_id {
-        let has = has_shopping_list(&id, &current_user.id, &db).await;
-        if !has {
+        let role = get_shopping_list_role(&id, &current_user.id, &db).await;
+        if role < Some(ShareRole::MANAGER) {
             let msg = String::from("Not allowed to view this list data");
             return Err(reject::custom(HttpError::Unauthorized(msg)));
         }
@@ -198,15 +348,31 @@ pub async fn search_user(
     Ok(json(&response))
 }
 
-async fn get_token(id: Uuid, redis: RedisConn) -> Result<String, Rejection> {
-    let token = create_token(&id, redis).await.map_err(|_e| {
-        println!("Token creation failed {:?}", _e);
-        reject::custom(HttpError::InternalServerError)
-    })?;
+async fn get_token(id: Uuid, user_agent: Option<String>, keys: &KeyStore, redis: RedisConn) -> Result<SessionTokens, Rejection> {
+    let token = create_token(&id, user_agent, keys, redis).await.map_err(reject::custom)?;
 
     Ok(token)
 }
 
+fn hash_password(password: &str) -> String {
+    let config = Config::default();
+    let salt: [u8; 32] = rand::thread_rng().gen();
+    hash_encoded(password.as_bytes(), &salt, &config).unwrap()
+}
+
+async fn update_password(user_id: &Uuid, password: &str, db: &DBConn) -> Result<(), HttpError> {
+    let hash = hash_password(password);
+    db.execute(
+        "UPDATE users SET password=$2 WHERE id=$1",
+        &[user_id, &hash],
+    ).await.map_err(HttpError::Query)?;
+    Ok(())
+}
+
+fn get_redis_password_reset_key(token: &str) -> String {
+    format!("password_reset:{}", token)
+}
+
 fn reject_password<T>(_e: T) -> Rejection {
     let password_error_message = String::from("invalid password");
     let password_error = HttpError::Unauthorized(password_error_message);
@@ -216,5 +382,17 @@ fn reject_password<T>(_e: T) -> Rejection {
 #[derive(Serialize)]
 struct TokenResponse {
     pub token: String,
+    #[serde(rename = "refreshToken")]
+    pub refresh_token: String,
     pub id: Uuid,
+}
+
+impl TokenResponse {
+    fn new(id: Uuid, tokens: SessionTokens) -> Self {
+        Self {
+            token: tokens.token,
+            refresh_token: tokens.refresh_token,
+            id,
+        }
+    }
 }
\ No newline at end of file